@@ -0,0 +1,119 @@
+use crate::config::{Config, GroupKind, Mode};
+use crate::naming;
+use crate::plan::{FileEntry, FileStatus, Plan};
+use crate::store::Store;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Plans starting to track the file `path` in `group_name`: copying it into
+/// the group directory in `repo`, and in link mode replacing it with a
+/// symlink to the copy. Also returns what else is needed for the group to
+/// pick the file up, if anything.
+pub fn plan(
+    config: &Config,
+    store: &dyn Store,
+    repo: &Path,
+    group_name: &str,
+    resolver: &str,
+    path: &Path,
+) -> Result<(Plan, Option<String>)> {
+    let group = config.get_group(group_name)?;
+    config.check_direction(group_name, true)?;
+    if group.kind != GroupKind::Files {
+        anyhow::bail!("Group '{}' doesn't sync files", group_name);
+    }
+    let resolved = config.resolve_group_path(group_name, resolver)?;
+    let path = std::path::absolute(path)
+        .with_context(|| format!("Failed to resolve: {}", path.display()))?;
+    let relative = path.strip_prefix(&resolved).with_context(|| {
+        format!(
+            "{} is not inside {} ({} of group '{}')",
+            path.display(),
+            resolved.display(),
+            resolver,
+            group_name
+        )
+    })?;
+    if relative.as_os_str().is_empty() || path.is_dir() {
+        anyhow::bail!("Add the files in {} one at a time", path.display());
+    }
+    if path.is_symlink() {
+        anyhow::bail!("{} is a symlink; it may already be tracked", path.display());
+    }
+
+    let in_repo = Path::new(group_name).join(naming::to_repo(group.filename_style, relative));
+    let repo_path = repo.join(&in_repo);
+    if store.exists(&repo_path) {
+        anyhow::bail!(
+            "{} is already in group '{}'; import the group to update it",
+            relative.display(),
+            group_name
+        );
+    }
+
+    // The copy carries its content, so link mode writes it instead of linking.
+    let mut copy = FileEntry::new(in_repo, path.clone(), repo_path.clone(), FileStatus::Create);
+    copy.generated = Some(store.read(&path)?);
+    let mut entries = vec![copy];
+    if config.mode == Mode::Link {
+        entries.push(FileEntry::new(
+            relative.to_path_buf(),
+            repo_path,
+            path.clone(),
+            FileStatus::Overwrite,
+        ));
+    }
+
+    let name = relative.to_string_lossy();
+    let note = group
+        .files
+        .as_ref()
+        .filter(|files| !files.iter().any(|file| *file == name))
+        .map(|_| {
+            format!(
+                "Group '{}' lists its files; add \"{}\" to `files` in doot.yaml",
+                group_name, name
+            )
+        });
+
+    let mut plan = Plan::new();
+    plan.add_group(group_name.to_string(), entries);
+    Ok((plan, note))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+
+    #[test]
+    fn plans_copy_and_link() {
+        let mut config = crate::testing::config("nux", Path::new("/home/me"), &[("bash", &[])]);
+        let store = MemoryStore::new().with_file("/home/me/.bashrc", b"set -o vi\n");
+        let add = |config: &Config, path: &str| {
+            plan(
+                config,
+                &store,
+                Path::new("/repo"),
+                "bash",
+                "nux",
+                Path::new(path),
+            )
+        };
+
+        let (added, note) = add(&config, "/home/me/.bashrc").unwrap();
+        let entries = &added.groups[0].entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].destination, Path::new("/repo/bash/.bashrc"));
+        assert_eq!(entries[0].generated.as_deref(), Some(&b"set -o vi\n"[..]));
+        assert!(note.is_some_and(|note| note.contains("\".bashrc\"")));
+
+        config.mode = Mode::Link;
+        let (added, _) = add(&config, "/home/me/.bashrc").unwrap();
+        let link = &added.groups[0].entries[1];
+        assert_eq!(link.source, Path::new("/repo/bash/.bashrc"));
+        assert_eq!(link.destination, Path::new("/home/me/.bashrc"));
+
+        assert!(add(&config, "/etc/hosts").is_err());
+    }
+}
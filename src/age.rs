@@ -0,0 +1,94 @@
+use crate::config::AgeConfig;
+use crate::process;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Extension of encrypted files.
+pub const EXTENSION: &str = "age";
+
+#[derive(Debug, Clone)]
+struct Keys {
+    identity: Option<PathBuf>,
+    recipients: Vec<String>,
+}
+
+static KEYS: RwLock<Option<Keys>> = RwLock::new(None);
+
+/// Sets the keys of the config's `age` section for the rest of the run.
+pub fn configure(config: &AgeConfig) -> Result<()> {
+    let identity = match &config.identity {
+        Some(identity) => Some(crate::resolver::resolve_path(identity)?),
+        None => None,
+    };
+    *KEYS.write().unwrap() = Some(Keys {
+        identity,
+        recipients: config.recipients.clone(),
+    });
+    Ok(())
+}
+
+fn keys() -> Keys {
+    KEYS.read().unwrap().clone().unwrap_or(Keys {
+        identity: None,
+        recipients: Vec::new(),
+    })
+}
+
+/// The path `relative` is decrypted to, if it is encrypted.
+pub fn target(relative: &Path) -> Option<PathBuf> {
+    crate::naming::strip_extension(relative, EXTENSION)
+}
+
+/// The encrypted file `relative` would be decrypted from.
+pub fn source(relative: &Path) -> PathBuf {
+    crate::naming::add_extension(relative, EXTENSION)
+}
+
+/// Whether `path` is an encrypted file.
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == EXTENSION)
+}
+
+/// Whether recipients are configured, so content can be encrypted.
+pub fn has_recipients() -> bool {
+    !keys().recipients.is_empty()
+}
+
+/// Whether `recipient` looks like a key `age --recipient` accepts: an
+/// `age1...` key or an SSH public key.
+pub fn is_recipient(recipient: &str) -> bool {
+    const BECH32: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    match recipient.strip_prefix("age1") {
+        Some(key) => key.len() >= 58 && key.chars().all(|c| BECH32.contains(c) || c == '1'),
+        None => ["ssh-ed25519 ", "ssh-rsa "]
+            .iter()
+            .any(|prefix| recipient.starts_with(prefix)),
+    }
+}
+
+/// Decrypts `content` with the configured identity. The plaintext is only
+/// ever kept in memory.
+pub fn decrypt(content: &[u8]) -> Result<Vec<u8>> {
+    let Some(identity) = keys().identity else {
+        anyhow::bail!("Set age.identity in doot.yaml to decrypt .age files");
+    };
+    let mut command = process::command("age");
+    command.arg("--decrypt").arg("--identity").arg(identity);
+    process::pipe(command, "age --decrypt", content)
+}
+
+/// Encrypts `content` to the configured recipients.
+pub fn encrypt(content: &[u8]) -> Result<Vec<u8>> {
+    let recipients = keys().recipients;
+    anyhow::ensure!(
+        !recipients.is_empty(),
+        "Set age.recipients in doot.yaml to encrypt .age files"
+    );
+    let mut command = process::command("age");
+    for recipient in &recipients {
+        command.arg("--recipient").arg(recipient);
+    }
+    process::pipe(command, "age", content)
+}
@@ -0,0 +1,107 @@
+//! `doot apply-once`: a non-interactive export for container entrypoints
+//! and provisioning scripts, reporting its result as JSON.
+
+use crate::backup::Backups;
+use crate::cli::Args;
+use crate::config::Config;
+use crate::doctor;
+use crate::executor::Executor;
+use crate::history::Journal;
+use crate::plan::{FileStatus, Plan, PlanBuilder};
+use crate::store::Store;
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    schema_version: u32,
+    plan: &'a str,
+    resolver: &'a str,
+    created: usize,
+    updated: usize,
+    removed: usize,
+    unchanged: usize,
+    /// Groups left out because they can't be synced here, with the reason
+    skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Serialize)]
+struct Skipped {
+    group: String,
+    reason: String,
+}
+
+/// Exports `plan_name` without prompting and prints a JSON report. Groups
+/// that can't be synced on this machine are skipped rather than failing the
+/// run; errors while writing files still do.
+pub fn run(
+    config: &Config,
+    store: &dyn Store,
+    plan_name: &str,
+    resolver: &str,
+    args: &Args,
+) -> Result<()> {
+    let mut plan = Plan::new();
+    let mut skipped = Vec::new();
+    let cwd = std::env::current_dir()?;
+    let builder = PlanBuilder::new(store);
+
+    for group_name in config.get_plan_groups(plan_name)? {
+        let group = config.get_group(&group_name)?;
+        if !group.resolvers.contains_key(resolver) {
+            continue;
+        }
+        let reason = match group.direction.forbids(false) {
+            Some(direction) => Some(direction.to_string()),
+            None => crate::plan::skip_reason(config, &group_name, resolver)?,
+        };
+        if let Some(reason) = reason {
+            skipped.push(Skipped {
+                group: group_name,
+                reason,
+            });
+            continue;
+        }
+        if let Some(problem) = doctor::check_group(config, &group_name, resolver)?.first() {
+            skipped.push(Skipped {
+                group: group_name,
+                reason: problem.clone(),
+            });
+            continue;
+        }
+
+        let resolved_path = config.resolve_group_path(&group_name, resolver)?;
+        let layers = crate::plan::group_layers(config, &group_name, &cwd)?;
+        let entries = builder.build_group_export(group, resolver, &layers, &resolved_path)?;
+        crate::plan::check_parents(group, &entries)?;
+        plan.add_group(group_name, entries);
+    }
+    plan.mark_sensitive(config);
+    plan.attach_hooks(config, false);
+
+    let backups = Backups::from_config(config, args.no_backup)?;
+    let journal = Journal::begin(
+        &format!("Apply plan '{}'", plan_name),
+        args.tag.clone(),
+        &backups,
+    )?;
+    let executor = Executor::new(store, config.mode)
+        .with_journal(&journal)
+        .quiet();
+    let result = executor.execute(&plan);
+    journal.finish()?;
+    result?;
+
+    let report = Report {
+        schema_version: crate::schema::VERSION,
+        plan: plan_name,
+        resolver,
+        created: plan.total_count_by_status(FileStatus::Create),
+        updated: plan.total_count_by_status(FileStatus::Overwrite),
+        removed: plan.total_count_by_status(FileStatus::Remove),
+        unchanged: plan.total_count_by_status(FileStatus::Same),
+        skipped,
+    };
+    println!("{}", serde_json::to_string(&report)?);
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::age;
+use crate::config::Config;
+use crate::history::{create_private_dir, write_private};
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Where runs keep the previous content of the files they overwrite or
+/// replace, so `doot undo` can restore them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backups {
+    /// In `backups/` of each run's history directory
+    History,
+    /// In one directory per run, named after it, under this directory
+    Dir(PathBuf),
+    /// Not kept; `undo` leaves overwritten files alone
+    Off,
+}
+
+impl Backups {
+    /// The backups configured in `backups`, or none with `--no-backup`.
+    pub fn from_config(config: &Config, disabled: bool) -> Result<Self> {
+        if disabled || !config.backups.enabled {
+            return Ok(Self::Off);
+        }
+        Ok(match config.backups_dir()? {
+            Some(dir) => Self::Dir(std::path::absolute(&dir).with_context(|| {
+                format!("Failed to resolve backups directory: {}", dir.display())
+            })?),
+            None => Self::History,
+        })
+    }
+
+    /// Directory the backups of the run with `id` go to, whose history is
+    /// kept in `run_dir`.
+    pub fn root(&self, run_dir: &Path, id: &str) -> Option<PathBuf> {
+        match self {
+            Self::History => Some(run_dir.join("backups")),
+            Self::Dir(dir) => Some(dir.join(id)),
+            Self::Off => None,
+        }
+    }
+}
+
+/// Copies the previous `content` of `destination` into `root`, at the same
+/// path relative to the filesystem root, and returns where it went. With
+/// `encrypt`, the copy is encrypted to the age recipients and gets the
+/// `.age` extension.
+pub fn save(root: &Path, destination: &Path, content: &[u8], encrypt: bool) -> Result<PathBuf> {
+    let mut relative: PathBuf = destination
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    if encrypt {
+        relative = age::source(&relative);
+    }
+    let path = root.join(relative);
+    if let Some(parent) = path.parent() {
+        create_private_dir(parent)?;
+    }
+    if encrypt {
+        write_private(&path, &age::encrypt(content)?)?;
+    } else {
+        write_private(&path, content)?;
+    }
+    Ok(path)
+}
+
+/// Reads a copy kept by a run, decrypting it if it was encrypted.
+pub fn load(path: &Path) -> Result<Vec<u8>> {
+    let content =
+        std::fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))?;
+    if age::is_encrypted(path) {
+        return age::decrypt(&content)
+            .with_context(|| format!("Failed to decrypt: {}", path.display()));
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_destination_paths() {
+        let dir = crate::testing::TempDir::new("backup");
+
+        let saved = save(
+            &dir,
+            Path::new("/home/me/.config/nvim/init.lua"),
+            b"old",
+            false,
+        )
+        .unwrap();
+        assert_eq!(saved, dir.join("home/me/.config/nvim/init.lua"));
+        assert_eq!(load(&saved).unwrap(), b"old");
+
+        let run_dir = Path::new("/state/history/1-2");
+        assert_eq!(
+            Backups::History.root(run_dir, "1-2"),
+            Some(run_dir.join("backups"))
+        );
+        assert_eq!(
+            Backups::Dir(PathBuf::from("/repo/.doot/backups")).root(run_dir, "1-2"),
+            Some(PathBuf::from("/repo/.doot/backups/1-2"))
+        );
+        assert_eq!(Backups::Off.root(run_dir, "1-2"), None);
+    }
+}
@@ -1,4 +1,8 @@
+use crate::config::Config;
+use crate::report::ReportFormat;
+use crate::style::OutputFormat;
 use bpaf::Bpaf;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Bpaf)]
@@ -8,6 +12,63 @@ pub struct Args {
     #[bpaf(short, long)]
     pub yes: bool,
 
+    /// Stage all resolved paths under this root (e.g. a mounted image)
+    #[bpaf(long, argument("DIR"))]
+    pub destdir: Option<PathBuf>,
+
+    /// Keep home, resolved paths and doot's state inside DIR, ignoring the real $HOME
+    #[bpaf(long, argument("DIR"))]
+    pub sandbox: Option<PathBuf>,
+
+    /// Substitute NAME for `{user}` in resolvers; repeat to export for several users
+    #[bpaf(long("user"), argument("NAME"))]
+    pub users: Vec<String>,
+
+    /// Print results of import, export, status and stats as text or json, or diff as a patch
+    #[bpaf(long, argument("FORMAT"), fallback(OutputFormat::Text))]
+    pub format: OutputFormat,
+
+    /// Never use the network; what needs it fails right away
+    #[bpaf(long)]
+    pub offline: bool,
+
+    /// Label recorded with the run; selects runs for `history` and `undo`
+    #[bpaf(long, argument("TAG"))]
+    pub tag: Option<String>,
+
+    /// Don't keep copies of the files a run overwrites; `undo` can't restore them
+    #[bpaf(long("no-backup"))]
+    pub no_backup: bool,
+
+    /// With export, also remove files earlier exports wrote whose source is
+    /// gone from the group
+    #[bpaf(long)]
+    pub prune: bool,
+
+    /// Overwrite files that changed on both sides since the last sync
+    #[bpaf(long)]
+    pub force: bool,
+
+    /// Show what import or export would change without changing anything
+    #[bpaf(short('n'), long("dry-run"))]
+    pub dry_run: bool,
+
+    /// Don't print next-step suggestions after a run
+    #[bpaf(short, long)]
+    pub quiet: bool,
+
+    /// Save the computed plan as JSON to FILE, for `doot plan diff`
+    #[bpaf(long("save-plan"), argument("FILE"))]
+    pub save_plan: Option<PathBuf>,
+
+    /// Show where each file of a plan lands, not just its name
+    #[bpaf(long("show-paths"))]
+    pub show_paths: bool,
+
+    /// Use words instead of symbols, box drawing and colors (for screen readers)
+    #[bpaf(long("ascii"), long("accessible"))]
+    pub ascii: bool,
+
     /// Path to config file
     #[bpaf(short, long, fallback(PathBuf::from("doot.yaml")))]
     pub config: PathBuf,
@@ -32,6 +93,57 @@ pub enum Command {
         target: Target,
     },
 
+    /// Remove files earlier exports wrote whose source is gone from the group
+    #[bpaf(command)]
+    Prune {
+        #[bpaf(external)]
+        target: Target,
+    },
+
+    /// Export a plan without prompting and print a JSON report (for container
+    /// entrypoints and provisioning scripts)
+    #[bpaf(command("apply-once"))]
+    ApplyOnce {
+        /// Name of the plan
+        #[bpaf(long, argument("PLAN"))]
+        plan: String,
+
+        /// Name of the resolver
+        #[bpaf(long, argument("RESOLVER"))]
+        resolver: String,
+
+        /// Dotfiles repo to read, e.g. a read-only mount (default: current directory)
+        #[bpaf(long, argument("DIR"))]
+        repo: Option<PathBuf>,
+    },
+
+    /// Clone a dotfiles repo and, with --plan, export a plan from it
+    #[bpaf(command)]
+    Clone {
+        /// Plan to export once the repo is cloned
+        #[bpaf(long, argument("PLAN"))]
+        plan: Option<String>,
+
+        /// Resolver to export with (default: this machine's)
+        #[bpaf(long, argument("RESOLVER"))]
+        resolver: Option<String>,
+
+        /// URL or path of the repo
+        #[bpaf(positional("URL"))]
+        url: String,
+
+        /// Directory to clone into (default: named after the repo)
+        #[bpaf(positional("DIR"))]
+        dir: Option<PathBuf>,
+    },
+
+    /// Work with plans saved by --save-plan and recorded runs
+    #[bpaf(command)]
+    Plan {
+        #[bpaf(external(plan_command))]
+        action: PlanCommand,
+    },
+
     /// List all plans, groups, and resolvers
     #[bpaf(command)]
     List,
@@ -43,10 +155,185 @@ pub enum Command {
         #[bpaf(short, long)]
         verbose: bool,
 
+        /// Show a matrix of every group against every resolver whose paths
+        /// exist on this machine
+        #[bpaf(long)]
+        all_resolvers: bool,
+
+        /// Only show this group
+        #[bpaf(long, argument("GROUP"))]
+        group: Option<String>,
+
+        /// Only show this plan and its groups
+        #[bpaf(long, argument("PLAN"))]
+        plan: Option<String>,
+
+        /// Name of the resolver
+        #[bpaf(positional("RESOLVER"))]
+        resolver: Option<String>,
+    },
+
+    /// List recorded runs (with the given --tag), newest first
+    #[bpaf(command)]
+    History,
+
+    /// Revert the most recent run (with the given --tag) not yet undone
+    #[bpaf(command)]
+    Undo,
+
+    /// Set up this machine's identity
+    #[bpaf(command)]
+    Machine {
+        #[bpaf(external(machine_command))]
+        action: MachineCommand,
+    },
+
+    /// Finish the most recent run (with the given --tag) stopped with Ctrl-C
+    #[bpaf(command)]
+    Resume,
+
+    /// Serve status requests from a warm cache over a Unix socket
+    #[bpaf(command)]
+    Daemon,
+
+    /// Replace this binary with the latest release
+    #[bpaf(command("self-update"))]
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[bpaf(long)]
+        check: bool,
+    },
+
+    /// Write a report of groups, drift and recent runs
+    #[bpaf(command)]
+    Report {
+        /// Report format (md)
+        #[bpaf(long, argument("FORMAT"), fallback(ReportFormat::Markdown))]
+        format: ReportFormat,
+
+        /// Write the report to FILE instead of stdout
+        #[bpaf(short('o'), long, argument("FILE"))]
+        output: Option<PathBuf>,
+
+        /// Name of the resolver
+        #[bpaf(positional("RESOLVER"))]
+        resolver: String,
+    },
+
+    /// Summarize each group's files, size and how often runs changed them
+    #[bpaf(command)]
+    Stats {
+        /// Name of the resolver
+        #[bpaf(positional("RESOLVER"))]
+        resolver: String,
+    },
+
+    /// Check that this machine has what the groups need before a run
+    #[bpaf(command)]
+    Doctor {
+        /// Name of the resolver
+        #[bpaf(positional("RESOLVER"))]
+        resolver: String,
+    },
+
+    /// Re-encrypt the repo's .age files to the current age.recipients
+    #[bpaf(command)]
+    Reencrypt {
+        /// Only re-encrypt this group's files
+        #[bpaf(long, argument("GROUP"))]
+        group: Option<String>,
+    },
+
+    /// Show how the system differs from the repo: the changes an import would make
+    #[bpaf(command)]
+    Diff {
+        #[bpaf(external)]
+        target: Target,
+    },
+
+    /// Apply patches to the files of a group
+    #[bpaf(command)]
+    Patch {
+        #[bpaf(external(patch_command))]
+        action: PatchCommand,
+    },
+
+    /// Start tracking a file: copy it into a group (and link it back in link mode)
+    #[bpaf(command)]
+    Add {
+        /// Name of the group
+        #[bpaf(positional("GROUP"))]
+        group: String,
+
+        /// Name of the resolver the file's path is relative to
+        #[bpaf(positional("RESOLVER"))]
+        resolver: String,
+
+        /// File to track
+        #[bpaf(positional("PATH"))]
+        path: PathBuf,
+    },
+
+    /// Find unmanaged dotfiles and propose new groups for them
+    #[bpaf(command)]
+    Scan {
+        /// How many directory levels below the resolver root to look at
+        #[bpaf(long, argument("DEPTH"), fallback(2))]
+        depth: usize,
+
         /// Name of the resolver
         #[bpaf(positional("RESOLVER"))]
         resolver: String,
     },
+
+    /// Rename something in doot.yaml everywhere it's used
+    #[bpaf(command)]
+    Mv {
+        #[bpaf(external(mv_command))]
+        action: MvCommand,
+    },
+
+    /// Change a setting in doot.yaml, keeping its comments and layout
+    #[bpaf(command)]
+    Set {
+        #[bpaf(external(set_command))]
+        action: SetCommand,
+    },
+}
+
+impl Command {
+    /// The resolver a command runs with, if it takes one.
+    pub fn resolver_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Command::Import { target }
+            | Command::Export { target }
+            | Command::Prune { target }
+            | Command::Diff { target } => match target {
+                Target::Group { resolver, .. } | Target::Plan { resolver, .. } => Some(resolver),
+            },
+            Command::ApplyOnce { resolver, .. }
+            | Command::Report { resolver, .. }
+            | Command::Stats { resolver }
+            | Command::Doctor { resolver }
+            | Command::Scan { resolver, .. }
+            | Command::Add { resolver, .. } => Some(resolver),
+            Command::Status { resolver, .. } | Command::Clone { resolver, .. } => resolver.as_mut(),
+            Command::Plan { .. }
+            | Command::SelfUpdate { .. }
+            | Command::List
+            | Command::History
+            | Command::Undo
+            | Command::Resume
+            | Command::Machine { .. }
+            | Command::Mv { .. }
+            | Command::Set { .. }
+            | Command::Reencrypt { .. }
+            | Command::Daemon => None,
+            Command::Patch {
+                action: PatchCommand::Apply { resolver, .. },
+            } => resolver.as_mut(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Bpaf)]
@@ -66,6 +353,10 @@ pub enum Target {
     /// Operate on a plan (multiple groups)
     #[bpaf(command)]
     Plan {
+        /// Fail instead of skipping groups that don't resolve on this machine
+        #[bpaf(long("require-all-groups"))]
+        require_all_groups: bool,
+
         /// Name of the plan
         #[bpaf(positional("PLAN"))]
         name: String,
@@ -76,6 +367,285 @@ pub enum Target {
     },
 }
 
+#[derive(Debug, Clone, Bpaf)]
+pub enum PlanCommand {
+    /// Show groups and files whose status differs between two saved plans
+    #[bpaf(command)]
+    Diff {
+        /// Compare against the plan of the most recent run (with the given --tag)
+        #[bpaf(long("against-last-run"))]
+        against_last_run: bool,
+
+        /// Plans saved with --save-plan: OLD NEW, or just NEW
+        #[bpaf(positional("PLAN"))]
+        plans: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Bpaf)]
+pub enum MachineCommand {
+    /// Name this machine and set its default resolver and profile
+    #[bpaf(command)]
+    Init {
+        /// Name shown for this machine, e.g. laptop
+        #[bpaf(long, argument("NAME"))]
+        name: String,
+
+        /// Resolver used by `status` when none is given
+        #[bpaf(long, argument("RESOLVER"))]
+        resolver: Option<String>,
+
+        /// Role of the machine, e.g. work
+        #[bpaf(long, argument("PROFILE"))]
+        profile: Option<String>,
+
+        /// Also record the machine in the repo's .doot/machines
+        #[bpaf(long)]
+        repo: bool,
+    },
+}
+
+#[derive(Debug, Clone, Bpaf)]
+pub enum MvCommand {
+    /// Rename a resolver in every group, with its requires_mount and variables
+    #[bpaf(command)]
+    Resolver {
+        #[bpaf(positional("OLD"))]
+        old: String,
+
+        #[bpaf(positional("NEW"))]
+        new: String,
+    },
+}
+
+#[derive(Debug, Clone, Bpaf)]
+pub enum SetCommand {
+    /// Set the path a group resolves to for a resolver, adding it if needed
+    #[bpaf(command)]
+    Resolver {
+        /// Name of the group
+        #[bpaf(positional("GROUP"))]
+        group: String,
+
+        /// Name of the resolver
+        #[bpaf(positional("NAME"))]
+        name: String,
+
+        /// Path the group resolves to, e.g. ~/.config/bash
+        #[bpaf(positional("PATH"))]
+        path: String,
+    },
+}
+
+#[derive(Debug, Clone, Bpaf)]
+pub enum PatchCommand {
+    /// Apply a unified diff to the group's repo files, then offer to export them
+    #[bpaf(command)]
+    Apply {
+        /// Group whose files the patch changes
+        #[bpaf(long, argument("GROUP"))]
+        group: String,
+
+        /// Patch with paths relative to the repo, e.g. from `doot diff --format patch`
+        #[bpaf(positional("PATCH"))]
+        patch: PathBuf,
+
+        /// Resolver to export the changed files to; defaults to this machine's
+        #[bpaf(positional("RESOLVER"))]
+        resolver: Option<String>,
+    },
+}
+
+/// Built-in commands; an alias with the same name is never expanded.
+const COMMANDS: &[&str] = &[
+    "import",
+    "export",
+    "prune",
+    "mv",
+    "set",
+    "apply-once",
+    "clone",
+    "plan",
+    "list",
+    "status",
+    "history",
+    "undo",
+    "resume",
+    "machine",
+    "daemon",
+    "self-update",
+    "report",
+    "stats",
+    "doctor",
+    "scan",
+    "add",
+    "diff",
+    "patch",
+];
+
+/// Top-level options that take a value, so the value isn't taken for a command.
+const VALUE_OPTIONS: &[&str] = &[
+    "-c",
+    "--config",
+    "--destdir",
+    "--sandbox",
+    "--user",
+    "--tag",
+    "--save-plan",
+    "--format",
+];
+
 pub fn parse() -> Args {
-    args().run()
+    let mut argv: Vec<String> = std::env::args().skip(1).collect();
+    // A config that fails to load is reported once the command runs.
+    if let Ok(config) = Config::load(&config_path(&argv)) {
+        if let Some(command) = config.default_command() {
+            argv = with_default_command(argv, &command);
+        }
+        argv = expand_alias(argv, &config.aliases);
+    }
+
+    match args().run_inner(bpaf::Args::from(argv.as_slice()).set_name("doot")) {
+        Ok(args) => args,
+        Err(err) => {
+            err.print_message(100);
+            std::process::exit(err.exit_code())
+        }
+    }
+}
+
+/// The `--config` path given in `argv`, before the arguments are parsed.
+fn config_path(argv: &[String]) -> PathBuf {
+    let mut path = PathBuf::from("doot.yaml");
+    let mut iter = argv.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-c" || arg == "--config" {
+            if let Some(value) = iter.next() {
+                path = PathBuf::from(value);
+            }
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            path = PathBuf::from(value);
+        } else if let Some(value) = arg.strip_prefix("-c").filter(|v| !v.is_empty()) {
+            path = PathBuf::from(value);
+        }
+    }
+    path
+}
+
+/// Index of the command word in `argv`, skipping top-level options.
+fn command_position(argv: &[String]) -> Option<usize> {
+    let mut i = 0;
+    while i < argv.len() {
+        let arg = &argv[i];
+        if VALUE_OPTIONS.contains(&arg.as_str()) {
+            i += 2;
+            continue;
+        }
+        if arg == "--" || !arg.starts_with('-') {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Appends `command` to `argv` if it names no command and asks for neither
+/// help nor the version.
+pub fn with_default_command(mut argv: Vec<String>, command: &str) -> Vec<String> {
+    let asks_for_info = argv
+        .iter()
+        .any(|arg| matches!(arg.as_str(), "-h" | "--help" | "-V" | "--version"));
+    if command_position(&argv).is_none() && !asks_for_info {
+        argv.extend(command.split_whitespace().map(String::from));
+    }
+    argv
+}
+
+/// Replaces the command word in `argv` with the command line of its alias, if
+/// it names one. Arguments after the alias are appended to its expansion.
+pub fn expand_alias(argv: Vec<String>, aliases: &BTreeMap<String, String>) -> Vec<String> {
+    let Some(position) = command_position(&argv) else {
+        return argv;
+    };
+    let name = &argv[position];
+    if COMMANDS.contains(&name.as_str()) {
+        return argv;
+    }
+    let Some(expansion) = aliases.get(name) else {
+        return argv;
+    };
+
+    let mut expanded = argv[..position].to_vec();
+    expanded.extend(expansion.split_whitespace().map(String::from));
+    expanded.extend(argv[position + 1..].iter().cloned());
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn aliases() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("push".to_string(), "export plan all nux --yes".to_string()),
+            ("status".to_string(), "status -v nux".to_string()),
+        ])
+    }
+
+    #[test]
+    fn expands_alias_and_keeps_surrounding_args() {
+        let expanded = expand_alias(
+            argv(&["-q", "-c", "x.yaml", "push", "--tag", "t"]),
+            &aliases(),
+        );
+        assert_eq!(
+            expanded,
+            argv(&["-q", "-c", "x.yaml", "export", "plan", "all", "nux", "--yes", "--tag", "t"])
+        );
+    }
+
+    #[test]
+    fn builtin_commands_win_over_aliases() {
+        let args = argv(&["status", "nux"]);
+        assert_eq!(expand_alias(args.clone(), &aliases()), args);
+    }
+
+    #[test]
+    fn option_values_are_not_commands() {
+        let args = argv(&["--tag", "push", "list"]);
+        assert_eq!(expand_alias(args.clone(), &aliases()), args);
+    }
+
+    #[test]
+    fn default_command_only_without_command() {
+        assert_eq!(
+            with_default_command(argv(&["-q"]), "status nux"),
+            argv(&["-q", "status", "nux"])
+        );
+        assert_eq!(
+            with_default_command(argv(&["list"]), "status nux"),
+            argv(&["list"])
+        );
+        assert_eq!(
+            with_default_command(argv(&["--help"]), "status nux"),
+            argv(&["--help"])
+        );
+    }
+
+    #[test]
+    fn finds_config_path() {
+        assert_eq!(config_path(&argv(&["push"])), PathBuf::from("doot.yaml"));
+        assert_eq!(
+            config_path(&argv(&["-c", "a.yaml", "push"])),
+            PathBuf::from("a.yaml")
+        );
+        assert_eq!(
+            config_path(&argv(&["--config=b.yaml"])),
+            PathBuf::from("b.yaml")
+        );
+    }
 }
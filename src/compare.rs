@@ -0,0 +1,69 @@
+use crate::config::CompareMode;
+
+/// Returns whether two file contents are equivalent under `mode`. Contents
+/// that fail to parse are only equivalent if they are byte-identical.
+pub fn equivalent(mode: CompareMode, a: &[u8], b: &[u8]) -> bool {
+    if a == b {
+        return true;
+    }
+
+    match mode {
+        CompareMode::SemanticJson => {
+            match (
+                serde_json::from_slice::<serde_json::Value>(a),
+                serde_json::from_slice::<serde_json::Value>(b),
+            ) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => false,
+            }
+        }
+        CompareMode::SemanticYaml => {
+            match (
+                serde_yaml::from_slice::<serde_yaml::Value>(a),
+                serde_yaml::from_slice::<serde_yaml::Value>(b),
+            ) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => false,
+            }
+        }
+        CompareMode::IgnoreTrailingWs => trimmed_lines(a).eq(trimmed_lines(b)),
+    }
+}
+
+/// Lines without trailing whitespace, ignoring trailing blank lines.
+fn trimmed_lines(content: &[u8]) -> impl Iterator<Item = &str> {
+    let text = std::str::from_utf8(content).unwrap_or_default();
+    text.trim_end().lines().map(str::trim_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semantic_json_ignores_key_order_and_formatting() {
+        let a = br#"{"a": 1, "b": [true, null]}"#;
+        let b = b"{\n  \"b\": [true, null],\n  \"a\": 1\n}\n";
+        assert!(equivalent(CompareMode::SemanticJson, a, b));
+        assert!(!equivalent(CompareMode::SemanticJson, a, br#"{"a": 2}"#));
+    }
+
+    #[test]
+    fn semantic_yaml_ignores_key_order() {
+        let a = b"a: 1\nb: two\n";
+        let b = b"b: two\na: 1\n";
+        assert!(equivalent(CompareMode::SemanticYaml, a, b));
+    }
+
+    #[test]
+    fn trailing_whitespace_is_ignored() {
+        let a = b"set -g mouse on  \nbind r reload\n\n\n";
+        let b = b"set -g mouse on\nbind r reload";
+        assert!(equivalent(CompareMode::IgnoreTrailingWs, a, b));
+        assert!(!equivalent(
+            CompareMode::IgnoreTrailingWs,
+            a,
+            b"  set -g mouse on"
+        ));
+    }
+}
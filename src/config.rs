@@ -1,7 +1,8 @@
+use crate::error::DootError;
 use anyhow::{Context, Result};
 use serde::Deserialize;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -11,50 +12,794 @@ pub enum Mode {
     Link,
 }
 
+/// Content hash used to compare files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupKind {
+    /// A directory of files mirrored to the resolved path
+    #[default]
+    Files,
+    /// A single `crontab` file installed with `crontab -`; resolvers name the user
+    Crontab,
+    /// A generated env file holding `variables`; resolvers name the file
+    Env,
+}
+
+/// Which ways a group may be synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    #[default]
+    Both,
+    /// Never imported, e.g. files an application rewrites in its own style
+    ExportOnly,
+    /// Never exported, e.g. captures of state kept only for reference
+    ImportOnly,
+}
+
+impl Direction {
+    /// Why an import (or export) of the group isn't allowed, if it isn't.
+    pub fn forbids(self, import: bool) -> Option<&'static str> {
+        match (self, import) {
+            (Direction::ExportOnly, true) => Some("export-only"),
+            (Direction::ImportOnly, false) => Some("import-only"),
+            _ => None,
+        }
+    }
+}
+
+/// Which missing directories an export may create for a group's files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CreateParents {
+    /// Any, including the resolved path itself
+    #[default]
+    Always,
+    /// Those below the resolved path, which must exist
+    MissingOnly,
+    /// None; the directory of every file must exist
+    Never,
+}
+
+/// How files are compared to decide whether they need to be synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareMode {
+    /// Equal if both parse to the same JSON value (ignores key order)
+    SemanticJson,
+    /// Equal if both parse to the same YAML value (ignores key order)
+    SemanticYaml,
+    /// Equal if lines match after trimming trailing whitespace
+    IgnoreTrailingWs,
+}
+
+/// How a file is merged into an existing destination on export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    Ini,
+    Toml,
+}
+
+/// How file names in a group's repo directory map to the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilenameStyle {
+    /// Repo and destination names are the same
+    #[default]
+    Plain,
+    /// `dot_bashrc` in the repo is `.bashrc` at the destination
+    DotPrefix,
+}
+
+/// A destination file kept in the repo as fragments, split before every
+/// line starting with `marker`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Split {
+    pub marker: String,
+    /// Directory in the group holding the fragments
+    pub into: String,
+}
+
+/// Where files matching a pattern go in a run relative to other files of the
+/// group, e.g. `env.sh: { before: rc.sh }`. Both sides are patterns.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Order {
+    #[serde(default)]
+    pub before: Option<String>,
+    #[serde(default)]
+    pub after: Option<String>,
+}
+
+/// Shell commands a group runs around the changes of a run, e.g. to reload
+/// an application after its files are exported.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    pub pre_import: Option<String>,
+    pub post_import: Option<String>,
+    pub pre_export: Option<String>,
+    pub post_export: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Group {
+    #[serde(default)]
+    pub kind: GroupKind,
+    /// Environment variables written by `env` groups, keyed by resolver
+    #[serde(default)]
+    pub variables: HashMap<String, BTreeMap<String, String>>,
+    /// Comparison modes for files matching a pattern
+    #[serde(default)]
+    pub compare: BTreeMap<String, CompareMode>,
+    /// Keys (or INI sections) synced for files matching a pattern; everything
+    /// else in those files is left as the application wrote it
+    #[serde(default)]
+    pub managed_keys: BTreeMap<String, Vec<String>>,
+    /// Files matching a pattern are merged into the destination on export
+    /// (repo wins per key) instead of replacing it
+    #[serde(default)]
+    pub merge: BTreeMap<String, MergeStrategy>,
+    /// Files matching these patterns are not owned by doot; only a block
+    /// between `# >>> doot:<group> >>>` markers inside them is synced
+    #[serde(default)]
+    pub blocks: Vec<String>,
+    /// Files matching these patterns are created if missing but never
+    /// overwritten (machine-local seed files)
+    #[serde(default)]
+    pub create_only: Vec<String>,
+    /// When set, only these files (relative to the group root) are synced
+    /// instead of walking the whole directory
+    #[serde(default)]
+    pub files: Option<Vec<String>>,
+    /// Destination files (relative to the resolved path) assembled on export
+    /// from the repo fragments matching a pattern, in path order
+    #[serde(default)]
+    pub concat: BTreeMap<String, String>,
+    /// Destination files (relative to the resolved path) generated on export
+    /// to source the exported files matching a pattern, in path order
+    #[serde(default)]
+    pub loaders: BTreeMap<String, String>,
+    /// Destination files split into fragments on import and joined back on
+    /// export
+    #[serde(default)]
+    pub split: BTreeMap<String, Split>,
+    /// Commands (e.g. `shfmt`) that imported files matching a pattern are
+    /// piped through before they are compared and written to the repo
+    #[serde(default)]
+    pub format: BTreeMap<String, String>,
+    /// Notes shown in the plan next to files matching a pattern, e.g. to
+    /// flag files that need a careful look
+    #[serde(default)]
+    pub notes: BTreeMap<String, String>,
+    /// Files written before or after others of the group, for files that are
+    /// read while the rest are written (e.g. `env.sh` sourced by `rc.sh`)
+    #[serde(default)]
+    pub order: BTreeMap<String, Order>,
+    /// Modes (octal, e.g. `"700"`) for directories an export creates,
+    /// relative to the resolved path; `.` is the path itself
+    #[serde(default)]
+    pub dir_modes: BTreeMap<String, String>,
+    /// Group whose files this group inherits; files of this group win
+    #[serde(default)]
+    pub extends: Option<String>,
+    /// Leave exported files read-only so edits go through the repo
+    #[serde(default)]
+    pub readonly: bool,
+    /// How repo file names map to destination names
+    #[serde(default)]
+    pub filename_style: FilenameStyle,
+    /// Set the Windows hidden attribute on exported files named with a dot
+    #[serde(default)]
+    pub hidden_attribute: bool,
+    /// How deep directory walks go; 1 only takes the files at the top
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Descend into symlinked directories and take symlinked files while
+    /// walking, instead of leaving them out
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Keep hashes of the group's files out of doot's state and the repo's
+    /// machine records
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Change the group's files through sudo, for system paths such as
+    /// `/etc` that doot can't write as the current user
+    #[serde(default)]
+    pub sudo: bool,
+    /// Owner of exported files, as `user` or `user:group`
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Which missing directories exports may create, so an unmounted drive
+    /// fails the run instead of being recreated in the wrong place
+    #[serde(default)]
+    pub create_parents: CreateParents,
+    /// Mount points resolvers need, e.g. `usb: /run/media/me/usb`; plans skip
+    /// the group with that resolver while nothing is mounted there
+    #[serde(default)]
+    pub requires_mount: HashMap<String, String>,
+    #[serde(flatten)]
+    pub resolvers: HashMap<String, String>,
+    /// Resolvers written as `name?`: plans skip the group where they don't
+    /// resolve to an existing path
+    #[serde(skip)]
+    pub optional_resolvers: BTreeSet<String>,
+}
+
+/// Names of the options a group takes. Resolvers share the group's keys, so
+/// none may be named like one, or look like a misspelling of one.
+const GROUP_OPTIONS: &[&str] = &[
+    "kind",
+    "variables",
+    "compare",
+    "managed_keys",
+    "merge",
+    "blocks",
+    "create_only",
+    "files",
+    "concat",
+    "loaders",
+    "split",
+    "format",
+    "notes",
+    "order",
+    "dir_modes",
+    "extends",
+    "readonly",
+    "filename_style",
+    "hidden_attribute",
+    "max_depth",
+    "follow_symlinks",
+    "sensitive",
+    "sudo",
+    "owner",
+    "hooks",
+    "direction",
+    "create_parents",
+    "requires_mount",
+];
+
+impl Group {
+    /// Fails for resolvers named like a group option, such as a `mode` copied
+    /// from the top level or a misspelled `kidn`, which would otherwise be
+    /// taken as paths.
+    fn check_resolver_names(&self, group: &str) -> Result<()> {
+        for name in self.resolvers.keys() {
+            let option = GROUP_OPTIONS
+                .iter()
+                .chain(&["mode"])
+                .find(|option| *option == name)
+                .or_else(|| {
+                    GROUP_OPTIONS
+                        .iter()
+                        .find(|option| name.len() >= 4 && is_typo(name, option))
+                });
+            if let Some(option) = option {
+                let hint = if option == name {
+                    String::new()
+                } else {
+                    format!(" (did you mean '{}'?)", option)
+                };
+                return Err(DootError::Config(format!(
+                    "Group '{}' has an unknown option '{}'{}; resolvers can't be named like group options",
+                    group, name, hint
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves resolvers written as `name?` to `name`, remembering them as optional.
+    fn split_optional_resolvers(&mut self) {
+        let optional: Vec<String> = self
+            .resolvers
+            .keys()
+            .filter(|name| name.ends_with('?'))
+            .cloned()
+            .collect();
+        for key in optional {
+            let path = self.resolvers.remove(&key).unwrap_or_default();
+            let name = key.trim_end_matches('?').to_string();
+            self.resolvers.insert(name.clone(), path);
+            self.optional_resolvers.insert(name);
+        }
+    }
+}
+
+/// Whether `a` is `b` with one character added, removed, replaced, or
+/// swapped with its neighbour.
+fn is_typo(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    match (a.len(), b.len()) {
+        (0, 0) => false,
+        (x, y) if x == y => {
+            a[1..] == b[1..] || (x >= 2 && a[0] == b[1] && a[1] == b[0] && a[2..] == b[2..])
+        }
+        (x, y) if x == y + 1 => a[1..] == *b,
+        (x, y) if x + 1 == y => *a == b[1..],
+        _ => false,
+    }
+}
+
+/// What expanding a resolver with an unset variable does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UndefinedVariables {
+    /// Fail naming the variable, group and resolver
+    #[default]
+    Error,
+    /// Expand it to nothing and print a warning
+    Empty,
+}
+
+/// Limits above which the interactive diff shows a summary instead.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiffLimits {
+    /// Largest file, in bytes, that is diffed
+    pub max_size: u64,
+    /// Longest line, in bytes, that is diffed
+    pub max_line_length: usize,
+}
+
+impl DiffLimits {
+    /// Returns why a diff between `old` and `new` should not be shown, if it
+    /// shouldn't.
+    pub fn exceeded(&self, old: &str, new: &str) -> Option<String> {
+        let size = old.len().max(new.len()) as u64;
+        if size > self.max_size {
+            return Some(format!("larger than {} bytes", self.max_size));
+        }
+        let longest = old.lines().chain(new.lines()).map(str::len).max();
+        if longest.is_some_and(|len| len > self.max_line_length) {
+            return Some(format!("lines longer than {} bytes", self.max_line_length));
+        }
+        None
+    }
+}
+
+impl Default for DiffLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 1_000_000,
+            max_line_length: 10_000,
+        }
+    }
+}
+
+/// How long `docker exec` (or `podman exec`) calls on container targets may
+/// take before they are retried, with twice the wait after each attempt.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ContainerLimits {
+    /// Seconds one call may take
+    pub timeout: u64,
+    /// Attempts after the first when a call times out
+    pub retries: u32,
+}
+
+impl Default for ContainerLimits {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            retries: 2,
+        }
+    }
+}
+
+/// Copies of the files runs overwrite or replace, for `doot undo`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    /// Directory for them, e.g. `.doot/backups`, instead of doot's history
+    pub dir: Option<String>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+/// What doot does with the repo's git history.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitConfig {
+    /// Commit the files an import changed, with a message listing them
+    #[serde(default)]
+    pub auto_commit: bool,
+}
+
+/// Keys for `.age` files, which are encrypted and decrypted with `age`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgeConfig {
+    /// Identity file decrypting them on export, e.g. `~/.config/age/key.txt`
+    #[serde(default)]
+    pub identity: Option<String>,
+    /// Recipients they are encrypted to on import
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+/// Environment given to external commands doot runs.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProcessEnv {
+    /// Pass doot's entire environment through instead of a minimal one
+    #[serde(default)]
+    pub inherit: bool,
+    /// Extra variables to pass through from doot's environment
+    #[serde(default)]
+    pub pass: Vec<String>,
+    /// Variables to set for every command
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub version: String,
+    /// Versions of doot this config is written for, e.g. `>=0.4`
+    #[serde(default)]
+    pub requires: Option<String>,
     #[serde(default)]
     pub mode: Mode,
     #[serde(default)]
+    pub hash: HashAlgorithm,
+    #[serde(default)]
+    pub process_env: ProcessEnv,
+    /// Variables defined while expanding paths and for external commands,
+    /// without touching the shell's environment
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Variables of `.tmpl` files, besides the built-in ones
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    #[serde(default)]
+    pub age: AgeConfig,
+    /// How resolvers referring to unset variables are handled
+    #[serde(default)]
+    pub undefined_variables: UndefinedVariables,
+    #[serde(default)]
+    pub diff: DiffLimits,
+    #[serde(default)]
+    pub containers: ContainerLimits,
+    #[serde(default)]
+    pub backups: BackupConfig,
+    /// Syntax names used to highlight diffs of files matching a file name or
+    /// pattern
+    #[serde(default)]
+    pub highlight: BTreeMap<String, String>,
+    /// Directory with extra `.sublime-syntax` definitions for diffs
+    #[serde(default)]
+    pub syntaxes: Option<String>,
+    #[serde(default)]
     pub plans: HashMap<String, Option<Vec<String>>>,
     #[serde(default)]
-    pub groups: HashMap<String, HashMap<String, String>>,
+    pub groups: HashMap<String, Group>,
+    /// Directory (or second repo) whose group directories shadow files of the
+    /// groups in this repo
+    #[serde(default)]
+    pub overrides: Option<String>,
+    /// Short names for full command lines, e.g. `push: export plan all nux --yes`
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Command line run when doot is invoked without a command
+    #[serde(default)]
+    pub default_command: Option<String>,
+    /// Record what each machine synced in `.doot/machines`, for `status`
+    #[serde(default)]
+    pub track_machines: bool,
+    /// Sync doot's own settings file as the built-in `doot` group
+    #[serde(default)]
+    pub manage_doot: bool,
+    #[serde(default)]
+    pub git: GitConfig,
+    /// Old resolver names and the canonical name they stand for, e.g.
+    /// `linux: nux`
+    #[serde(default)]
+    pub resolver_aliases: BTreeMap<String, String>,
+    /// Root prepended to every resolved path, set by `--destdir`
+    #[serde(skip)]
+    pub destdir: Option<PathBuf>,
+    /// User substituted for `{user}` in resolvers, set by `--user`
+    #[serde(skip)]
+    pub user: Option<String>,
 }
 
 impl Config {
     pub fn load(path: &Path) -> Result<Self> {
         let content = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-        let config: Config =
-            serde_yaml::from_str(&content).with_context(|| "Failed to parse doot.yaml")?;
+            .map_err(|source| DootError::io("read config file", path, source))?;
+        let mut config = Self::from_yaml(&content, path)?;
 
-        if config.version != "v1" {
-            anyhow::bail!("Unsupported config version: {}", config.version);
+        // Machine-local aliases win over the repo's.
+        config.aliases.extend(crate::settings::load()?.aliases);
+        Ok(config)
+    }
+
+    /// With `manage_doot`, adds the built-in group syncing doot's settings,
+    /// for every resolver the other groups use.
+    fn add_doot_group(&mut self) -> Result<()> {
+        if !self.manage_doot {
+            return Ok(());
+        }
+        if self.groups.contains_key(crate::settings::GROUP) {
+            return Err(DootError::Config(format!(
+                "Group '{}' is built in with manage_doot; rename yours",
+                crate::settings::GROUP
+            ))
+            .into());
         }
+        let resolvers: BTreeSet<String> = self
+            .groups
+            .values()
+            .flat_map(|group| group.resolvers.keys().cloned())
+            .collect();
+        self.groups.insert(
+            crate::settings::GROUP.to_string(),
+            crate::settings::group(resolvers),
+        );
+        Ok(())
+    }
 
-        Ok(config)
+    /// Fails with an upgrade hint if this binary doesn't satisfy `requires`.
+    fn check_requires(&self) -> Result<()> {
+        let Some(requirement) = &self.requires else {
+            return Ok(());
+        };
+        let current = crate::version::Version::current();
+        if !crate::version::satisfies(current, requirement)? {
+            return Err(DootError::Config(format!(
+                "doot.yaml requires doot {}, but this is doot {}. \
+                 Run `doot self-update` or install a matching version.",
+                requirement, current
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Returns the canonical name of `resolver`, following `resolver_aliases`.
+    pub fn canonical_resolver<'a>(&'a self, resolver: &'a str) -> &'a str {
+        self.resolver_aliases
+            .get(resolver)
+            .map_or(resolver, |canonical| canonical.as_str())
+    }
+
+    /// Renames resolvers that groups define under an alias to their canonical
+    /// name.
+    fn normalize_resolvers(&mut self) -> Result<()> {
+        for (alias, canonical) in &self.resolver_aliases {
+            if self.resolver_aliases.contains_key(canonical) {
+                return Err(DootError::Config(format!(
+                    "Resolver alias '{}' points to '{}', which is an alias itself",
+                    alias, canonical
+                ))
+                .into());
+            }
+            for (name, group) in &mut self.groups {
+                let Some(path) = group.resolvers.remove(alias) else {
+                    continue;
+                };
+                if group.resolvers.contains_key(canonical) {
+                    return Err(DootError::Config(format!(
+                        "Group '{}' defines both '{}' and its alias '{}'",
+                        name, canonical, alias
+                    ))
+                    .into());
+                }
+                group.resolvers.insert(canonical.clone(), path);
+                if group.optional_resolvers.remove(alias) {
+                    group.optional_resolvers.insert(canonical.clone());
+                }
+                if let Some(mount) = group.requires_mount.remove(alias) {
+                    group.requires_mount.insert(canonical.clone(), mount);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Command line run by a bare `doot`: `default_command` if set, otherwise
+    /// `status` for the only resolver the groups define, or for all of them.
+    pub fn default_command(&self) -> Option<String> {
+        if let Some(command) = &self.default_command {
+            return Some(command.clone());
+        }
+
+        let resolvers: BTreeSet<&String> = self
+            .groups
+            .values()
+            .flat_map(|group| group.resolvers.keys())
+            .collect();
+        match resolvers.into_iter().collect::<Vec<_>>().as_slice() {
+            [] => None,
+            [resolver] => Some(format!("status {}", resolver)),
+            _ => Some("status --all-resolvers".to_string()),
+        }
+    }
+
+    /// Defines `env` for path expansion and, together with `process_env`, for
+    /// external commands, and sets the limits of container commands.
+    pub fn configure_environment(&self) -> Result<()> {
+        crate::store::container::configure(&self.containers);
+        crate::resolver::configure(&self.env)?;
+        let mut process_env = self.process_env.clone();
+        for (name, value) in crate::resolver::variables() {
+            process_env.set.entry(name).or_insert(value);
+        }
+        crate::process::configure(&process_env);
+        crate::template::configure(&self.vars, &self.user_name());
+        crate::age::configure(&self.age)?;
+        Ok(())
+    }
+
+    /// Directories of `sudo` groups, for every resolver, whose files are
+    /// changed through sudo.
+    pub fn sudo_paths(&self) -> Vec<PathBuf> {
+        // A sandbox contains every path, so nothing needs sudo there.
+        match crate::sandbox::root() {
+            Some(_) => Vec::new(),
+            None => self.resolved_paths(|group| group.sudo),
+        }
+    }
+
+    /// Directories of `sensitive` groups, on the machine for every resolver
+    /// and in the repo, whose hashes must not be cached.
+    pub fn sensitive_paths(&self) -> Vec<PathBuf> {
+        let mut sensitive = self.resolved_paths(|group| group.sensitive);
+        if let Ok(repo) = std::env::current_dir() {
+            sensitive.extend(
+                self.groups
+                    .iter()
+                    .filter(|(_, group)| group.sensitive)
+                    .map(|(name, _)| repo.join(name)),
+            );
+        }
+        sensitive
     }
 
-    pub fn get_group(&self, name: &str) -> Result<&HashMap<String, String>> {
+    /// Resolved paths, for every resolver, of the groups matching `filter`.
+    fn resolved_paths(&self, filter: impl Fn(&Group) -> bool) -> Vec<PathBuf> {
+        self.groups
+            .iter()
+            .filter(|(_, group)| filter(group))
+            .flat_map(|(name, group)| {
+                group
+                    .resolvers
+                    .keys()
+                    .filter_map(|resolver| self.resolve_group_path(name, resolver).ok())
+            })
+            .collect()
+    }
+
+    pub fn get_group(&self, name: &str) -> Result<&Group> {
         self.groups
             .get(name)
-            .with_context(|| format!("Group '{}' not found", name))
+            .ok_or_else(|| DootError::Config(format!("Group '{}' not found", name)).into())
     }
 
     pub fn get_resolver(&self, group: &str, resolver: &str) -> Result<&str> {
-        let group_resolvers = self.get_group(group)?;
-        group_resolvers
+        self.get_group(group)?
+            .resolvers
             .get(resolver)
             .map(|s| s.as_str())
-            .with_context(|| format!("Resolver '{}' not found in group '{}'", resolver, group))
+            .ok_or_else(|| {
+                DootError::Resolver(format!(
+                    "Resolver '{}' not found in group '{}'",
+                    resolver, group
+                ))
+                .into()
+            })
+    }
+
+    /// The user files are synced for: `--user`, or the current one.
+    pub fn user_name(&self) -> String {
+        self.user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_default()
+    }
+
+    /// Expands the path of `resolver` in `group`, staged under `destdir` if set.
+    pub fn resolve_group_path(&self, group: &str, resolver: &str) -> Result<PathBuf> {
+        let raw = self
+            .get_resolver(group, resolver)?
+            .replace("{user}", &self.user_name());
+        let lenient = self.undefined_variables == UndefinedVariables::Empty;
+        let path = crate::resolver::expand(&raw, lenient)
+            .with_context(|| format!("Resolver '{}' of group '{}' is invalid", resolver, group))?;
+
+        // Crontab resolvers name users rather than paths, and container
+        // paths live outside the host file system.
+        if self.get_group(group)?.kind == GroupKind::Crontab
+            || crate::store::container::is_container_path(&path)
+        {
+            if crate::sandbox::root().is_some() {
+                return Err(DootError::Conflict(format!(
+                    "Group '{}' targets a crontab or container, which --sandbox can't contain",
+                    group
+                ))
+                .into());
+            }
+            return Ok(path);
+        }
+
+        if let Some(root) = crate::sandbox::root() {
+            return Ok(crate::sandbox::confine(root, &path));
+        }
+        Ok(match &self.destdir {
+            Some(destdir) => crate::resolver::stage(destdir, &path),
+            None => path,
+        })
+    }
+
+    /// Expands the `syntaxes` directory, if configured.
+    pub fn syntaxes_dir(&self) -> Result<Option<PathBuf>> {
+        self.syntaxes
+            .as_deref()
+            .map(crate::resolver::resolve_path)
+            .transpose()
+    }
+
+    /// Fails if `group_name` may not be imported (or exported).
+    pub fn check_direction(&self, group_name: &str, import: bool) -> Result<()> {
+        let group = self.get_group(group_name)?;
+        match group.direction.forbids(import) {
+            Some(direction) => Err(DootError::Config(format!(
+                "Group '{}' is {}, so it can't be {}",
+                group_name,
+                direction,
+                if import { "imported" } else { "exported" }
+            ))
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Expands the backups directory, if configured.
+    pub fn backups_dir(&self) -> Result<Option<PathBuf>> {
+        self.backups
+            .dir
+            .as_deref()
+            .map(crate::resolver::resolve_path)
+            .transpose()
+    }
+
+    /// Expands the `overrides` directory, if configured.
+    pub fn overrides_dir(&self) -> Result<Option<PathBuf>> {
+        self.overrides
+            .as_deref()
+            .map(crate::resolver::resolve_path)
+            .transpose()
     }
 
     pub fn get_plan_groups(&self, plan: &str) -> Result<Vec<String>> {
         let plan_groups = self
             .plans
             .get(plan)
-            .with_context(|| format!("Plan '{}' not found", plan))?;
+            .ok_or_else(|| DootError::Config(format!("Plan '{}' not found", plan)))?;
 
         match plan_groups {
             None => Ok(self.groups.keys().cloned().collect()),
@@ -62,23 +807,56 @@ impl Config {
         }
     }
 
-    #[cfg(test)]
-    pub fn parse(content: &str) -> Result<Self> {
-        let config: Config =
-            serde_yaml::from_str(content).with_context(|| "Failed to parse config")?;
+    /// Parses the config in `content`, read from `path`, without the
+    /// machine-local settings `load` adds.
+    pub fn from_yaml(content: &str, path: &Path) -> Result<Self> {
+        let mut config: Config =
+            serde_yaml::from_str(content).map_err(|source| DootError::ConfigParse {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        for group in config.groups.values_mut() {
+            group.split_optional_resolvers();
+        }
+        for (name, group) in &config.groups {
+            group.check_resolver_names(name)?;
+        }
+        config.normalize_resolvers()?;
+        config.add_doot_group()?;
 
         if config.version != "v1" {
-            anyhow::bail!("Unsupported config version: {}", config.version);
+            return Err(DootError::Config(format!(
+                "Unsupported config version: {}",
+                config.version
+            ))
+            .into());
         }
+        config.check_requires()?;
 
         Ok(config)
     }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub fn parse(content: &str) -> Result<Self> {
+        Self::from_yaml(content, Path::new("doot.yaml"))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn diff_limits_catch_size_and_long_lines() {
+        let limits = DiffLimits {
+            max_size: 20,
+            max_line_length: 5,
+        };
+        assert!(limits.exceeded("a\nb\n", "a\nc\n").is_none());
+        assert!(limits.exceeded("", &"x\n".repeat(11)).is_some());
+        assert!(limits.exceeded("abcdefg", "").is_some());
+    }
+
     #[test]
     fn parse_minimal_config() {
         let config = Config::parse("version: v1").unwrap();
@@ -88,6 +866,86 @@ mod tests {
         assert!(config.plans.is_empty());
     }
 
+    #[test]
+    fn manage_doot_adds_the_settings_group() {
+        let yaml =
+            "version: v1\nmanage_doot: true\ngroups:\n  bash:\n    nux: \"~\"\n    mac: \"~\"\n";
+        let config = Config::parse(yaml).unwrap();
+        let group = &config.groups["doot"];
+        assert_eq!(group.resolvers.len(), 2);
+        assert_eq!(group.resolvers["nux"], "~/.config/doot");
+        assert_eq!(
+            group.files.as_deref(),
+            Some(&["settings.yaml".to_string()][..])
+        );
+
+        let taken = format!("{}  doot:\n    nux: \"~\"\n", yaml);
+        assert!(Config::parse(&taken).is_err());
+    }
+
+    #[test]
+    fn direction_refuses_the_other_way() {
+        let config = Config::parse(
+            "version: v1\ngroups:\n  firefox:\n    nux: \"~\"\n    direction: export-only\n",
+        )
+        .unwrap();
+        assert_eq!(config.groups["firefox"].direction, Direction::ExportOnly);
+        assert!(config.check_direction("firefox", false).is_ok());
+        let error = config.check_direction("firefox", true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Group 'firefox' is export-only, so it can't be imported"
+        );
+    }
+
+    #[test]
+    fn parse_optional_resolvers() {
+        let config =
+            Config::parse("version: v1\ngroups:\n  bash:\n    nux: \"~\"\n    mac?: \"$HOME\"\n")
+                .unwrap();
+        let group = &config.groups["bash"];
+        assert_eq!(group.resolvers["mac"], "$HOME");
+        assert!(group.optional_resolvers.contains("mac"));
+        assert!(!group.optional_resolvers.contains("nux"));
+    }
+
+    #[test]
+    fn resolver_aliases_rename_to_canonical() {
+        let config = Config::parse(
+            "version: v1\nresolver_aliases:\n  linux: nux\ngroups:\n  bash:\n    linux?: \"~\"\n",
+        )
+        .unwrap();
+        let group = &config.groups["bash"];
+        assert_eq!(group.resolvers["nux"], "~");
+        assert!(group.optional_resolvers.contains("nux"));
+        assert_eq!(config.canonical_resolver("linux"), "nux");
+        assert_eq!(config.canonical_resolver("mac"), "mac");
+
+        let err = Config::parse(
+            "version: v1\nresolver_aliases:\n  linux: nux\ngroups:\n  bash:\n    linux: \"~\"\n    nux: \"~\"\n",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("defines both"));
+    }
+
+    #[test]
+    fn resolvers_named_like_options_are_rejected() {
+        for (option, hint) in [
+            ("kidn: crontab", "did you mean 'kind'"),
+            ("mode: link", "'mode'"),
+        ] {
+            let err = Config::parse(&format!(
+                "version: v1\ngroups:\n  cron:\n    nux: \"~\"\n    {}\n",
+                option
+            ))
+            .unwrap_err();
+            assert!(err.to_string().contains(hint), "{}", err);
+        }
+        assert!(
+            Config::parse("version: v1\ngroups:\n  g:\n    work: \"~\"\n    node: \"~\"\n").is_ok()
+        );
+    }
+
     #[test]
     fn parse_rejects_unknown_version() {
         let err = Config::parse("version: v99").unwrap_err();
@@ -100,6 +958,46 @@ mod tests {
         assert_eq!(config.mode, Mode::Link);
     }
 
+    #[test]
+    fn parse_hash_algorithm() {
+        let config = Config::parse("version: v1").unwrap();
+        assert_eq!(config.hash, HashAlgorithm::Blake3);
+
+        let config = Config::parse("version: v1\nhash: sha256").unwrap();
+        assert_eq!(config.hash, HashAlgorithm::Sha256);
+    }
+
+    #[test]
+    fn parse_compare_patterns() {
+        let config = Config::parse(
+            r#"
+version: v1
+groups:
+  code:
+    nux: "~/.config/Code/User"
+    compare:
+      "*.json": semantic-json
+      "*.conf": ignore-trailing-ws
+    managed_keys:
+      settings.json: [editor.fontSize]
+    merge:
+      .gitconfig: ini
+    blocks: [.bashrc]
+    create_only: [local.zsh]
+"#,
+        )
+        .unwrap();
+
+        let code = config.get_group("code").unwrap();
+        assert_eq!(code.resolvers.len(), 1);
+        assert_eq!(code.compare["*.json"], CompareMode::SemanticJson);
+        assert_eq!(code.compare["*.conf"], CompareMode::IgnoreTrailingWs);
+        assert_eq!(code.managed_keys["settings.json"], ["editor.fontSize"]);
+        assert_eq!(code.merge[".gitconfig"], MergeStrategy::Ini);
+        assert_eq!(code.blocks, [".bashrc"]);
+        assert_eq!(code.create_only, ["local.zsh"]);
+    }
+
     #[test]
     fn get_resolver_from_group() {
         let config = Config::parse(
@@ -117,6 +1015,51 @@ groups:
         assert_eq!(config.get_resolver("bash", "mac").unwrap(), "$HOME");
     }
 
+    #[test]
+    fn resolve_group_path_under_destdir() {
+        let mut config = Config::parse(
+            r#"
+version: v1
+groups:
+  ssh:
+    nux: "/etc/ssh"
+  cron:
+    kind: crontab
+    nux: "root"
+"#,
+        )
+        .unwrap();
+        config.destdir = Some(PathBuf::from("/mnt/newroot"));
+
+        assert_eq!(
+            config.resolve_group_path("ssh", "nux").unwrap(),
+            PathBuf::from("/mnt/newroot/etc/ssh")
+        );
+        assert_eq!(
+            config.resolve_group_path("cron", "nux").unwrap(),
+            PathBuf::from("root")
+        );
+    }
+
+    #[test]
+    fn resolve_group_path_substitutes_user() {
+        let mut config = Config::parse(
+            r#"
+version: v1
+groups:
+  bash:
+    nux: "/home/{user}"
+"#,
+        )
+        .unwrap();
+        config.user = Some("alice".to_string());
+
+        assert_eq!(
+            config.resolve_group_path("bash", "nux").unwrap(),
+            PathBuf::from("/home/alice")
+        );
+    }
+
     #[test]
     fn get_resolver_missing_group() {
         let config = Config::parse("version: v1").unwrap();
@@ -180,4 +1123,48 @@ groups:
         let groups = config.get_plan_groups("minimal").unwrap();
         assert_eq!(groups, vec!["bash"]);
     }
+
+    #[test]
+    fn parse_crontab_group() {
+        let config = Config::parse(
+            r#"
+version: v1
+groups:
+  cron:
+    kind: crontab
+    nux: "$USER"
+  bash:
+    nux: "~"
+"#,
+        )
+        .unwrap();
+
+        let cron = config.get_group("cron").unwrap();
+        assert_eq!(cron.kind, GroupKind::Crontab);
+        assert_eq!(cron.resolvers.len(), 1);
+        assert_eq!(config.get_resolver("cron", "nux").unwrap(), "$USER");
+        assert_eq!(config.get_group("bash").unwrap().kind, GroupKind::Files);
+    }
+
+    #[test]
+    fn parse_env_group_variables() {
+        let config = Config::parse(
+            r#"
+version: v1
+groups:
+  env:
+    kind: env
+    nux: "~/.config/environment.d/doot.conf"
+    variables:
+      nux:
+        EDITOR: nvim
+"#,
+        )
+        .unwrap();
+
+        let env = config.get_group("env").unwrap();
+        assert_eq!(env.kind, GroupKind::Env);
+        assert_eq!(env.resolvers.len(), 1);
+        assert_eq!(env.variables["nux"]["EDITOR"], "nvim");
+    }
 }
@@ -0,0 +1,505 @@
+//! `doot mv resolver`, `doot set resolver` and the groups `doot doctor`
+//! registers: edits of doot.yaml that keep its comments and layout, checked by
+//! parsing the result.
+
+use crate::config::{Config, Group};
+use crate::error::DootError;
+use anyhow::{Context, Result};
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A line of the form `key: ...`, with the byte range of the key (including
+/// quotes) and the key without them.
+struct KeyLine<'a> {
+    indent: usize,
+    range: std::ops::Range<usize>,
+    key: &'a str,
+}
+
+impl KeyLine<'_> {
+    /// What follows the colon, without a trailing comment.
+    fn value<'l>(&self, line: &'l str) -> &'l str {
+        let after = line[self.range.end..].trim_start()[1..].trim();
+        match after.starts_with('#') {
+            true => "",
+            false => after,
+        }
+    }
+}
+
+fn key_line(line: &str) -> Option<KeyLine<'_>> {
+    let indent = indent(line);
+    let rest = &line[indent..];
+    if rest.is_empty() || rest.starts_with('#') || rest.starts_with('-') {
+        return None;
+    }
+    let (key, length) = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let end = rest[1..].find(quote)?;
+            (&rest[1..=end], end + 2)
+        }
+        _ => {
+            let end = rest.find(':')?;
+            (rest[..end].trim_end(), end)
+        }
+    };
+    let after = rest[length..].trim_start();
+    (after.starts_with(':') && !key.is_empty()).then_some(KeyLine {
+        indent,
+        range: indent..indent + length,
+        key,
+    })
+}
+
+/// Whether `line` holds content, rather than being blank or a comment.
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// The lines `start..end` below the line `start - 1` that are indented deeper
+/// than `parent`, and the indentation of their keys.
+fn block(lines: &[String], start: usize, parent: usize) -> (usize, Option<usize>) {
+    let mut end = start;
+    let mut child = None;
+    while end < lines.len() {
+        let line = &lines[end];
+        if is_content(line) {
+            if indent(line) <= parent {
+                break;
+            }
+            child.get_or_insert(indent(line));
+        }
+        end += 1;
+    }
+    // Trailing blank lines and comments belong to what follows.
+    while end > start && !is_content(&lines[end - 1]) {
+        end -= 1;
+    }
+    (end, child)
+}
+
+/// A group in doot.yaml: its name, the lines of its settings and their
+/// indentation.
+struct GroupLines {
+    name: String,
+    start: usize,
+    end: usize,
+    indent: Option<usize>,
+}
+
+fn groups(lines: &[String]) -> Result<Vec<GroupLines>> {
+    let Some(header) = lines
+        .iter()
+        .position(|line| key_line(line).is_some_and(|k| k.indent == 0 && k.key == "groups"))
+    else {
+        return Ok(Vec::new());
+    };
+    let (end, Some(group_indent)) = block(lines, header + 1, 0) else {
+        return Ok(Vec::new());
+    };
+
+    let mut groups = Vec::new();
+    let mut i = header + 1;
+    while i < end {
+        let Some(key) = key_line(&lines[i]).filter(|k| k.indent == group_indent) else {
+            if is_content(&lines[i]) && indent(&lines[i]) == group_indent {
+                return Err(not_editable());
+            }
+            i += 1;
+            continue;
+        };
+        if !key.value(&lines[i]).is_empty() {
+            // A group written inline, such as `bash: {nux: "~"}`.
+            return Err(not_editable());
+        }
+        let (group_end, settings) = block(lines, i + 1, group_indent);
+        groups.push(GroupLines {
+            name: key.key.to_string(),
+            start: i + 1,
+            end: group_end,
+            indent: settings,
+        });
+        i = group_end.max(i + 1);
+    }
+    Ok(groups)
+}
+
+fn not_editable() -> anyhow::Error {
+    DootError::Config(
+        "doot.yaml writes groups in a form doot can't edit safely; edit it by hand".to_string(),
+    )
+    .into()
+}
+
+/// Replaces the key of `line`, at `range`, with `new`, keeping its quotes.
+fn rename_key(line: &mut String, range: std::ops::Range<usize>, new: &str) {
+    let replacement = match line[range.clone()].chars().next() {
+        Some(quote @ ('"' | '\'')) => format!("{}{}{}", quote, new, quote),
+        _ => new.to_string(),
+    };
+    line.replace_range(range, &replacement);
+}
+
+/// Renames resolver `old` to `new` in every group of `text`: the key of its
+/// path, and its entries under `requires_mount` and `variables`. Returns the
+/// edited text and the groups that changed.
+pub fn rename_resolver(text: &str, old: &str, new: &str) -> Result<(String, Vec<String>)> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let mut renamed = Vec::new();
+    for group in groups(&lines)? {
+        let Some(settings) = group.indent else {
+            continue;
+        };
+        let mut changed = false;
+        let mut i = group.start;
+        while i < group.end {
+            let Some(key) = key_line(&lines[i]).filter(|k| k.indent == settings) else {
+                i += 1;
+                continue;
+            };
+            if key.key.trim_end_matches('?') == new {
+                return Err(DootError::Config(format!(
+                    "Group '{}' already has a resolver '{}'",
+                    group.name, new
+                ))
+                .into());
+            }
+            if key.key.trim_end_matches('?') == old {
+                let name = new.to_string() + &key.key[old.len()..];
+                let range = key.range.clone();
+                rename_key(&mut lines[i], range, &name);
+                changed = true;
+            } else if key.key == "requires_mount" || key.key == "variables" {
+                let (end, Some(child)) = block(&lines, i + 1, settings) else {
+                    i += 1;
+                    continue;
+                };
+                for line in &mut lines[i + 1..end] {
+                    let range = key_line(line)
+                        .filter(|k| k.indent == child && k.key == old)
+                        .map(|k| k.range);
+                    if let Some(range) = range {
+                        rename_key(line, range, new);
+                        changed = true;
+                    }
+                }
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        if changed {
+            renamed.push(group.name);
+        }
+    }
+    Ok((join(&lines, text), renamed))
+}
+
+/// Sets the path of resolver `name` of `group` in `text` to `path`, adding
+/// the resolver if the group doesn't have it.
+pub fn set_resolver(text: &str, group_name: &str, name: &str, path: &str) -> Result<String> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let group = groups(&lines)?
+        .into_iter()
+        .find(|group| group.name == group_name)
+        .ok_or_else(|| DootError::Config(format!("Group '{}' not found", group_name)))?;
+    // A double-quoted YAML string; `~` alone would otherwise read as null.
+    let value = serde_json::to_string(path)?;
+
+    let Some(settings) = group.indent else {
+        let line = format!(
+            "{}{}: {}",
+            " ".repeat(indent(&lines[group.start - 1]) + 2),
+            name,
+            value
+        );
+        lines.insert(group.start, line);
+        return Ok(join(&lines, text));
+    };
+    let existing = (group.start..group.end).find_map(|i| {
+        key_line(&lines[i])
+            .filter(|k| k.indent == settings && k.key.trim_end_matches('?') == name)
+            .map(|k| (i, k.range.end))
+    });
+    match existing {
+        Some((i, key_end)) => {
+            let colon = key_end + lines[i][key_end..].find(':').unwrap_or_default();
+            // Keep a trailing comment.
+            let rest = &lines[i][colon + 1..];
+            let comment = rest
+                .find(" #")
+                .map(|at| rest[rest[..at].trim_end().len()..].to_string())
+                .unwrap_or_default();
+            lines[i] = format!("{}: {}{}", &lines[i][..colon], value, comment);
+        }
+        None => {
+            let line = format!("{}{}: {}", " ".repeat(settings), name, value);
+            lines.insert(group.end, line);
+        }
+    }
+    Ok(join(&lines, text))
+}
+
+/// Adds group `group_name` with resolver `name` at `path` after the last group
+/// of `text`, adding `groups:` if there is none.
+pub fn add_group(text: &str, group_name: &str, name: &str, path: &str) -> Result<String> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let value = serde_json::to_string(path)?;
+    let existing = groups(&lines)?;
+    let Some(last) = existing.last() else {
+        if lines
+            .iter()
+            .any(|line| key_line(line).is_some_and(|k| k.indent == 0 && k.key == "groups"))
+        {
+            return Err(not_editable());
+        }
+        lines.push("groups:".to_string());
+        lines.push(format!("  {}:", group_name));
+        lines.push(format!("    {}: {}", name, value));
+        return Ok(join(&lines, text));
+    };
+    let group_indent = indent(&lines[last.start - 1]);
+    let settings = existing
+        .iter()
+        .find_map(|group| group.indent)
+        .unwrap_or(group_indent + 2);
+    lines.splice(
+        last.end..last.end,
+        [
+            format!("{}{}:", " ".repeat(group_indent), group_name),
+            format!("{}{}: {}", " ".repeat(settings), name, value),
+        ],
+    );
+    Ok(join(&lines, text))
+}
+
+fn join(lines: &[String], original: &str) -> String {
+    let mut text = lines.join("\n");
+    if original.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// What the edits may change of a group, to compare before and after.
+type Resolvers = (
+    BTreeMap<String, String>,
+    Vec<String>,
+    BTreeMap<String, String>,
+    BTreeMap<String, BTreeMap<String, String>>,
+);
+
+fn resolvers(group: &Group) -> Resolvers {
+    (
+        group.resolvers.clone().into_iter().collect(),
+        group.optional_resolvers.iter().cloned().collect(),
+        group.requires_mount.clone().into_iter().collect(),
+        group.variables.clone().into_iter().collect(),
+    )
+}
+
+/// Checks that `edited` parses and that its groups' resolvers are those of
+/// `config` after `expected`.
+fn verify(
+    config: &Config,
+    edited: &str,
+    path: &Path,
+    expected: impl FnOnce(&mut BTreeMap<String, Resolvers>),
+) -> Result<()> {
+    let result = Config::from_yaml(edited, path)
+        .context("The edited doot.yaml doesn't parse; it was left as it was")?;
+    let all = |config: &Config| -> BTreeMap<String, Resolvers> {
+        config
+            .groups
+            .iter()
+            .map(|(name, group)| (name.clone(), resolvers(group)))
+            .collect()
+    };
+    let mut before = all(config);
+    expected(&mut before);
+    if before != all(&result) {
+        return Err(not_editable());
+    }
+    Ok(())
+}
+
+fn rename(map: &mut BTreeMap<String, impl Sized>, old: &str, new: &str) {
+    if let Some(value) = map.remove(old) {
+        map.insert(new.to_string(), value);
+    }
+}
+
+/// `doot mv resolver OLD NEW`
+pub fn run_mv_resolver(
+    config: &Config,
+    path: &Path,
+    old: &str,
+    new: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read: {}", path.display()))?;
+    let (edited, groups) = rename_resolver(&text, old, new)?;
+    if groups.is_empty() {
+        return Err(DootError::Resolver(format!("No group has a resolver '{}'", old)).into());
+    }
+    verify(config, &edited, path, |groups| {
+        for (paths, optional, mounts, vars) in groups.values_mut() {
+            rename(paths, old, new);
+            rename(mounts, old, new);
+            rename(vars, old, new);
+            *optional = std::mem::take(optional)
+                .into_iter()
+                .map(|name| if name == old { new.to_string() } else { name })
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .collect();
+        }
+    })?;
+    if write(path, &text, &edited, dry_run)? {
+        println!(
+            "Renamed resolver '{}' to '{}' in {}",
+            old,
+            new,
+            groups.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// `doot set resolver GROUP NAME PATH`
+pub fn run_set_resolver(
+    config: &Config,
+    path: &Path,
+    group: &str,
+    name: &str,
+    resolved: &str,
+    dry_run: bool,
+) -> Result<()> {
+    config.get_group(group)?;
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read: {}", path.display()))?;
+    let edited = set_resolver(&text, group, name, resolved)?;
+    verify(config, &edited, path, |groups| {
+        if let Some((paths, ..)) = groups.get_mut(group) {
+            paths.insert(name.to_string(), resolved.to_string());
+        }
+    })?;
+    if write(path, &text, &edited, dry_run)? {
+        println!(
+            "Set resolver '{}' of group '{}' to {}",
+            name, group, resolved
+        );
+    }
+    Ok(())
+}
+
+/// Registers the repo directory `group` as a group with resolver `name` at
+/// `resolved`, for `doot doctor`.
+pub fn run_add_group(
+    config: &Config,
+    path: &Path,
+    group: &str,
+    name: &str,
+    resolved: &str,
+) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read: {}", path.display()))?;
+    let edited = add_group(&text, group, name, resolved)?;
+    verify(config, &edited, path, |groups| {
+        let paths = BTreeMap::from([(name.to_string(), resolved.to_string())]);
+        groups.insert(
+            group.to_string(),
+            (paths, Vec::new(), BTreeMap::new(), BTreeMap::new()),
+        );
+    })?;
+    write(path, &text, &edited, false)?;
+    println!("Added group '{}' to {}", group, path.display());
+    Ok(())
+}
+
+/// Writes `edited` over `path` at once, or with `--dry-run` only shows the
+/// change. Returns whether it wrote.
+fn write(path: &Path, text: &str, edited: &str, dry_run: bool) -> Result<bool> {
+    if dry_run {
+        let name = path.display().to_string();
+        print!(
+            "{}",
+            TextDiff::from_lines(text, edited)
+                .unified_diff()
+                .header(&name, &name)
+        );
+        return Ok(false);
+    }
+    let staged = path.with_extension("yaml.tmp");
+    std::fs::write(&staged, edited)
+        .with_context(|| format!("Failed to write: {}", staged.display()))?;
+    std::fs::rename(&staged, path)
+        .with_context(|| format!("Failed to write: {}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "version: v1
+groups:
+  # Shell
+  bash:
+    nux: \"~\"  # home
+    mac?: \"~\"
+    requires_mount:
+      nux: /mnt
+  vim:
+    nux: \"~/.vim\"
+
+plans:
+  all: [bash, vim]
+";
+
+    #[test]
+    fn renames_resolver_in_every_group() {
+        let (edited, groups) = rename_resolver(CONFIG, "nux", "linux").unwrap();
+        assert_eq!(groups, ["bash", "vim"]);
+        assert_eq!(
+            edited,
+            CONFIG
+                .replace("    nux: \"~\"", "    linux: \"~\"")
+                .replace("      nux: /mnt", "      linux: /mnt")
+                .replace("    nux: \"~/.vim\"", "    linux: \"~/.vim\"")
+        );
+
+        let (edited, groups) = rename_resolver(CONFIG, "mac", "darwin").unwrap();
+        assert_eq!(groups, ["bash"]);
+        assert!(edited.contains("    darwin?: \"~\"\n"));
+        assert!(rename_resolver(CONFIG, "nux", "mac").is_err());
+    }
+
+    #[test]
+    fn sets_resolver_path() {
+        let edited = set_resolver(CONFIG, "bash", "nux", "~/.config/bash").unwrap();
+        assert!(edited.contains("    nux: \"~/.config/bash\"  # home\n"));
+
+        let edited = set_resolver(CONFIG, "vim", "mac", "~").unwrap();
+        assert!(edited.contains("    nux: \"~/.vim\"\n    mac: \"~\"\n\nplans:"));
+        assert!(set_resolver(CONFIG, "zsh", "nux", "~").is_err());
+
+        let config = Config::from_yaml(&edited, Path::new("doot.yaml")).unwrap();
+        assert_eq!(config.groups["vim"].resolvers["mac"], "~");
+    }
+
+    #[test]
+    fn adds_group_after_the_last() {
+        let edited = add_group(CONFIG, "tmux", "nux", "~").unwrap();
+        assert!(edited.contains("    nux: \"~/.vim\"\n  tmux:\n    nux: \"~\"\n\nplans:"));
+        let edited = add_group("version: v1\n", "tmux", "nux", "~").unwrap();
+        assert_eq!(edited, "version: v1\ngroups:\n  tmux:\n    nux: \"~\"\n");
+    }
+}
@@ -0,0 +1,166 @@
+use crate::config::Config;
+use crate::state;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Reports kept in the crash directory; older ones are deleted.
+const MAX_REPORTS: usize = 20;
+
+static CONFIG: OnceLock<ConfigSummary> = OnceLock::new();
+
+/// What a report says about the config: names and settings, but no paths,
+/// variable values or other content that could be private.
+#[derive(Debug, Clone, Serialize)]
+struct ConfigSummary {
+    version: String,
+    mode: String,
+    hash: String,
+    plans: Vec<String>,
+    /// Group names with their kind and resolver names
+    groups: BTreeMap<String, String>,
+    env: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    doot: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    timestamp: u64,
+    command: Vec<String>,
+    config: Option<ConfigSummary>,
+    kind: &'static str,
+    /// Category of an error, see `DootError::code`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    message: String,
+    causes: Vec<String>,
+    backtrace: String,
+}
+
+/// Remembers the loaded config for reports written later in the run.
+pub fn set_config(config: &Config) {
+    let mut groups = BTreeMap::new();
+    for (name, group) in &config.groups {
+        let mut resolvers: Vec<_> = group.resolvers.keys().cloned().collect();
+        resolvers.sort();
+        groups.insert(
+            name.clone(),
+            format!("{:?}: {}", group.kind, resolvers.join(", ")),
+        );
+    }
+    let mut plans: Vec<_> = config.plans.keys().cloned().collect();
+    plans.sort();
+
+    let _ = CONFIG.set(ConfigSummary {
+        version: config.version.clone(),
+        mode: format!("{:?}", config.mode),
+        hash: format!("{:?}", config.hash),
+        plans,
+        groups,
+        env: config.env.keys().cloned().collect(),
+    });
+}
+
+/// Writes a report for panics, in addition to the usual panic message.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let message = match info.location() {
+            Some(location) => format!("{} at {}", payload(info.payload()), location),
+            None => payload(info.payload()),
+        };
+        let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+        if let Ok(path) = write("panic", None, message, Vec::new(), backtrace) {
+            eprintln!(
+                "doot crashed. A report was written to {}; please attach it when reporting a bug.",
+                path.display()
+            );
+        }
+    }));
+}
+
+/// Writes a report for an error that ends the run. The backtrace is only
+/// captured if `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set.
+pub fn report_error(error: &anyhow::Error) {
+    let causes = error
+        .chain()
+        .skip(1)
+        .map(|cause| cause.to_string())
+        .collect();
+    let backtrace = error.backtrace().to_string();
+    if let Ok(path) = write(
+        "error",
+        Some(crate::error::code(error)),
+        error.to_string(),
+        causes,
+        backtrace,
+    ) {
+        eprintln!("Details: {}", path.display());
+    }
+}
+
+fn payload(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+fn write(
+    kind: &'static str,
+    code: Option<&'static str>,
+    message: String,
+    causes: Vec<String>,
+    backtrace: String,
+) -> Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let report = Report {
+        doot: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        timestamp,
+        command: std::env::args().skip(1).collect(),
+        config: CONFIG.get().cloned(),
+        kind,
+        code,
+        message,
+        causes,
+        backtrace,
+    };
+
+    let dir = state::dir()?.join("crashes");
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    let path = dir.join(format!("{}-{}.json", timestamp, std::process::id()));
+    std::fs::write(&path, serde_json::to_vec_pretty(&report)?)
+        .with_context(|| format!("Failed to write: {}", path.display()))?;
+
+    prune(&dir);
+    Ok(path)
+}
+
+/// Deletes all but the newest `MAX_REPORTS` reports.
+fn prune(dir: &std::path::Path) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut reports: Vec<PathBuf> = read_dir
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    reports.sort();
+    let excess = reports.len().saturating_sub(MAX_REPORTS);
+    for path in &reports[..excess] {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::config::{Config, HashAlgorithm};
+use crate::error::DootError;
+use crate::plan::{self, FileEntry, PlanBuilder};
+use crate::state;
+use crate::status::Scope;
+use crate::store::{self, CachingStore};
+use anyhow::{Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::SystemTime;
+
+/// Returns the socket of the daemon serving `config_path` in the current
+/// directory. Each repository and config file gets its own daemon.
+pub fn socket_path(config_path: &Path) -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let config = cwd.join(config_path);
+    let config = config.canonicalize().unwrap_or(config);
+
+    let key = format!("{}\0{}", cwd.display(), config.display());
+    let key = store::hash_bytes(HashAlgorithm::Blake3, key.as_bytes());
+    Ok(state::dir()?.join(format!("daemon-{}.sock", &key[..16])))
+}
+
+/// Watches the directories of cached files and drops the cache entries of
+/// files that change. This catches edits that leave size and mtime unchanged,
+/// e.g. on filesystems with coarse timestamps.
+struct Invalidator {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    watched: HashSet<PathBuf>,
+}
+
+impl Invalidator {
+    fn new() -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher =
+            notify::recommended_watcher(sender).context("Failed to start file watcher")?;
+        Ok(Self {
+            watcher,
+            events,
+            watched: HashSet::new(),
+        })
+    }
+
+    /// Drops cache entries of files changed since the last call.
+    fn apply(&self, store: &CachingStore) {
+        for event in self.events.try_iter().flatten() {
+            for path in &event.paths {
+                store.invalidate(path);
+            }
+        }
+    }
+
+    /// Starts watching the directories of newly cached files.
+    fn watch(&mut self, store: &CachingStore) {
+        for path in store.cached_paths() {
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            // Files in directories that cannot be watched still get size and
+            // mtime checks.
+            if self.watched.insert(dir.to_path_buf()) {
+                let _ = self.watcher.watch(dir, RecursiveMode::NonRecursive);
+            }
+        }
+    }
+}
+
+/// Config and store kept warm between requests.
+struct Warm {
+    config_path: PathBuf,
+    modified: Option<SystemTime>,
+    config: Config,
+    store: CachingStore,
+    invalidator: Option<Invalidator>,
+}
+
+impl Warm {
+    fn load(config_path: &Path) -> Result<Self> {
+        let config = Config::load(config_path)?;
+        config.configure_environment()?;
+        let store = CachingStore::new(store::create_store(
+            config.mode,
+            config.hash,
+            config.sudo_paths(),
+        ))
+        .sensitive(config.sensitive_paths());
+        let invalidator = Invalidator::new()
+            .inspect_err(|e| eprintln!("Watching files disabled: {:#}", e))
+            .ok();
+        Ok(Self {
+            config_path: config_path.to_path_buf(),
+            modified: modified(config_path),
+            config,
+            store,
+            invalidator,
+        })
+    }
+
+    /// Reloads the config (dropping the cache) if it changed on disk.
+    fn refresh(&mut self) -> Result<()> {
+        if modified(&self.config_path) != self.modified {
+            *self = Self::load(&self.config_path)?;
+        }
+        if let Some(invalidator) = &self.invalidator {
+            invalidator.apply(&self.store);
+        }
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+pub fn serve(config_path: &Path) -> Result<()> {
+    let socket = socket_path(config_path)?;
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            anyhow::bail!("A daemon is already running for this repository");
+        }
+        std::fs::remove_file(&socket)
+            .with_context(|| format!("Failed to remove stale socket: {}", socket.display()))?;
+    }
+
+    let listener = UnixListener::bind(&socket)
+        .with_context(|| format!("Failed to bind socket: {}", socket.display()))?;
+    let mut warm = Warm::load(config_path)?;
+
+    println!("Listening on {}", socket.display());
+
+    for stream in listener.incoming() {
+        let result = stream
+            .context("Failed to accept connection")
+            .and_then(|stream| handle(&mut warm, stream));
+        if let Err(e) = result {
+            eprintln!("Request failed: {:#}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Outcome of planning the export of one group, as sent by the daemon.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Planned {
+    Entries(Vec<FileEntry>),
+    /// Planning failed; `code` is the error's [`crate::error::code`]
+    Failed {
+        code: String,
+        message: String,
+    },
+}
+
+impl Planned {
+    fn of(result: Result<Vec<FileEntry>>) -> Self {
+        match result {
+            Ok(entries) => Self::Entries(entries),
+            Err(error) => Self::Failed {
+                code: crate::error::code(&error).to_string(),
+                message: format!("{:#}", error),
+            },
+        }
+    }
+
+    /// The result the group would have had if it was planned in-process,
+    /// keeping the error category that decides whether the run goes on.
+    fn into_result(self) -> Result<Vec<FileEntry>> {
+        match self {
+            Self::Entries(entries) => Ok(entries),
+            Self::Failed { code, message } => Err(match code.as_str() {
+                "timeout" => DootError::Timeout(message).into(),
+                "config" => DootError::Config(message).into(),
+                "resolver" => DootError::Resolver(message).into(),
+                "conflict" => DootError::Conflict(message).into(),
+                _ => anyhow::anyhow!(message),
+            }),
+        }
+    }
+}
+
+/// Handles one request, of the form
+/// `status\t<resolver>\t<verbose>\t<color>\t<ascii>\t<json>\t<scope>` or
+/// `export\t<resolver>\t<group>...`.
+fn handle(warm: &mut Warm, mut stream: UnixStream) -> Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let fields: Vec<_> = request.trim_end().split('\t').collect();
+
+    let mut body = Vec::new();
+    let result = match fields.as_slice() {
+        ["status", resolver, verbose, color, ascii, json, scope] => warm.refresh().and_then(|_| {
+            colored::control::set_override(*color == "1");
+            crate::style::set_ascii(*ascii == "1");
+            crate::style::set_json(*json == "1");
+            crate::status::write(
+                &mut body,
+                &warm.config,
+                &warm.store,
+                resolver,
+                &scope.parse()?,
+                *verbose == "1",
+            )
+        }),
+        ["export", resolver, groups @ ..] => warm.refresh().and_then(|_| {
+            let builder = PlanBuilder::new(&warm.store);
+            let planned: Vec<_> = groups
+                .iter()
+                .map(|group| {
+                    Planned::of(plan::export_entries(
+                        &warm.config,
+                        &builder,
+                        group,
+                        resolver,
+                    ))
+                })
+                .collect();
+            serde_json::to_writer(&mut body, &planned)?;
+            Ok(())
+        }),
+        _ => Err(anyhow::anyhow!("Unknown request: {}", request.trim_end())),
+    };
+
+    if let Some(invalidator) = &mut warm.invalidator {
+        invalidator.watch(&warm.store);
+    }
+
+    match result {
+        Ok(()) => {
+            stream.write_all(b"ok\n")?;
+            stream.write_all(&body)?;
+        }
+        Err(e) => {
+            stream.write_all(b"error\n")?;
+            stream.write_all(format!("{:#}", e).as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends `request` to a running daemon and returns its answer, or `None` if
+/// no daemon is serving this repository or it can't answer for `config`.
+fn request(config: &Config, config_path: &Path, request: &str) -> Result<Option<String>> {
+    if !serves(config) {
+        return Ok(None);
+    }
+    let socket = socket_path(config_path)?;
+    let Ok(mut stream) = UnixStream::connect(&socket) else {
+        return Ok(None);
+    };
+    writeln!(stream, "{}", request)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    match response.split_once('\n') {
+        Some(("ok", body)) => Ok(Some(body.to_string())),
+        Some(("error", message)) => anyhow::bail!("{}", message),
+        _ => Ok(None),
+    }
+}
+
+/// Asks a running daemon to plan exporting `groups` with `resolver`, using
+/// its warm config and hashes. Returns each group's entries, or `None` if no
+/// daemon can answer.
+pub fn request_export(
+    config: &Config,
+    config_path: &Path,
+    resolver: &str,
+    groups: &[String],
+) -> Result<Option<Vec<Result<Vec<FileEntry>>>>> {
+    let request_line = format!("export\t{}\t{}", resolver, groups.join("\t"));
+    let Some(body) = request(config, config_path, &request_line)? else {
+        return Ok(None);
+    };
+    let planned: Vec<Planned> =
+        serde_json::from_str(&body).context("Failed to parse the daemon's plan")?;
+    anyhow::ensure!(
+        planned.len() == groups.len(),
+        "The daemon planned {} group(s) instead of {}",
+        planned.len(),
+        groups.len()
+    );
+    Ok(Some(
+        planned.into_iter().map(Planned::into_result).collect(),
+    ))
+}
+
+/// Whether the daemon can answer for `config`. It only watches the live
+/// roots, so runs under `--destdir`, `--user` or `--sandbox` are checked
+/// directly.
+fn serves(config: &Config) -> bool {
+    config.destdir.is_none() && config.user.is_none() && crate::sandbox::root().is_none()
+}
+
+/// Asks a running daemon for the status output and prints it. Returns
+/// `false` if no daemon is serving this repository, or it can't answer for
+/// `config`.
+pub fn request_status(
+    config: &Config,
+    config_path: &Path,
+    resolver: &str,
+    scope: &Scope,
+    verbose: bool,
+) -> Result<bool> {
+    let color = colored::control::SHOULD_COLORIZE.should_colorize();
+    let request_line = format!(
+        "status\t{}\t{}\t{}\t{}\t{}\t{}",
+        resolver,
+        verbose as u8,
+        color as u8,
+        crate::style::is_ascii() as u8,
+        crate::style::is_json() as u8,
+        scope
+    );
+    match request(config, config_path, &request_line)? {
+        Some(body) => {
+            print!("{}", body);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::FileStatus;
+
+    #[test]
+    fn skips_daemon_for_other_roots() {
+        let config = Config::parse("version: v1\ngroups: {}\n").unwrap();
+        assert!(serves(&config));
+
+        let destdir = Config {
+            destdir: Some(PathBuf::from("/tmp/stage")),
+            ..config.clone()
+        };
+        assert!(!serves(&destdir));
+        assert!(
+            !request_status(&destdir, Path::new("doot.yaml"), "nux", &Scope::All, false).unwrap()
+        );
+
+        let user = Config {
+            user: Some("alice".to_string()),
+            ..config
+        };
+        assert!(!serves(&user));
+    }
+
+    #[test]
+    fn planned_exports_keep_entries_and_error_categories() {
+        let entry = crate::testing::entry(".rc", "/repo/sh/.rc", "/home/.rc", FileStatus::Create);
+        let results: Vec<Result<Vec<FileEntry>>> = vec![
+            Ok(vec![entry]),
+            Err(DootError::Timeout("Timed out reading /box".to_string()).into()),
+            Err(anyhow::anyhow!("disk on fire")),
+        ];
+        let planned: Vec<_> = results.into_iter().map(Planned::of).collect();
+        let body = serde_json::to_string(&planned).unwrap();
+        let planned: Vec<Planned> = serde_json::from_str(&body).unwrap();
+        let results: Vec<_> = planned.into_iter().map(Planned::into_result).collect();
+
+        let entries = results[0].as_ref().unwrap();
+        assert_eq!(entries[0].destination, PathBuf::from("/home/.rc"));
+        let timeout = results[1].as_ref().unwrap_err();
+        assert_eq!(crate::error::code(timeout), "timeout");
+        assert_eq!(timeout.to_string(), "Timed out reading /box");
+        assert_eq!(results[2].as_ref().unwrap_err().to_string(), "disk on fire");
+    }
+}
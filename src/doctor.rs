@@ -0,0 +1,322 @@
+use crate::config::{Config, CreateParents, Group, GroupKind};
+use crate::process;
+use crate::store::container;
+use crate::style::Icon;
+use anyhow::Result;
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+/// External programs a group of `kind` needs on this machine.
+fn required_programs(kind: GroupKind) -> &'static [&'static str] {
+    match kind {
+        GroupKind::Crontab => &["crontab"],
+        GroupKind::Files | GroupKind::Env => &[],
+    }
+}
+
+/// Returns the problems that would make syncing `group_name` fail partway
+/// through a run on this machine.
+pub fn check_group(config: &Config, group_name: &str, resolver: &str) -> Result<Vec<String>> {
+    let group = config.get_group(group_name)?;
+    let mut problems = Vec::new();
+
+    let engine = match config.resolve_group_path(group_name, resolver) {
+        Ok(path) => {
+            if let Some(problem) = missing_root(group, &path) {
+                problems.push(problem);
+            }
+            container::engine_of(&path)
+        }
+        Err(e) => {
+            problems.push(format!("{:#}", e));
+            None
+        }
+    };
+
+    for program in required_programs(group.kind).iter().chain(&engine) {
+        if !process::is_available(program) {
+            problems.push(format!("`{}` not found in PATH", program));
+        }
+    }
+    if let Some((engine, host)) = engine.and_then(|e| Some((e, container::remote_host(e)?))) {
+        if let Err(e) = crate::offline::ensure_online(&format!("`{}` at {}", engine, host)) {
+            problems.push(e.to_string());
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Problems with the `age` keys in the config: an identity file that can't
+/// be read, recipients `age` won't accept, or `sensitive` groups whose
+/// backups can't be encrypted because there are no recipients.
+pub fn age_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(identity) = &config.age.identity {
+        match crate::resolver::resolve_path(identity) {
+            Ok(path) => {
+                if let Err(e) = std::fs::File::open(&path) {
+                    problems.push(format!(
+                        "age.identity {} can't be read: {}",
+                        path.display(),
+                        e
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("age.identity: {:#}", e)),
+        }
+    }
+    for recipient in &config.age.recipients {
+        if !crate::age::is_recipient(recipient) {
+            problems.push(format!(
+                "age.recipients: not an age or SSH key: {}",
+                recipient
+            ));
+        }
+    }
+    if config.backups.enabled && config.age.recipients.is_empty() {
+        let mut sensitive: Vec<_> = config
+            .groups
+            .iter()
+            .filter(|(_, group)| group.sensitive)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        sensitive.sort();
+        for name in sensitive {
+            problems.push(format!(
+                "Group '{}' is sensitive, but age.recipients isn't set, so runs that back up its files fail",
+                name
+            ));
+        }
+    }
+    let uses_age = config.age.identity.is_some() || !config.age.recipients.is_empty();
+    if uses_age && !process::is_available("age") {
+        problems.push("`age` not found in PATH".to_string());
+    }
+    problems
+}
+
+/// Complaint about the resolved path `root` of `group` not existing, if its
+/// `create_parents` doesn't allow creating it.
+fn missing_root(group: &Group, root: &Path) -> Option<String> {
+    let checked = group.kind == GroupKind::Files
+        && group.create_parents != CreateParents::Always
+        && !crate::store::is_pseudo_path(root);
+    (checked && !root.exists()).then(|| format!("root missing: {}", root.display()))
+}
+
+/// Checks the groups of a run before anything is changed.
+pub fn preflight(config: &Config, groups: &[String], resolver: &str) -> Result<()> {
+    for group_name in groups {
+        if let Some(problem) = check_group(config, group_name, resolver)?.first() {
+            anyhow::bail!(
+                "Group '{}' cannot be synced on this machine: {}",
+                group_name,
+                problem
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Pairs of resolvers that expand to the same path in every group, so one
+/// could become an alias of the other.
+pub fn consolidation_candidates(config: &Config) -> Vec<(String, String)> {
+    let names: BTreeSet<&String> = config
+        .groups
+        .values()
+        .flat_map(|group| group.resolvers.keys())
+        .collect();
+    let names: Vec<&String> = names.into_iter().collect();
+
+    let mut candidates = Vec::new();
+    for (i, a) in names.iter().enumerate() {
+        for b in &names[i + 1..] {
+            let same_everywhere = config.groups.iter().all(|(group_name, group)| {
+                match (
+                    group.resolvers.contains_key(*a),
+                    group.resolvers.contains_key(*b),
+                ) {
+                    (false, false) => true,
+                    (true, true) => {
+                        match (
+                            config.resolve_group_path(group_name, a),
+                            config.resolve_group_path(group_name, b),
+                        ) {
+                            (Ok(path_a), Ok(path_b)) => path_a == path_b,
+                            _ => false,
+                        }
+                    }
+                    _ => false,
+                }
+            });
+            if same_everywhere {
+                candidates.push(((*a).clone(), (*b).clone()));
+            }
+        }
+    }
+    candidates
+}
+
+/// Directories at the top of `repo` that look like groups, holding a
+/// `.dootignore` or known dotfiles, but that no group of `config` declares.
+/// Nothing syncs them.
+pub fn undeclared_groups(config: &Config, repo: &Path) -> Result<Vec<String>> {
+    let overrides = config.overrides_dir()?;
+    let Ok(entries) = std::fs::read_dir(repo) else {
+        return Ok(Vec::new());
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|ft| ft.is_dir()))
+        .filter(|entry| Some(entry.path()) != overrides)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.') && !config.groups.contains_key(name))
+        .filter(|name| {
+            let dir = repo.join(name);
+            dir.join(".dootignore").exists() || crate::scan::has_known_dotfiles(&dir)
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Offers to add each of `names` to doot.yaml as a group with `resolver`,
+/// asking for its path.
+fn offer_registration(config_path: &Path, resolver: &str, names: &[String]) -> Result<()> {
+    for name in names {
+        print!(
+            "\n  Path of '{}' for resolver '{}' to add it as a group (empty to skip): ",
+            name, resolver
+        );
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let path = input.trim();
+        if !path.is_empty() {
+            // Each registration is checked against doot.yaml as it is now.
+            let config = Config::load(config_path)?;
+            crate::config_edit::run_add_group(&config, config_path, name, resolver, path)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn run(config: &Config, config_path: &Path, resolver: &str, yes: bool) -> Result<()> {
+    let mut names: Vec<_> = config
+        .groups
+        .iter()
+        .filter(|(_, group)| group.resolvers.contains_key(resolver))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    names.sort();
+
+    println!("{}\n", format!("Doctor ({})", resolver).bold());
+
+    let mut count = 0;
+    for name in names {
+        let problems = check_group(config, name, resolver)?;
+        if problems.is_empty() {
+            println!("[{}] {}", Icon::InSync.glyph().blue(), name);
+        }
+        for problem in &problems {
+            println!("[{}] {}: {}", Icon::Warning.glyph().yellow(), name, problem);
+        }
+        count += problems.len();
+    }
+
+    let age = age_problems(config);
+    if !age.is_empty() {
+        println!();
+    }
+    for problem in &age {
+        println!("[{}] {}", Icon::Warning.glyph().yellow(), problem);
+    }
+    count += age.len();
+
+    let candidates = consolidation_candidates(config);
+    if !candidates.is_empty() {
+        println!();
+    }
+    for (a, b) in &candidates {
+        println!(
+            "[{}] Resolvers '{}' and '{}' expand to the same paths in every group; \
+             consider keeping one and adding `resolver_aliases: {{ {}: {} }}`",
+            Icon::Warning.glyph().yellow(),
+            a,
+            b,
+            b,
+            a
+        );
+    }
+
+    let repo = std::env::current_dir()?;
+    let undeclared = undeclared_groups(config, &repo)?;
+    if !undeclared.is_empty() {
+        println!();
+    }
+    for name in &undeclared {
+        println!(
+            "[{}] Directory '{}' looks like a group but isn't in doot.yaml, so it never syncs",
+            Icon::Warning.glyph().yellow(),
+            name
+        );
+    }
+    if !undeclared.is_empty() && !yes && std::io::stdin().is_terminal() {
+        offer_registration(config_path, resolver, &undeclared)?;
+    }
+
+    if count > 0 {
+        anyhow::bail!("{} problem(s) found", count);
+    }
+
+    println!("\nNo problems found.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_group_directories_missing_from_the_config() {
+        let repo = crate::testing::TempDir::new("doctor");
+        for dir in ["bash", "tmux", "zsh", "docs", ".git"] {
+            std::fs::create_dir_all(repo.join(dir)).unwrap();
+        }
+        std::fs::write(repo.join("bash/.bashrc"), "").unwrap();
+        std::fs::write(repo.join("tmux/.tmux.conf"), "").unwrap();
+        std::fs::write(repo.join("zsh/.dootignore"), "").unwrap();
+        std::fs::write(repo.join("docs/notes.md"), "").unwrap();
+        let config = Config::parse("version: v1\ngroups:\n  bash:\n    nux: \"~\"\n").unwrap();
+
+        assert_eq!(undeclared_groups(&config, &repo).unwrap(), ["tmux", "zsh"]);
+    }
+
+    #[test]
+    fn reports_unreadable_identity_and_bad_recipients() {
+        let config = Config::parse(
+            "version: v1\ngroups: {}\nage:\n  identity: /nonexistent/doot/key.txt\n  recipients:\n    - age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p\n    - ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI\n    - not-a-key\n",
+        )
+        .unwrap();
+
+        let problems = age_problems(&config);
+        assert!(problems[0].contains("/nonexistent/doot/key.txt can't be read"));
+        assert_eq!(
+            problems[1],
+            "age.recipients: not an age or SSH key: not-a-key"
+        );
+        assert!(problems[2..].iter().all(|p| p.contains("`age` not found")));
+
+        let config = Config::parse(
+            "version: v1\ngroups:\n  ssh:\n    nux: \"~/.ssh\"\n    sensitive: true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            age_problems(&config),
+            ["Group 'ssh' is sensitive, but age.recipients isn't set, so runs that back up its files fail"]
+        );
+    }
+}
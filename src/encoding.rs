@@ -0,0 +1,116 @@
+//! Decoding of text files for display, for configs written in encodings
+//! other than UTF-8 (common for files produced on Windows).
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        })
+    }
+}
+
+/// Guesses the encoding of `content`: by its byte order mark, by the NUL
+/// bytes ASCII text has every other byte in UTF-16, or else UTF-8 if it is
+/// valid and Latin-1 if not.
+pub fn detect(content: &[u8]) -> Encoding {
+    match content {
+        [0xEF, 0xBB, 0xBF, ..] => return Encoding::Utf8Bom,
+        [0xFF, 0xFE, ..] => return Encoding::Utf16Le,
+        [0xFE, 0xFF, ..] => return Encoding::Utf16Be,
+        _ => {}
+    }
+    if std::str::from_utf8(content).is_ok() && !content.contains(&0) {
+        return Encoding::Utf8;
+    }
+    if content.len().is_multiple_of(2) {
+        let nuls = |offset: usize| {
+            content
+                .iter()
+                .skip(offset)
+                .step_by(2)
+                .filter(|b| **b == 0)
+                .count()
+        };
+        let (even, odd) = (nuls(0), nuls(1));
+        let pairs = content.len() / 2;
+        // Mostly ASCII text: one byte of nearly every pair is NUL.
+        if odd * 10 >= pairs * 8 && even * 10 < pairs {
+            return Encoding::Utf16Le;
+        }
+        if even * 10 >= pairs * 8 && odd * 10 < pairs {
+            return Encoding::Utf16Be;
+        }
+    }
+    match std::str::from_utf8(content) {
+        Ok(_) => Encoding::Utf8,
+        Err(_) => Encoding::Latin1,
+    }
+}
+
+/// Decodes `content` as text, with the encoding it was detected in.
+/// Unpaired UTF-16 surrogates are replaced.
+pub fn decode(content: &[u8]) -> (String, Encoding) {
+    let encoding = detect(content);
+    let utf16 = |content: &[u8], unit: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = content
+            .chunks_exact(2)
+            .map(|pair| unit([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(content).into_owned(),
+        Encoding::Utf8Bom => String::from_utf8_lossy(&content[3..]).into_owned(),
+        Encoding::Utf16Le => utf16(
+            content.strip_prefix(&[0xFF, 0xFE]).unwrap_or(content),
+            u16::from_le_bytes,
+        ),
+        Encoding::Utf16Be => utf16(
+            content.strip_prefix(&[0xFE, 0xFF]).unwrap_or(content),
+            u16::from_be_bytes,
+        ),
+        Encoding::Latin1 => content.iter().map(|b| char::from(*b)).collect(),
+    };
+    (text, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_windows_encodings() {
+        let utf16le: Vec<u8> = "[core]\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(
+            decode(&utf16le),
+            ("[core]\r\n".to_string(), Encoding::Utf16Le)
+        );
+        let mut bom = vec![0xFE, 0xFF];
+        bom.extend("é".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(decode(&bom), ("é".to_string(), Encoding::Utf16Be));
+        assert_eq!(decode(b"caf\xe9"), ("café".to_string(), Encoding::Latin1));
+        assert_eq!(decode("café".as_bytes()).1, Encoding::Utf8);
+        assert_eq!(
+            decode(b"\xEF\xBB\xBFa"),
+            ("a".to_string(), Encoding::Utf8Bom)
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+
+const HEADER: &str = "# Generated by doot from doot.yaml. Do not edit by hand.\n";
+
+/// Renders variables as a `KEY=value` env file, in the format read by
+/// `environment.d` and most `.env` loaders.
+pub fn render(variables: &BTreeMap<String, String>) -> Vec<u8> {
+    let mut content = String::from(HEADER);
+    for (key, value) in variables {
+        content.push_str(&format!("{key}={value}\n"));
+    }
+    content.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_sorted_variables() {
+        let variables = BTreeMap::from([
+            ("PAGER".to_string(), "less".to_string()),
+            ("EDITOR".to_string(), "nvim".to_string()),
+        ]);
+
+        let content = String::from_utf8(render(&variables)).unwrap();
+        assert_eq!(content, format!("{HEADER}EDITOR=nvim\nPAGER=less\n"));
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+
+/// Errors at module boundaries, grouped into categories with stable codes so
+/// crash reports and machine-readable output can tell them apart. They convert
+/// into `anyhow::Error`, which renders them as before.
+#[derive(Debug, thiserror::Error)]
+pub enum DootError {
+    /// doot.yaml is invalid or refers to something it doesn't define.
+    #[error("{0}")]
+    Config(String),
+    #[error("Failed to parse {}", path.display())]
+    ConfigParse {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    /// A resolver is missing or doesn't expand to a path.
+    #[error("{0}")]
+    Resolver(String),
+    /// Reading or writing a file failed.
+    #[error("Failed to {action}: {}", path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The plan can't be carried out as computed.
+    #[error("{0}")]
+    Conflict(String),
+    /// Syncing a file failed; the cause is the underlying error.
+    #[error("Failed to {action} {}", path.display())]
+    Execution { action: &'static str, path: PathBuf },
+    /// An external command, such as `docker exec`, didn't finish in time.
+    #[error("{0}")]
+    Timeout(String),
+    /// A group's hook failed or exited with a non-zero status.
+    #[error("{0}")]
+    Hook(String),
+    /// Something needs the network, but `--offline` is set.
+    #[error("{0}")]
+    Offline(String),
+    /// Ctrl-C stopped the run between two files.
+    #[error("Interrupted after {applied} of {total} changes")]
+    Interrupted { applied: usize, total: usize },
+}
+
+impl DootError {
+    pub fn io(action: &'static str, path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        DootError::Io {
+            action,
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Machine-readable category of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DootError::Config(_) | DootError::ConfigParse { .. } => "config",
+            DootError::Resolver(_) => "resolver",
+            DootError::Io { .. } => "io",
+            DootError::Conflict(_) => "conflict",
+            DootError::Execution { .. } => "execution",
+            DootError::Timeout(_) => "timeout",
+            DootError::Hook(_) => "hook",
+            DootError::Offline(_) => "offline",
+            DootError::Interrupted { .. } => "interrupted",
+        }
+    }
+}
+
+/// Code of the outermost `DootError` in `error`, or `other` if it has none.
+pub fn code(error: &anyhow::Error) -> &'static str {
+    error
+        .downcast_ref::<DootError>()
+        .or_else(|| {
+            error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<DootError>())
+        })
+        .map_or("other", DootError::code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_finds_categories_in_the_chain() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        let error = anyhow::Error::from(DootError::io("read", "/home/.bashrc", io));
+        assert_eq!(code(&error), "io");
+        assert_eq!(error.to_string(), "Failed to read: /home/.bashrc");
+
+        let error = error.context(DootError::Execution {
+            action: "export",
+            path: PathBuf::from(".bashrc"),
+        });
+        assert_eq!(code(&error), "execution");
+
+        let error = anyhow::Error::from(DootError::Config("Group 'x' not found".into()))
+            .context("Failed to load");
+        assert_eq!(code(&error), "config");
+        assert_eq!(code(&anyhow::anyhow!("plain")), "other");
+    }
+}
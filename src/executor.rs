@@ -1,27 +1,170 @@
-use crate::config::Mode;
-use crate::plan::{FileEntry, FileStatus, Plan};
-use crate::store::{LinkStore, Store};
+use crate::age;
+use crate::config::{DiffLimits, Mode};
+use crate::encoding::{self, Encoding};
+use crate::error::DootError;
+use crate::highlight::Highlighter;
+use crate::history::Journal;
+use crate::hooks;
+use crate::interrupt;
+use crate::messages::{self, text, Message};
+use crate::plan::{FileEntry, FileStatus, GroupPlan, Hook, Plan};
+use crate::progress::Progress;
+use crate::store::{self, Store};
+use crate::style::{self, Icon};
+use crate::transaction::Transaction;
 use anyhow::Result;
 use colored::Colorize;
 use similar::{ChangeTag, TextDiff};
+use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::io::{self, Write};
-use syntect::easy::HighlightLines;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
-use syntect::util::as_24_bit_terminal_escaped;
+use std::path::{Path, PathBuf};
 
 fn apply_diff_tint(highlighted: &str, tint: &str) -> String {
     highlighted.replace("\x1b[0m", &format!("\x1b[0m{}", tint)) + "\x1b[0m"
 }
 
+/// Name of `entry` on the side of the diff at `path`: its repo name if
+/// `path` is in the repo, otherwise its name at the destination.
+fn diff_label<'e>(entry: &'e FileEntry, path: &Path) -> &'e Path {
+    match &entry.repo_path {
+        Some(repo_path) if path.ends_with(repo_path) => repo_path,
+        _ => &entry.relative_path,
+    }
+}
+
+/// Lists the changes a run stopped with Ctrl-C didn't get to.
+fn print_not_applied(remaining: &[(&GroupPlan, &FileEntry)], applied: usize, total: usize) {
+    println!(
+        "\n{}",
+        messages::format(Message::Interrupted, &[&applied, &total]).yellow()
+    );
+    println!("  {}:", text(Message::NotApplied));
+    for (group, entry) in remaining {
+        println!("    {}/{}", group.group_name, entry.relative_path.display());
+    }
+}
+
+/// Changes a run made and failed to make, printed as its last line on stderr
+/// for scripts wrapping doot.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub created: usize,
+    pub overwritten: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+impl Tally {
+    fn add(&mut self, status: &FileStatus) {
+        match status {
+            FileStatus::Create => self.created += 1,
+            FileStatus::Overwrite | FileStatus::Conflict => self.overwritten += 1,
+            FileStatus::Remove => self.removed += 1,
+            FileStatus::Same => {}
+        }
+    }
+}
+
+impl std::fmt::Display for Tally {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "doot: {} created, {} overwritten, {} removed, {} failed",
+            self.created, self.overwritten, self.removed, self.failed
+        )
+    }
+}
+
 pub struct Executor<'a> {
     store: &'a dyn Store,
     mode: Mode,
+    journal: Option<&'a Journal>,
+    diff_limits: DiffLimits,
+    highlight: BTreeMap<String, String>,
+    syntax_dir: Option<PathBuf>,
+    quiet: bool,
+    show_paths: bool,
+    dry_run: bool,
+    force: bool,
+    tally: Cell<Tally>,
 }
 
 impl<'a> Executor<'a> {
     pub fn new(store: &'a dyn Store, mode: Mode) -> Self {
-        Self { store, mode }
+        Self {
+            store,
+            mode,
+            journal: None,
+            diff_limits: DiffLimits::default(),
+            highlight: BTreeMap::new(),
+            syntax_dir: None,
+            quiet: false,
+            show_paths: false,
+            dry_run: false,
+            force: false,
+            tally: Cell::new(Tally::default()),
+        }
+    }
+
+    /// Records every change in `journal` before it is made.
+    pub fn with_journal(mut self, journal: &'a Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Skips diffs of files too large to highlight quickly.
+    pub fn with_diff_limits(mut self, diff_limits: DiffLimits) -> Self {
+        self.diff_limits = diff_limits;
+        self
+    }
+
+    /// Highlights diffs with the syntaxes named in `highlight` and extra
+    /// syntax definitions from `syntax_dir`.
+    pub fn with_highlighting(
+        mut self,
+        highlight: BTreeMap<String, String>,
+        syntax_dir: Option<PathBuf>,
+    ) -> Self {
+        self.highlight = highlight;
+        self.syntax_dir = syntax_dir;
+        self
+    }
+
+    /// Prints the full destination of each file in the plan and the full
+    /// paths in diff headers (`--show-paths`).
+    pub fn with_paths(mut self, show_paths: bool) -> Self {
+        self.show_paths = show_paths;
+        self
+    }
+
+    /// Runs without prompting and reports that nothing was changed; the store
+    /// is expected to be a `RecordingStore`.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Overwrites files in conflict instead of refusing to run (`--force`).
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Label of `path` in a diff header: the full path with `--show-paths`,
+    /// otherwise its name inside the group.
+    fn header_path(&self, group_name: &str, entry: &FileEntry, path: &Path) -> String {
+        if self.show_paths {
+            path.display().to_string()
+        } else {
+            format!("{}/{}", group_name, diff_label(entry, path).display())
+        }
+    }
+
+    /// Doesn't print the files `execute` changes.
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
     }
 
     pub fn display_plan(&self, plan: &Plan, operation: &str) {
@@ -33,24 +176,76 @@ impl<'a> Executor<'a> {
         println!("\n{}:\n", operation);
 
         for group in &plan.groups {
+            if let Some(reason) = &group.skipped {
+                println!(
+                    "  {}: {}\n",
+                    group.group_name.dimmed(),
+                    format!("{} ({})", text(Message::SkippedGroup), reason).dimmed()
+                );
+                continue;
+            }
+
             println!("  {}:", group.group_name.bold());
 
             if group.entries.is_empty() {
-                println!("    {}", "(no files)".dimmed());
+                println!("    {}", text(Message::NoFiles).dimmed());
             } else {
                 for entry in &group.entries {
                     let (icon, label) = match entry.status {
-                        FileStatus::Same => ("✓".blue(), "same".blue()),
-                        FileStatus::Create => ("+".green(), "create".green()),
-                        FileStatus::Overwrite => ("~".yellow(), "overwrite".yellow()),
+                        FileStatus::Same if entry.create_only => (
+                            Icon::InSync.glyph().blue(),
+                            text(Message::SameUnmanaged).blue(),
+                        ),
+                        FileStatus::Same => {
+                            (Icon::InSync.glyph().blue(), text(Message::Same).blue())
+                        }
+                        FileStatus::Create => {
+                            (Icon::New.glyph().green(), text(Message::Create).green())
+                        }
+                        FileStatus::Overwrite => (
+                            Icon::Modified.glyph().yellow(),
+                            text(Message::Overwrite).yellow(),
+                        ),
+                        FileStatus::Remove => {
+                            (Icon::Removed.glyph().red(), text(Message::Remove).red())
+                        }
+                        FileStatus::Conflict => {
+                            (Icon::Warning.glyph().red(), text(Message::Conflict).red())
+                        }
                     };
+                    // Changes that go through sudo or age are called out.
+                    let mut label = label.to_string();
+                    if self.store.is_privileged(&entry.destination) {
+                        label = format!("{}, {}", label, "sudo".magenta().bold());
+                    }
+                    if entry.is_encrypted() {
+                        label = format!("{}, {}", label, "encrypted".magenta());
+                    }
 
-                    println!(
-                        "    [{}] {} ({})",
-                        icon,
-                        entry.relative_path.display(),
-                        label
-                    );
+                    if self.show_paths {
+                        println!(
+                            "    [{}] {} {} {} ({})",
+                            icon,
+                            entry.relative_path.display(),
+                            style::arrow(),
+                            entry.destination.display(),
+                            label
+                        );
+                    } else {
+                        println!(
+                            "    [{}] {} ({})",
+                            icon,
+                            entry.relative_path.display(),
+                            label
+                        );
+                    }
+                    if let Some(note) = &entry.note {
+                        println!(
+                            "        [{}] {}",
+                            Icon::Warning.glyph().yellow(),
+                            note.yellow()
+                        );
+                    }
                 }
             }
             println!();
@@ -59,88 +254,126 @@ impl<'a> Executor<'a> {
         let same = plan.total_count_by_status(FileStatus::Same);
         let create = plan.total_count_by_status(FileStatus::Create);
         let overwrite = plan.total_count_by_status(FileStatus::Overwrite);
+        let remove = plan.total_count_by_status(FileStatus::Remove);
+        let conflict = plan.total_count_by_status(FileStatus::Conflict);
 
-        println!(
-            "Summary: {} same, {} to create, {} to overwrite",
-            same, create, overwrite
-        );
+        let mut summary = messages::format(Message::Summary, &[&same, &create, &overwrite]);
+        if remove > 0 {
+            summary.push_str(&messages::format(Message::SummaryRemove, &[&remove]));
+        }
+        if conflict > 0 {
+            summary.push_str(&messages::format(Message::SummaryConflict, &[&conflict]));
+        }
+        println!("{}", summary);
     }
 
     pub fn confirm(&self, plan: &Plan) -> Result<bool> {
         loop {
-            print!("\nProceed? [y/N/d] ");
+            print!(
+                "\n{} {} ",
+                text(Message::Proceed),
+                text(Message::ProceedChoices)
+            );
             io::stdout().flush()?;
 
             let mut input = String::new();
             io::stdin().read_line(&mut input)?;
 
-            match input.trim().to_ascii_lowercase().as_str() {
-                "y" => return Ok(true),
+            match input.trim().to_lowercase().as_str() {
+                answer if messages::is_yes(answer) => return Ok(true),
                 "n" | "" => return Ok(false),
                 "d" => self.show_diffs(plan)?,
-                _ => println!(
-                    "Invalid option. Use 'y' to proceed, 'n' to abort, or 'd' to show diffs."
-                ),
+                _ => println!("{}", text(Message::InvalidOption)),
             }
         }
     }
 
-    fn show_diffs(&self, plan: &Plan) -> Result<()> {
+    pub fn show_diffs(&self, plan: &Plan) -> Result<()> {
+        let highlighter = Highlighter::new(&self.highlight, self.syntax_dir.as_deref())?;
         println!();
         for group in &plan.groups {
             for entry in &group.entries {
                 if entry.status == FileStatus::Same {
                     continue;
                 }
-                self.show_entry_diff(entry, &group.group_name)?;
+                self.show_entry_diff(entry, &group.group_name, &highlighter)?;
             }
         }
         Ok(())
     }
 
-    fn show_entry_diff(&self, entry: &FileEntry, group_name: &str) -> Result<()> {
-        let old_content = if self.store.exists(&entry.destination) {
-            String::from_utf8_lossy(&self.store.read(&entry.destination)?).into_owned()
+    fn show_entry_diff(
+        &self,
+        entry: &FileEntry,
+        group_name: &str,
+        highlighter: &Highlighter,
+    ) -> Result<()> {
+        // Encrypted files are compared as plaintext, decrypted in memory.
+        let encrypted = age::is_encrypted(&entry.destination);
+        let old_bytes = if !self.store.exists(&entry.destination) {
+            Vec::new()
+        } else if encrypted {
+            age::decrypt(&self.store.read(&entry.destination)?)?
         } else {
-            String::new()
+            self.store.read(&entry.destination)?
         };
 
-        let new_content = String::from_utf8_lossy(&self.store.read(&entry.source)?).into_owned();
+        let new_bytes = if entry.status == FileStatus::Remove {
+            Vec::new()
+        } else if encrypted {
+            self.store.read(&entry.source)?
+        } else {
+            entry.source_content(self.store)?
+        };
+
+        // Files in other encodings are shown decoded, with the encoding noted.
+        let (old_content, old_encoding) = encoding::decode(&old_bytes);
+        let (new_content, new_encoding) = encoding::decode(&new_bytes);
+        let side = |name: &str, encoding: Encoding| match encoding {
+            Encoding::Utf8 => name.to_string(),
+            encoding => format!("{}, {}", name, encoding),
+        };
 
         println!(
             "{}",
             format!(
-                "--- {}/{} (destination)",
-                group_name,
-                entry.relative_path.display()
+                "--- {} ({})",
+                self.header_path(group_name, entry, &entry.destination),
+                side("destination", old_encoding)
             )
             .red()
         );
         println!(
             "{}",
             format!(
-                "+++ {}/{} (source)",
-                group_name,
-                entry.relative_path.display()
+                "+++ {} ({})",
+                self.header_path(group_name, entry, &entry.source),
+                side("source", new_encoding)
             )
             .green()
         );
-        println!("{}", "─".repeat(60).dimmed());
-
-        let ps = SyntaxSet::load_defaults_newlines();
-        let ts = ThemeSet::load_defaults();
-        let theme = &ts.themes["base16-ocean.dark"];
+        println!("{}", style::rule(60).dimmed());
+
+        if let Some(reason) = self.diff_limits.exceeded(&old_content, &new_content) {
+            println!(
+                "  Diff skipped ({}): {} lines, {} bytes {} {} lines, {} bytes",
+                reason,
+                old_content.lines().count(),
+                old_content.len(),
+                style::arrow(),
+                new_content.lines().count(),
+                new_content.len()
+            );
+            println!();
+            return Ok(());
+        }
 
-        let syntax = ps
-            .find_syntax_for_file(&entry.relative_path)
-            .ok()
-            .flatten()
-            .unwrap_or_else(|| ps.find_syntax_plain_text());
+        let syntax = highlighter.syntax_for(&entry.relative_path);
 
         let diff = TextDiff::from_lines(&old_content, &new_content);
         for (idx, group) in diff.grouped_ops(3).iter().enumerate() {
             if idx > 0 {
-                println!("{}", "───".dimmed());
+                println!("{}", style::rule(3).dimmed());
             }
             for op in group {
                 for change in diff.iter_changes(op) {
@@ -164,16 +397,25 @@ impl<'a> Executor<'a> {
                         ChangeTag::Equal => " ".dimmed(),
                     };
 
-                    print!("\x1b[48;2;40;40;50m{}\x1b[0m {} ", line_num.dimmed(), sign);
-
-                    let highlighted = self.highlight_line(&ps, syntax, theme, line);
-
-                    let styled = match tag {
-                        ChangeTag::Delete => apply_diff_tint(&highlighted, "\x1b[31m"),
-                        ChangeTag::Insert => apply_diff_tint(&highlighted, "\x1b[32m"),
-                        ChangeTag::Equal => highlighted,
-                    };
-                    print!("{}", styled);
+                    if style::is_ascii() {
+                        print!("{} {} {}", line_num, sign, line);
+                    } else {
+                        print!(
+                            "{}{}\x1b[0m {} ",
+                            highlighter.gutter(),
+                            line_num.dimmed(),
+                            sign
+                        );
+
+                        let highlighted = highlighter.highlight_line(syntax, line);
+
+                        let styled = match tag {
+                            ChangeTag::Delete => apply_diff_tint(&highlighted, "\x1b[31m"),
+                            ChangeTag::Insert => apply_diff_tint(&highlighted, "\x1b[32m"),
+                            ChangeTag::Equal => highlighted,
+                        };
+                        print!("{}", styled);
+                    }
                     if !line.ends_with('\n') {
                         println!();
                     }
@@ -184,81 +426,530 @@ impl<'a> Executor<'a> {
         Ok(())
     }
 
-    fn highlight_line(
-        &self,
-        ps: &SyntaxSet,
-        syntax: &syntect::parsing::SyntaxReference,
-        theme: &syntect::highlighting::Theme,
-        line: &str,
-    ) -> String {
-        let mut h = HighlightLines::new(syntax, theme);
-        match h.highlight_line(line, ps) {
-            Ok(ranges) => as_24_bit_terminal_escaped(&ranges, false),
-            Err(_) => line.to_string(),
-        }
+    /// What the last `execute` changed and failed to change.
+    pub fn tally(&self) -> Tally {
+        self.tally.get()
     }
 
     pub fn execute(&self, plan: &Plan) -> Result<()> {
-        for group in &plan.groups {
-            if !group.has_changes() {
+        if let Some(journal) = self.journal {
+            journal.check(plan)?;
+        }
+        let changes: Vec<_> = plan
+            .groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.status != FileStatus::Same)
+                    .map(move |entry| (group, entry))
+            })
+            .collect();
+
+        let transaction = Transaction::new();
+        let mut tally = Tally::default();
+        // Nothing stays changed after a rollback.
+        let rolled_back = Tally {
+            failed: changes.len(),
+            ..Tally::default()
+        };
+        let mut current: Option<&GroupPlan> = None;
+        // Where the current group's changes begin: its first change, the
+        // transaction and journal lengths and the tally. A group whose store
+        // times out is rolled back to there, so it is skipped as a whole.
+        let mut group_start = (0, 0, 0, tally);
+        // Groups whose store timed out or whose hook failed; the rest of the
+        // run goes on without them.
+        let mut timed_out: Vec<&str> = Vec::new();
+        let mut hook_failed: Vec<&str> = Vec::new();
+        let progress = Progress::new(changes.len(), !self.silent());
+        for (applied, (group, entry)) in changes.iter().enumerate() {
+            let name = group.group_name.as_str();
+            if timed_out.contains(&name) || hook_failed.contains(&name) {
+                tally.failed += 1;
+                self.tally.set(tally);
+                progress.advance();
                 continue;
             }
+            if interrupt::requested() {
+                progress.suspend(|| print_not_applied(&changes[applied..], applied, changes.len()));
+                if let Some(journal) = self.journal {
+                    journal.interrupt(self.store, &changes[applied..])?;
+                }
+                return Err(DootError::Interrupted {
+                    applied,
+                    total: changes.len(),
+                }
+                .into());
+            }
 
-            println!("  {}:", group.group_name);
-            for entry in &group.entries {
-                if entry.status == FileStatus::Same {
+            if current.is_none_or(|current| current.group_name != group.group_name) {
+                if let Some(previous) =
+                    current.filter(|g| !timed_out.contains(&g.group_name.as_str()))
+                {
+                    if !progress.suspend(|| self.run_hook(previous, &previous.post_hook)) {
+                        hook_failed.push(&previous.group_name);
+                    }
+                }
+                if !self.silent() {
+                    progress.println(format!("  {}:", group.group_name));
+                }
+                current = Some(group);
+                group_start = (
+                    applied,
+                    transaction.mark(),
+                    self.journal.map_or(0, Journal::mark),
+                    tally,
+                );
+                // Nothing of the group is written yet, so there is nothing to
+                // roll back when its pre-hook fails.
+                if !progress.suspend(|| self.run_hook(group, &group.pre_hook)) {
+                    hook_failed.push(name);
+                    tally.failed += 1;
+                    self.tally.set(tally);
+                    progress.advance();
                     continue;
                 }
-                self.execute_entry(entry)?;
+            }
+            progress.start(name, &entry.relative_path);
+            let result = transaction
+                .record(self.store, &entry.destination)
+                .and_then(|_| self.execute_entry(group, entry));
+            if let Err(error) = result {
+                let action = match entry.status {
+                    FileStatus::Create => "create",
+                    FileStatus::Remove => "remove",
+                    _ => "update",
+                };
+                if crate::error::code(&error) == "timeout" {
+                    progress.println(format!(
+                        "    {} {}",
+                        format!("Failed to {}:", action).red(),
+                        error
+                    ));
+                    timed_out.push(name);
+                    let (first, changes, recorded, before) = group_start;
+                    progress.suspend(|| self.roll_back_group(&transaction, changes, recorded));
+                    tally = before;
+                    tally.failed += applied - first + 1;
+                    self.tally.set(tally);
+                    progress.advance();
+                    continue;
+                }
+                let error = error.context(DootError::Execution {
+                    action,
+                    path: entry.destination.clone(),
+                });
+                self.tally.set(rolled_back);
+                return Err(progress.suspend(|| self.roll_back(&transaction, error)));
+            }
+            if let Some(owner) = &group.owner {
+                if let Err(error) = self.store.chown(&entry.destination, owner) {
+                    self.tally.set(rolled_back);
+                    return Err(progress.suspend(|| self.roll_back(&transaction, error)));
+                }
+            }
+            if !self.silent() {
+                let action = text(match entry.status {
+                    FileStatus::Create => Message::Created,
+                    FileStatus::Overwrite | FileStatus::Conflict => Message::Updated,
+                    FileStatus::Remove => Message::Removed,
+                    FileStatus::Same => Message::Skipped,
+                });
+                progress.println(format!("    {} {}", action, entry.relative_path.display()));
+            }
+            tally.add(&entry.status);
+            self.tally.set(tally);
+            progress.advance();
+        }
+        if let Some(last) = current.filter(|g| {
+            let name = g.group_name.as_str();
+            !timed_out.contains(&name) && !hook_failed.contains(&name)
+        }) {
+            if !progress.suspend(|| self.run_hook(last, &last.post_hook)) {
+                hook_failed.push(&last.group_name);
             }
         }
 
+        if !hook_failed.is_empty() {
+            return Err(
+                DootError::Hook(format!("Hooks failed for {}", hook_failed.join(", "))).into(),
+            );
+        }
+        if !timed_out.is_empty() {
+            return Err(
+                DootError::Timeout(format!("Timed out syncing {}", timed_out.join(", "))).into(),
+            );
+        }
         Ok(())
     }
 
-    fn execute_entry(&self, entry: &FileEntry) -> Result<()> {
-        match self.mode {
-            Mode::File => {
-                let content = self.store.read(&entry.source)?;
-                self.store.write(&entry.destination, &content)?;
-            }
-            Mode::Link => {
-                LinkStore::create_symlink(&entry.source, &entry.destination)?;
+    /// Runs `hook` of `group`, if it has one, printing its output. Returns
+    /// whether it succeeded. A dry run only names the hook.
+    fn run_hook(&self, group: &GroupPlan, hook: &Option<Hook>) -> bool {
+        let Some(hook) = hook else {
+            return true;
+        };
+        if self.dry_run {
+            if !self.silent() {
+                println!("    Would run {}: {}", hook.name, hook.command);
             }
+            return true;
         }
 
-        let action = match entry.status {
-            FileStatus::Create => "Created",
-            FileStatus::Overwrite => "Updated",
-            FileStatus::Same => "Skipped",
+        let output = match hooks::run(hook, group.config_name()) {
+            Ok(output) => output,
+            Err(error) => {
+                eprintln!("    {} {:#}", "Hook failed:".red(), error);
+                return false;
+            }
         };
+        let succeeded = output.status.success();
+        if succeeded && self.silent() {
+            return true;
+        }
+        // Failures are reported even when quiet, on stderr.
+        let print = |line: String| match self.silent() {
+            true => eprintln!("{}", line),
+            false => println!("{}", line),
+        };
+        print(format!("    Ran {}: {}", hook.name, hook.command));
+        let text = [output.stdout, output.stderr].concat();
+        for line in String::from_utf8_lossy(&text).lines() {
+            print(format!("      {}", line.dimmed()));
+        }
+        if !succeeded {
+            let failure = format!(
+                "{} of group '{}' failed with {}",
+                hook.name, group.group_name, output.status
+            );
+            print(format!("    {}", failure.red()));
+        }
+        succeeded
+    }
+
+    /// Reverts the changes made before `error` stopped the run, so it
+    /// doesn't leave some files synced and others not.
+    fn roll_back(&self, transaction: &Transaction, error: anyhow::Error) -> anyhow::Error {
+        match transaction.roll_back(self.store) {
+            Ok(0) => error,
+            Ok(count) => {
+                if !self.silent() {
+                    println!(
+                        "    {}",
+                        format!(
+                            "Rolled back {} file(s) to how they were before the run",
+                            count
+                        )
+                        .yellow()
+                    );
+                }
+                if let Some(journal) = self.journal {
+                    journal.discard();
+                }
+                error
+            }
+            Err(rollback) => error.context(format!(
+                "Rolling back also failed, so some changes remain ({:#}); see `doot undo`",
+                rollback
+            )),
+        }
+    }
 
-        println!("    {} {}", action, entry.relative_path.display());
+    /// Rolls back the changes of a group that can't be finished, those
+    /// recorded after the first `changes` of `transaction` and `recorded` of
+    /// the journal, and reports a failure to do so without stopping the run.
+    fn roll_back_group(&self, transaction: &Transaction, changes: usize, recorded: usize) {
+        match transaction.roll_back_from(self.store, changes) {
+            Ok(0) => {}
+            Ok(count) => {
+                if !self.silent() {
+                    println!(
+                        "    {}",
+                        format!("Rolled back {} file(s) of the group", count).yellow()
+                    );
+                }
+                if let Some(journal) = self.journal {
+                    journal.discard_from(recorded);
+                }
+            }
+            Err(error) => eprintln!(
+                "    {} {:#}; see `doot undo`",
+                "Rolling back the group failed, so some changes remain:".red(),
+                error
+            ),
+        }
+    }
+
+    fn execute_entry(&self, group: &GroupPlan, entry: &FileEntry) -> Result<()> {
+        if let Some(journal) = self.journal {
+            journal.record(self.store, group, entry)?;
+        }
+
+        let can_link = entry.generated.is_none()
+            && !store::is_pseudo_path(&entry.source)
+            && !store::is_pseudo_path(&entry.destination);
+
+        if entry.status != FileStatus::Remove && !store::is_pseudo_path(&entry.destination) {
+            for (dir, mode) in &entry.dir_modes {
+                if !self.store.exists(dir) {
+                    self.store.create_dir(dir, *mode)?;
+                }
+            }
+        }
+
+        match self.mode {
+            _ if entry.status == FileStatus::Remove => self.store.remove(&entry.destination)?,
+            Mode::Link if can_link => {
+                self.store.link(&entry.source, &entry.destination)?;
+            }
+            Mode::File | Mode::Link => {
+                let content = entry.source_content(self.store)?;
+                self.store.write(&entry.destination, &content)?;
+                if entry.hidden {
+                    self.store.hide(&entry.destination)?;
+                }
+                if entry.readonly && !store::is_pseudo_path(&entry.destination) {
+                    self.store.make_readonly(&entry.destination)?;
+                }
+            }
+        }
         Ok(())
     }
 
-    pub fn run(&self, plan: &Plan, operation: &str, skip_confirm: bool) -> Result<()> {
-        self.display_plan(plan, operation);
+    /// Whether progress is left out, with `quiet` or because the caller
+    /// prints a JSON report instead.
+    fn silent(&self) -> bool {
+        self.quiet || style::is_json()
+    }
+
+    /// Displays, confirms, and executes a plan. Returns whether it was applied.
+    pub fn run(&self, plan: &Plan, operation: &str, skip_confirm: bool) -> Result<bool> {
+        let json = style::is_json();
+        if !json {
+            self.display_plan(plan, operation);
+        }
 
         if !plan.has_changes() {
-            println!("\nNothing to do.");
-            return Ok(());
+            if !json {
+                println!("\n{}", text(Message::NothingToDo));
+            }
+            return Ok(false);
         }
 
-        let proceed = if skip_confirm {
+        let conflicts = plan.total_count_by_status(FileStatus::Conflict);
+        if conflicts > 0 && !self.force && !self.dry_run {
+            return Err(DootError::Conflict(format!(
+                "{} file(s) changed on both sides since the last sync; pass --force to overwrite them",
+                conflicts
+            ))
+            .into());
+        }
+
+        let backups = self.journal.and_then(|journal| journal.backup_dir());
+        crate::space::check(plan, self.mode, backups)?;
+
+        let proceed = if skip_confirm || self.dry_run {
             true
+        } else if json {
+            anyhow::bail!("--format json can't prompt; pass --yes or --dry-run");
         } else {
             self.confirm(plan)?
         };
 
-        if proceed {
-            println!("\nExecuting...\n");
+        if json {
+            let _interrupt = interrupt::catch();
             self.execute(plan)?;
-            println!("\nDone!");
+        } else if proceed {
+            println!("\n{}\n", text(Message::Executing));
+            let _interrupt = interrupt::catch();
+            self.execute(plan)?;
+            let done = if self.dry_run {
+                Message::DryRun
+            } else {
+                Message::Done
+            };
+            println!("\n{}", text(done));
         } else {
-            println!("\nAborted.");
+            println!("\n{}", text(Message::Aborted));
         }
 
-        Ok(())
+        Ok(proceed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HashAlgorithm;
+    use crate::store::{FileStore, Mutation, RecordingStore};
+    use crate::testing::entry;
+
+    #[test]
+    fn execute_records_changes_in_recording_store() {
+        let files = FileStore::new(HashAlgorithm::default());
+        let store = RecordingStore::new(&files);
+        let dir = crate::testing::TempDir::new("executor");
+        let home = dir.join("home");
+        let destination = home.join(".ssh/config");
+
+        let mut plan = Plan::new();
+        plan.add_group(
+            "ssh".to_string(),
+            vec![FileEntry {
+                generated: Some(b"Host *\n".to_vec()),
+                readonly: true,
+                dir_modes: vec![(home.join(".ssh"), 0o700)],
+                ..entry(
+                    ".ssh/config",
+                    "ssh/.ssh/config",
+                    &destination,
+                    FileStatus::Create,
+                )
+            }],
+        );
+        let executor = Executor::new(&store, Mode::File).quiet();
+        executor.execute(&plan).unwrap();
+        assert_eq!(
+            executor.tally().to_string(),
+            "doot: 1 created, 0 overwritten, 0 removed, 0 failed"
+        );
+
+        assert!(!home.exists());
+        assert_eq!(
+            store.mutations(),
+            [
+                Mutation::CreateDir {
+                    path: home.join(".ssh"),
+                    mode: 0o700
+                },
+                Mutation::Write {
+                    path: destination.clone(),
+                    content: b"Host *\n".to_vec()
+                },
+                Mutation::MakeReadonly { path: destination },
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_hook_skips_its_group() {
+        let store = crate::testing::MemoryStore::new();
+        let mut plan = Plan::new();
+        for name in ["bash", "tmux"] {
+            let mut generated = entry(".rc", "", format!("/home/me/{}", name), FileStatus::Create);
+            generated.generated = Some(b"set -o vi\n".to_vec());
+            plan.add_group(name.to_string(), vec![generated]);
+        }
+        plan.groups[0].pre_hook = Some(Hook {
+            name: "pre_export",
+            command: "exit 3".to_string(),
+        });
+        plan.groups[1].post_hook = Some(Hook {
+            name: "post_export",
+            command: "test \"$DOOT_GROUP\" = tmux".to_string(),
+        });
+
+        let error = Executor::new(&store, Mode::File)
+            .quiet()
+            .execute(&plan)
+            .unwrap_err();
+        assert_eq!(crate::error::code(&error), "hook");
+        assert_eq!(error.to_string(), "Hooks failed for bash");
+        assert!(store.file("/home/me/bash").is_none());
+        assert!(store.file("/home/me/tmux").is_some());
+    }
+
+    /// Times out writing `path`, like a container that stopped responding.
+    struct TimingOut {
+        inner: crate::testing::MemoryStore,
+        path: PathBuf,
+    }
+
+    impl Store for TimingOut {
+        fn name(&self) -> &'static str {
+            "timing out"
+        }
+
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            self.inner.read(path)
+        }
+
+        fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+            if path == self.path {
+                return Err(
+                    DootError::Timeout(format!("Timed out writing {}", path.display())).into(),
+                );
+            }
+            self.inner.write(path, content)
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.inner.exists(path)
+        }
+
+        fn remove(&self, path: &Path) -> Result<()> {
+            self.inner.remove(path)
+        }
+
+        fn create_dir(&self, path: &Path, mode: u32) -> Result<()> {
+            self.inner.create_dir(path, mode)
+        }
+
+        fn make_readonly(&self, path: &Path) -> Result<()> {
+            self.inner.make_readonly(path)
+        }
+    }
+
+    #[test]
+    fn timed_out_group_is_rolled_back() {
+        let store = TimingOut {
+            inner: crate::testing::MemoryStore::new()
+                .with_file("/box/.profile", b"old\n")
+                .with_file("/repo/sh/.profile", b"new\n")
+                .with_file("/repo/sh/.bashrc", b"set -o vi\n")
+                .with_file("/repo/git/.gitconfig", b"[user]\n"),
+            path: PathBuf::from("/box/.bashrc"),
+        };
+        let mut plan = Plan::new();
+        plan.add_group(
+            "sh".to_string(),
+            vec![
+                entry(
+                    ".profile",
+                    "/repo/sh/.profile",
+                    "/box/.profile",
+                    FileStatus::Overwrite,
+                ),
+                entry(
+                    ".bashrc",
+                    "/repo/sh/.bashrc",
+                    "/box/.bashrc",
+                    FileStatus::Create,
+                ),
+            ],
+        );
+        plan.add_group(
+            "git".to_string(),
+            vec![entry(
+                ".gitconfig",
+                "/repo/git/.gitconfig",
+                "/home/.gitconfig",
+                FileStatus::Create,
+            )],
+        );
+
+        let executor = Executor::new(&store, Mode::File).quiet();
+        let error = executor.execute(&plan).unwrap_err();
+        assert_eq!(crate::error::code(&error), "timeout");
+        assert_eq!(store.inner.file("/box/.profile").unwrap(), b"old\n");
+        assert!(store.inner.file("/box/.bashrc").is_none());
+        assert_eq!(store.inner.file("/home/.gitconfig").unwrap(), b"[user]\n");
+        assert_eq!(
+            executor.tally().to_string(),
+            "doot: 1 created, 0 overwritten, 0 removed, 2 failed"
+        );
     }
 }
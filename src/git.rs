@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Returns the files under `dir` with uncommitted changes (modified, staged,
+/// deleted or untracked), relative to `dir`.
+///
+/// Returns `None` if `dir` is not inside a git work tree or `git` is not
+/// available on this machine.
+pub fn uncommitted_files(dir: &Path) -> Option<Vec<String>> {
+    let tracked = git(dir, &["diff", "HEAD", "--name-only", "--relative", "-z"])?;
+    let untracked = git(dir, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+
+    let mut files: Vec<String> = tracked
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|file| !file.is_empty())
+        .map(String::from)
+        .collect();
+    files.sort();
+    files.dedup();
+    Some(files)
+}
+
+/// Stages `files` of the repo at `repo` and commits them, and nothing else
+/// that was staged, with `message`.
+pub fn commit(repo: &Path, files: &[PathBuf], message: &str) -> Result<()> {
+    run(repo, &["add", "--all", "--"], files)?;
+    run(
+        repo,
+        &["commit", "--quiet", "--message", message, "--"],
+        files,
+    )
+}
+
+/// Clones the repo at `url` into `dir`, showing git's progress.
+pub fn clone(url: &str, dir: &Path) -> Result<()> {
+    let status = crate::process::command("git")
+        .args(["clone", "--", url])
+        .arg(dir)
+        // The config with `process_env` isn't there before the clone, so
+        // pass the SSH agent on for SSH URLs.
+        .envs(std::env::var_os("SSH_AUTH_SOCK").map(|sock| ("SSH_AUTH_SOCK", sock)))
+        .status()
+        .context("Failed to run git")?;
+    anyhow::ensure!(status.success(), "Failed to clone {}", url);
+    Ok(())
+}
+
+/// Directory a clone of `url` goes to by default: the repo's name, as
+/// `git clone` picks it.
+pub fn default_dir(url: &str) -> PathBuf {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(url);
+    PathBuf::from(name.strip_suffix(".git").unwrap_or(name))
+}
+
+fn run(repo: &Path, args: &[&str], files: &[PathBuf]) -> Result<()> {
+    let output = crate::process::command("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .args(files)
+        .output()
+        .context("Failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`git {}` failed: {}",
+        args[0],
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = crate::process::command("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_dir_is_repo_name() {
+        assert_eq!(
+            default_dir("https://github.com/me/dotfiles.git"),
+            PathBuf::from("dotfiles")
+        );
+        assert_eq!(default_dir("git@github.com:me/dots"), PathBuf::from("dots"));
+        assert_eq!(default_dir("host:dots.git"), PathBuf::from("dots"));
+        assert_eq!(default_dir("/srv/git/dotfiles/"), PathBuf::from("dotfiles"));
+    }
+}
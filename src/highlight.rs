@@ -0,0 +1,126 @@
+//! Syntax highlighting for the interactive diff.
+
+use crate::patterns::PatternMatcher;
+use crate::style::ColorDepth;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    depth: ColorDepth,
+    /// Syntax names keyed by exact file name
+    names: BTreeMap<String, String>,
+    /// Syntax names keyed by pattern, used when no file name matches
+    patterns: PatternMatcher<String>,
+}
+
+impl Highlighter {
+    /// Loads the default syntaxes plus any `.sublime-syntax` files in
+    /// `extra_dir`. `overrides` maps file names or patterns to syntax names.
+    pub fn new(overrides: &BTreeMap<String, String>, extra_dir: Option<&Path>) -> Result<Self> {
+        let syntaxes = match extra_dir {
+            Some(dir) => {
+                let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+                builder
+                    .add_from_folder(dir, true)
+                    .with_context(|| format!("Failed to load syntaxes: {}", dir.display()))?;
+                builder.build()
+            }
+            None => SyntaxSet::load_defaults_newlines(),
+        };
+
+        let is_name = |pattern: &str| !pattern.contains(['*', '?', '[', '/']);
+        let names = overrides
+            .iter()
+            .filter(|(pattern, _)| is_name(pattern))
+            .map(|(name, syntax)| (name.clone(), syntax.clone()))
+            .collect();
+        let patterns = overrides
+            .iter()
+            .filter(|(pattern, _)| !is_name(pattern))
+            .map(|(pattern, syntax)| (pattern.clone(), syntax.clone()))
+            .collect();
+
+        Ok(Self {
+            syntaxes,
+            theme: ThemeSet::load_defaults().themes["base16-ocean.dark"].clone(),
+            depth: ColorDepth::detect(),
+            names,
+            patterns: PatternMatcher::new(&patterns)?,
+        })
+    }
+
+    /// Returns the syntax for `relative`: an override if one matches and names
+    /// a known syntax, otherwise the one detected from the file.
+    pub fn syntax_for(&self, relative: &Path) -> &SyntaxReference {
+        let file_name = relative.file_name().unwrap_or_default().to_string_lossy();
+        let overridden = self
+            .names
+            .get(file_name.as_ref())
+            .or_else(|| self.patterns.find(relative))
+            .and_then(|name| self.find_syntax(name));
+
+        overridden
+            .or_else(|| self.syntaxes.find_syntax_for_file(relative).ok().flatten())
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+
+    /// Finds a syntax by name (case-insensitively) or by file extension.
+    fn find_syntax(&self, name: &str) -> Option<&SyntaxReference> {
+        self.syntaxes
+            .syntaxes()
+            .iter()
+            .find(|syntax| syntax.name.eq_ignore_ascii_case(name))
+            .or_else(|| self.syntaxes.find_syntax_by_extension(name))
+    }
+
+    /// Escape setting the background of the line number gutter.
+    pub fn gutter(&self) -> String {
+        self.depth.bg(40, 40, 50)
+    }
+
+    pub fn highlight_line(&self, syntax: &SyntaxReference, line: &str) -> String {
+        let mut h = HighlightLines::new(syntax, &self.theme);
+        let Ok(ranges) = h.highlight_line(line, &self.syntaxes) else {
+            return line.to_string();
+        };
+
+        let mut out = String::new();
+        for (style, text) in ranges {
+            let c = style.foreground;
+            out.push_str(&self.depth.fg(c.r, c.g, c.b));
+            out.push_str(text);
+        }
+        out + "\x1b[0m"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_win_over_patterns() {
+        let overrides = BTreeMap::from([
+            ("*.conf".to_string(), "Python".to_string()),
+            ("kitty.conf".to_string(), "rb".to_string()),
+            ("*.bogus".to_string(), "No Such Syntax".to_string()),
+        ]);
+        let highlighter = Highlighter::new(&overrides, None).unwrap();
+
+        assert_eq!(highlighter.syntax_for(Path::new("a/b.conf")).name, "Python");
+        assert_eq!(
+            highlighter.syntax_for(Path::new("kitty/kitty.conf")).name,
+            "Ruby"
+        );
+        assert_eq!(
+            highlighter.syntax_for(Path::new("x.bogus")).name,
+            "Plain Text"
+        );
+    }
+}
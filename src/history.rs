@@ -0,0 +1,526 @@
+use crate::age;
+use crate::backup::{self, Backups};
+use crate::error::DootError;
+use crate::plan::{FileEntry, FileStatus, GroupPlan, Plan};
+use crate::snapshot::Snapshot;
+use crate::state;
+use crate::store::{hash_bytes, Store};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A file changed by a run, with the content it had before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub relative_path: PathBuf,
+    pub destination: PathBuf,
+    pub created: bool,
+    /// Saved previous content, relative to the run directory unless it was
+    /// kept elsewhere; none if backups were off
+    pub backup: Option<String>,
+}
+
+/// A change an interrupted run didn't make, kept for `doot resume`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEntry {
+    pub group: String,
+    /// User the file is handed to, if the run exported for one
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub relative_path: PathBuf,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub status: FileStatus,
+    /// Hash of the content to write, checked again before resuming
+    pub hash: Option<String>,
+    /// Name of the saved generated content inside the run directory
+    pub generated: Option<String>,
+    #[serde(default)]
+    pub readonly: bool,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub dir_modes: Vec<(PathBuf, u32)>,
+}
+
+/// A recorded run, stored as `run.json` in its own directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Run {
+    pub id: String,
+    pub timestamp: u64,
+    pub operation: String,
+    pub tag: Option<String>,
+    pub entries: Vec<JournalEntry>,
+    #[serde(default)]
+    pub undone: bool,
+    /// Stopped with Ctrl-C before all changes were made
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Changes left to make when the run was interrupted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending: Vec<PendingEntry>,
+    /// The plan the run was started with
+    #[serde(default)]
+    pub plan: Option<Snapshot>,
+}
+
+/// Records the files a run changes and their previous contents, so the run
+/// can be listed with `doot history` and reverted with `doot undo`.
+pub struct Journal {
+    dir: PathBuf,
+    backups: Option<PathBuf>,
+    run: Mutex<Run>,
+}
+
+pub fn history_dir() -> Result<PathBuf> {
+    Ok(state::dir()?.join("history"))
+}
+
+impl Journal {
+    pub fn begin(operation: &str, tag: Option<String>, backups: &Backups) -> Result<Self> {
+        let timestamp = now();
+        let id = format!("{}-{}", timestamp, std::process::id());
+        let dir = history_dir()?.join(&id);
+        create_private_dir(&dir)?;
+
+        Ok(Self {
+            backups: backups.root(&dir, &id),
+            dir,
+            run: Mutex::new(Run {
+                id,
+                timestamp,
+                operation: operation.to_string(),
+                tag,
+                entries: Vec::new(),
+                undone: false,
+                interrupted: false,
+                pending: Vec::new(),
+                plan: None,
+            }),
+        })
+    }
+
+    /// Fails if `plan` would back up files of a sensitive group that can't be
+    /// encrypted, before anything is changed.
+    pub fn check(&self, plan: &Plan) -> Result<()> {
+        if self.backups.is_none() {
+            return Ok(());
+        }
+        for group in plan.groups.iter().filter(|g| g.skipped.is_none()) {
+            encrypts(group)?;
+        }
+        Ok(())
+    }
+
+    /// Saves the current content of `entry`'s destination before it is
+    /// changed, encrypted if `group` is sensitive.
+    pub fn record(&self, store: &dyn Store, group: &GroupPlan, entry: &FileEntry) -> Result<()> {
+        let mut run = self.run.lock().unwrap();
+        let created = entry.status == FileStatus::Create || !store.exists(&entry.destination);
+
+        let backup = match &self.backups {
+            Some(root) if !created => {
+                let encrypt = encrypts(group)?;
+                let content = store.read(&entry.destination)?;
+                let path = backup::save(root, &entry.destination, &content, encrypt)?;
+                let path = path.strip_prefix(&self.dir).unwrap_or(&path);
+                Some(path.to_string_lossy().into_owned())
+            }
+            _ => None,
+        };
+
+        run.entries.push(JournalEntry {
+            relative_path: entry.relative_path.clone(),
+            destination: entry.destination.clone(),
+            created,
+            backup,
+        });
+        Ok(())
+    }
+
+    /// How many changes were recorded so far, to discard later with
+    /// [`Journal::discard_from`].
+    pub fn mark(&self) -> usize {
+        self.run.lock().unwrap().entries.len()
+    }
+
+    /// Forgets the changes recorded so far, once they were rolled back.
+    pub fn discard(&self) {
+        self.discard_from(0);
+    }
+
+    /// Forgets the changes recorded after the first `start`, once they were
+    /// rolled back.
+    pub fn discard_from(&self, start: usize) {
+        self.run.lock().unwrap().entries.truncate(start);
+    }
+
+    /// Directory the run's backups go to, if it keeps any.
+    pub fn backup_dir(&self) -> Option<&Path> {
+        self.backups.as_deref()
+    }
+
+    /// Records the plan the run executes.
+    pub fn set_plan(&self, plan: Snapshot) {
+        self.run.lock().unwrap().plan = Some(plan);
+    }
+
+    /// Continues recording `run`, which was interrupted, for `doot resume`.
+    pub fn reopen(mut run: Run, backups: &Backups) -> Result<Self> {
+        let dir = history_dir()?.join(&run.id);
+        run.interrupted = false;
+        run.pending.clear();
+        Ok(Self {
+            backups: backups.root(&dir, &run.id),
+            dir,
+            run: Mutex::new(run),
+        })
+    }
+
+    /// Marks the run as interrupted, saving the changes it didn't make.
+    pub fn interrupt(
+        &self,
+        store: &dyn Store,
+        remaining: &[(&GroupPlan, &FileEntry)],
+    ) -> Result<()> {
+        let mut pending = Vec::new();
+        for (i, (group, entry)) in remaining.iter().enumerate() {
+            let generated = match &entry.generated {
+                // Kept out of the state directory rather than stored in
+                // plaintext; the run has to be started again instead.
+                Some(_) if group.sensitive && !age::has_recipients() => {
+                    eprintln!(
+                        "    Not kept for resume: {}/{} (sensitive, and age.recipients isn't set)",
+                        group.group_name,
+                        entry.relative_path.display()
+                    );
+                    continue;
+                }
+                Some(content) if group.sensitive => {
+                    let name = format!("pending-{}.{}", i, age::EXTENSION);
+                    write_private(&self.dir.join(&name), &age::encrypt(content)?)?;
+                    Some(name)
+                }
+                Some(content) => {
+                    let name = format!("pending-{}", i);
+                    write_private(&self.dir.join(&name), content)?;
+                    Some(name)
+                }
+                None => None,
+            };
+            // Without a hash, resume can't tell that a sensitive file changed.
+            let hash = match entry.status {
+                FileStatus::Remove => None,
+                _ if group.sensitive => None,
+                _ => Some(hash_bytes(
+                    store.hash_algorithm(),
+                    &entry.source_content(store)?,
+                )),
+            };
+            pending.push(PendingEntry {
+                group: group.group_name.clone(),
+                owner: group.owner.as_ref().map(|owner| owner.name.clone()),
+                relative_path: entry.relative_path.clone(),
+                source: entry.source.clone(),
+                destination: entry.destination.clone(),
+                status: entry.status.clone(),
+                hash,
+                generated,
+                readonly: entry.readonly,
+                hidden: entry.hidden,
+                dir_modes: entry.dir_modes.clone(),
+            });
+        }
+
+        let mut run = self.run.lock().unwrap();
+        run.interrupted = true;
+        run.pending = pending;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let run = self.run.into_inner().unwrap();
+        if run.entries.is_empty() && run.pending.is_empty() {
+            let _ = std::fs::remove_dir_all(&self.dir);
+            return Ok(());
+        }
+        save(&self.dir, &run)
+    }
+}
+
+/// Whether copies of `group`'s files are encrypted. Those of sensitive groups
+/// must be, so without age recipients they are refused.
+fn encrypts(group: &GroupPlan) -> Result<bool> {
+    if !group.sensitive {
+        return Ok(false);
+    }
+    if !age::has_recipients() {
+        return Err(DootError::Config(format!(
+            "Group '{}' is sensitive, so its backups need age.recipients to be encrypted; \
+             set them in doot.yaml or pass --no-backup",
+            group.group_name
+        ))
+        .into());
+    }
+    Ok(true)
+}
+
+fn save(dir: &Path, run: &Run) -> Result<()> {
+    let content = serde_json::to_vec_pretty(run)?;
+    write_private(&dir.join("run.json"), &content)
+}
+
+/// Creates `dir` readable only by the current user, since runs keep copies of
+/// the files they changed.
+pub(crate) fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))
+}
+
+/// Writes `content` to `path`, readable only by the current user.
+pub(crate) fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write: {}", path.display()))?;
+    std::io::Write::write_all(&mut file, content)
+        .with_context(|| format!("Failed to write: {}", path.display()))
+}
+
+/// Loads recorded runs, oldest first, optionally only those with `tag`.
+pub fn load_runs(tag: Option<&str>) -> Result<Vec<Run>> {
+    let dir = history_dir()?;
+    let mut runs = Vec::new();
+
+    let Ok(read_dir) = std::fs::read_dir(&dir) else {
+        return Ok(runs);
+    };
+
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let path = entry.path().join("run.json");
+        let Ok(content) = std::fs::read(&path) else {
+            continue;
+        };
+        let run: Run = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse: {}", path.display()))?;
+        if tag.is_none_or(|tag| run.tag.as_deref() == Some(tag)) {
+            runs.push(run);
+        }
+    }
+
+    runs.sort_by(|a, b| (a.timestamp, &a.id).cmp(&(b.timestamp, &b.id)));
+    Ok(runs)
+}
+
+/// Rebuilds the changes `run` didn't make before it was interrupted, after
+/// checking that their content is still what the run would have written.
+pub fn pending_plan(store: &dyn Store, run: &Run) -> Result<Plan> {
+    pending_plan_in(store, run, &history_dir()?.join(&run.id))
+}
+
+fn pending_plan_in(store: &dyn Store, run: &Run, dir: &Path) -> Result<Plan> {
+    let mut plan = Plan::new();
+    let mut changed = Vec::new();
+
+    for pending in &run.pending {
+        let generated = match &pending.generated {
+            Some(name) => Some(backup::load(&dir.join(name))?),
+            None => None,
+        };
+        let entry = FileEntry {
+            generated,
+            readonly: pending.readonly,
+            hidden: pending.hidden,
+            dir_modes: pending.dir_modes.clone(),
+            ..FileEntry::new(
+                pending.relative_path.clone(),
+                pending.source.clone(),
+                pending.destination.clone(),
+                pending.status.clone(),
+            )
+        };
+
+        if let Some(hash) = &pending.hash {
+            let current = match entry.source_content(store) {
+                Ok(content) => Some(hash_bytes(store.hash_algorithm(), &content)),
+                Err(_) => None,
+            };
+            if current.as_ref() != Some(hash) {
+                changed.push(format!(
+                    "{}/{}",
+                    pending.group,
+                    pending.relative_path.display()
+                ));
+                continue;
+            }
+        }
+
+        match plan.groups.last_mut() {
+            Some(group) if group.group_name == pending.group => group.entries.push(entry),
+            _ => match &pending.owner {
+                Some(owner) => plan.add_owned_group(
+                    pending.group.clone(),
+                    vec![entry],
+                    crate::users::lookup(owner)?,
+                ),
+                None => plan.add_group(pending.group.clone(), vec![entry]),
+            },
+        }
+    }
+
+    if !changed.is_empty() {
+        anyhow::bail!(
+            "{} source file(s) changed since the run was interrupted:\n  {}\n\
+             Run the import or export again to plan the remaining changes.",
+            changed.len(),
+            changed.join("\n  ")
+        );
+    }
+    Ok(plan)
+}
+
+/// Reverts a run: restores overwritten files and removes created ones.
+pub fn undo(store: &dyn Store, run: &mut Run) -> Result<()> {
+    let dir = history_dir()?.join(&run.id);
+
+    for entry in run.entries.iter().rev() {
+        if !entry.created && entry.backup.is_none() {
+            println!("    Kept {} (no backup)", entry.relative_path.display());
+            continue;
+        }
+        // Read the backup first, since it may live outside the history.
+        let content = match &entry.backup {
+            Some(backup) => Some(backup::load(&dir.join(backup))?),
+            None => None,
+        };
+        // Remove first so symlinks are replaced rather than written through.
+        store.remove(&entry.destination)?;
+
+        if let Some(content) = content {
+            store.write(&entry.destination, &content)?;
+            println!("    Restored {}", entry.relative_path.display());
+        } else {
+            println!("    Removed {}", entry.relative_path.display());
+        }
+    }
+
+    run.undone = true;
+    save(&dir, run)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Civil-from-days, from Howard Hinnant's date algorithms.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+
+    #[test]
+    fn format_known_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951825600), "2000-02-29 12:00:00 UTC");
+        assert_eq!(format_timestamp(1792281600), "2026-10-18 00:00:00 UTC");
+    }
+
+    #[test]
+    fn sensitive_groups_need_recipients_for_backups() {
+        let mut plan = Plan::new();
+        plan.add_group("ssh".to_string(), Vec::new());
+        assert!(!encrypts(&plan.groups[0]).unwrap());
+
+        plan.groups[0].sensitive = true;
+        let error = encrypts(&plan.groups[0]).unwrap_err();
+        assert_eq!(crate::error::code(&error), "config");
+        assert!(error.to_string().contains("age.recipients"));
+    }
+
+    #[test]
+    fn pending_plan_checks_source_hashes() {
+        let store = MemoryStore::new()
+            .with_file("/repo/vim/.vimrc", b"set nu\n")
+            .with_file("/repo/git/.gitconfig", b"[user]\n");
+        let pending = |group: &str, path: &str, content: &[u8]| PendingEntry {
+            group: group.to_string(),
+            owner: None,
+            relative_path: PathBuf::from(path),
+            source: Path::new("/repo").join(group).join(path),
+            destination: Path::new("/home").join(path),
+            status: FileStatus::Create,
+            hash: Some(hash_bytes(store.hash_algorithm(), content)),
+            generated: None,
+            readonly: false,
+            hidden: false,
+            dir_modes: Vec::new(),
+        };
+        let mut run = Run {
+            id: "1-1".to_string(),
+            timestamp: 1,
+            operation: "Export plan 'all'".to_string(),
+            tag: None,
+            entries: Vec::new(),
+            undone: false,
+            interrupted: true,
+            plan: None,
+            pending: vec![
+                pending("vim", ".vimrc", b"set nu\n"),
+                pending("git", ".gitconfig", b"[user]\n"),
+            ],
+        };
+
+        let plan = pending_plan_in(&store, &run, Path::new("/nonexistent")).unwrap();
+        let groups: Vec<_> = plan.groups.iter().map(|g| g.group_name.as_str()).collect();
+        assert_eq!(groups, ["vim", "git"]);
+
+        run.pending[0].hash = Some("stale".to_string());
+        let error = pending_plan_in(&store, &run, Path::new("/nonexistent")).unwrap_err();
+        assert!(error.to_string().contains("vim/.vimrc"));
+    }
+}
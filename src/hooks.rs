@@ -0,0 +1,27 @@
+use crate::plan::Hook;
+use crate::process;
+use anyhow::{Context, Result};
+use std::process::Output;
+
+/// Runs `hook` through the shell from the repo, with the name of the group
+/// in `DOOT_GROUP`, and returns its output.
+pub fn run(hook: &Hook, group: &str) -> Result<Output> {
+    #[cfg(unix)]
+    let mut command = {
+        let mut command = process::command("sh");
+        command.arg("-c");
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = process::command("cmd");
+        command.arg("/C");
+        command
+    };
+    command
+        .arg(&hook.command)
+        .env("DOOT_GROUP", group)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run {} of group '{}'", hook.name, group))
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of a run stopped with Ctrl-C, as shells report it (128 + SIGINT).
+pub const EXIT_CODE: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Catches Ctrl-C while alive, so a run can stop between files instead of
+/// in the middle of a write. A second Ctrl-C stops doot right away.
+pub struct Guard(());
+
+/// Starts catching Ctrl-C until the returned guard is dropped.
+pub fn catch() -> Guard {
+    REQUESTED.store(false, Ordering::SeqCst);
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, handle as *const () as libc::sighandler_t);
+    }
+    Guard(())
+}
+
+/// Whether Ctrl-C was pressed since `catch`.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+extern "C" fn handle(_signal: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+    // Storing an atomic and resetting the handler are async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+}
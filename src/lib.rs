@@ -0,0 +1,91 @@
+//! doot, a dotfile manager. The `doot` binary is a thin command line front
+//! end over these modules.
+//!
+//! Only `config`, `plan`, `store`, `executor` and `error` are meant for use
+//! outside doot, together with `testing` (behind the `testing` feature) to
+//! run plans against an in-memory store. The other public modules exist for
+//! the binary and may change in any release.
+
+#[doc(hidden)]
+pub mod add;
+pub(crate) mod age;
+#[doc(hidden)]
+pub mod apply_once;
+#[doc(hidden)]
+pub mod backup;
+#[doc(hidden)]
+pub mod cli;
+pub(crate) mod compare;
+pub mod config;
+#[doc(hidden)]
+pub mod config_edit;
+#[doc(hidden)]
+pub mod crash;
+#[doc(hidden)]
+#[cfg(unix)]
+pub mod daemon;
+#[doc(hidden)]
+pub mod doctor;
+pub(crate) mod encoding;
+pub(crate) mod env_file;
+pub mod error;
+pub mod executor;
+#[doc(hidden)]
+pub mod git;
+pub(crate) mod highlight;
+#[doc(hidden)]
+pub mod history;
+pub(crate) mod hooks;
+#[doc(hidden)]
+pub mod interrupt;
+#[doc(hidden)]
+pub mod machines;
+pub(crate) mod merge;
+#[doc(hidden)]
+pub mod messages;
+pub(crate) mod naming;
+#[doc(hidden)]
+pub mod offline;
+#[doc(hidden)]
+pub mod patch;
+pub(crate) mod patterns;
+pub mod plan;
+pub(crate) mod process;
+pub(crate) mod progress;
+#[doc(hidden)]
+pub mod reencrypt;
+#[doc(hidden)]
+pub mod report;
+pub(crate) mod resolver;
+#[doc(hidden)]
+pub mod sandbox;
+#[doc(hidden)]
+pub mod scan;
+pub(crate) mod schema;
+#[doc(hidden)]
+pub mod self_update;
+pub(crate) mod settings;
+#[doc(hidden)]
+pub mod snapshot;
+pub(crate) mod space;
+pub(crate) mod state;
+#[doc(hidden)]
+pub mod stats;
+#[doc(hidden)]
+pub mod status;
+pub mod store;
+#[doc(hidden)]
+pub mod style;
+#[doc(hidden)]
+pub mod summary;
+#[doc(hidden)]
+pub mod sync_state;
+pub(crate) mod systemd;
+#[doc(hidden)]
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub(crate) mod transaction;
+#[doc(hidden)]
+pub mod users;
+pub(crate) mod version;
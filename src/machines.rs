@@ -0,0 +1,288 @@
+use crate::plan::{FileStatus, Plan};
+use crate::state;
+use crate::store::{hash_bytes, Store};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Directory in the repo with one file per machine, so machines never edit
+/// the same file and merging their changes can't conflict.
+pub const DIR: &str = ".doot/machines";
+
+/// What a machine last synced, as committed to the repo.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MachineState {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolver: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub groups: BTreeMap<String, GroupSync>,
+}
+
+/// This machine's identity, kept in the state directory and set up with
+/// `doot machine init`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocalMachine {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Resolver used when a command isn't given one
+    #[serde(default)]
+    pub resolver: Option<String>,
+    /// Free-form role of the machine, such as `work`, shown in `status`
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+impl LocalMachine {
+    /// The registered name, or the hostname.
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(hostname)
+    }
+}
+
+/// The latest run on a machine that included a group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupSync {
+    pub operation: String,
+    #[serde(default)]
+    pub import: bool,
+    pub timestamp: u64,
+    /// Hashes of the group's repo files after the run, by path in the repo
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+/// How a machine's last sync of a group compares to the repo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Freshness {
+    /// The repo files are what the machine last synced
+    Current,
+    /// The repo changed since, e.g. through another machine
+    Behind,
+}
+
+/// Returns this machine's identity, creating its ID on first use. It lives in
+/// the state directory, so it survives hostname changes but not a reinstall.
+/// A resolver or profile not registered comes from doot's settings file.
+pub fn local() -> Result<LocalMachine> {
+    let mut machine = load_local()?;
+    let settings = crate::settings::load()?;
+    machine.resolver = machine.resolver.or(settings.resolver);
+    machine.profile = machine.profile.or(settings.profile);
+    Ok(machine)
+}
+
+fn load_local() -> Result<LocalMachine> {
+    let path = local_path()?;
+    if let Ok(content) = std::fs::read(&path) {
+        return serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse: {}", path.display()));
+    }
+
+    // Keep the ID of machines that synced before they could be registered.
+    let legacy = state::dir()?.join("machine-id");
+    let id = match std::fs::read_to_string(&legacy) {
+        Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+        _ => {
+            let seed = format!(
+                "{:?}-{}-{}",
+                std::time::SystemTime::now(),
+                std::process::id(),
+                hostname()
+            );
+            hash_bytes(crate::config::HashAlgorithm::Sha256, seed.as_bytes())[..16].to_string()
+        }
+    };
+    let machine = LocalMachine {
+        id,
+        ..Default::default()
+    };
+    save(&path, &machine)?;
+    Ok(machine)
+}
+
+fn local_path() -> Result<PathBuf> {
+    Ok(state::dir()?.join("machine.json"))
+}
+
+/// Names this machine and sets its default resolver and profile, also in its
+/// file in `repo` if given.
+pub fn init(
+    name: &str,
+    resolver: Option<String>,
+    profile: Option<String>,
+    repo: Option<&Path>,
+) -> Result<LocalMachine> {
+    let mut machine = load_local()?;
+    machine.name = Some(name.to_string());
+    machine.resolver = resolver;
+    machine.profile = profile;
+    save(&local_path()?, &machine)?;
+
+    if let Some(repo) = repo {
+        let (path, mut state) = load_own(repo, &machine)?;
+        state.name = machine.name();
+        state.resolver = machine.resolver.clone();
+        state.profile = machine.profile.clone();
+        save(&path, &state)?;
+    }
+    Ok(machine)
+}
+
+fn save(path: &Path, value: &impl Serialize) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let content = serde_json::to_vec_pretty(value)?;
+    std::fs::write(path, content).with_context(|| format!("Failed to write: {}", path.display()))
+}
+
+/// Path and content of `machine`'s file in `repo`, empty if it has none yet.
+fn load_own(repo: &Path, machine: &LocalMachine) -> Result<(PathBuf, MachineState)> {
+    let path = repo.join(DIR).join(format!("{}.json", machine.id));
+    let state = match std::fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse: {}", path.display()))?,
+        Err(_) => MachineState {
+            id: machine.id.clone(),
+            name: machine.name(),
+            resolver: machine.resolver.clone(),
+            profile: machine.profile.clone(),
+            ..Default::default()
+        },
+    };
+    Ok((path, state))
+}
+
+/// Whether this machine's file in `repo` records an export.
+pub fn ever_exported(repo: &Path) -> Result<bool> {
+    let (_, state) = load_own(repo, &local()?)?;
+    Ok(state.groups.values().any(|sync| !sync.import))
+}
+
+pub fn hostname() -> String {
+    std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Loads the state of every machine from `repo`, sorted by name.
+pub fn load_all(repo: &Path) -> Result<Vec<MachineState>> {
+    let dir = repo.join(DIR);
+    let Ok(read_dir) = std::fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+
+    let mut machines = Vec::new();
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let content =
+            std::fs::read(&path).with_context(|| format!("Failed to read: {}", path.display()))?;
+        let machine: MachineState = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse: {}", path.display()))?;
+        machines.push(machine);
+    }
+    machines.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+    Ok(machines)
+}
+
+/// Records the groups of an applied `plan` in this machine's file in `repo`.
+/// `import` tells which side of the entries is the repo.
+pub fn record(
+    repo: &Path,
+    store: &dyn Store,
+    plan: &Plan,
+    operation: &str,
+    import: bool,
+) -> Result<()> {
+    let (path, mut machine) = load_own(repo, &local()?)?;
+
+    let timestamp = crate::history::now();
+    for group in plan.groups.iter().filter(|g| g.skipped.is_none()) {
+        let mut files = BTreeMap::new();
+        // A sensitive group is recorded as synced, without hashes of its files.
+        for entry in group.entries.iter().filter(|_| !group.sensitive) {
+            if entry.status == FileStatus::Remove {
+                continue;
+            }
+            let repo_path = if import {
+                &entry.destination
+            } else {
+                &entry.source
+            };
+            // Files from an override directory outside the repo aren't shared.
+            let Ok(relative) = repo_path.strip_prefix(repo) else {
+                continue;
+            };
+            let content = entry.source_content(store)?;
+            files.insert(
+                relative.to_path_buf(),
+                hash_bytes(store.hash_algorithm(), &content),
+            );
+        }
+        machine.groups.insert(
+            group.group_name.clone(),
+            GroupSync {
+                operation: operation.to_string(),
+                import,
+                timestamp,
+                files,
+            },
+        );
+    }
+
+    save(&path, &machine)
+}
+
+/// Compares what a machine last synced of a group with the files in `repo`.
+pub fn freshness(repo: &Path, store: &dyn Store, sync: &GroupSync) -> Freshness {
+    let current = sync.files.iter().all(|(path, hash)| {
+        store
+            .read(&repo.join(path))
+            .is_ok_and(|content| &hash_bytes(store.hash_algorithm(), &content) == hash)
+    });
+    if current {
+        Freshness::Current
+    } else {
+        Freshness::Behind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+
+    #[test]
+    fn freshness_compares_repo_hashes() {
+        let store = MemoryStore::new().with_file("/repo/bash/.bashrc", b"set -o vi\n");
+        let hash = |content: &[u8]| hash_bytes(store.hash_algorithm(), content);
+        let sync = |content: &[u8]| GroupSync {
+            operation: "Export plan 'all'".to_string(),
+            import: false,
+            timestamp: 0,
+            files: BTreeMap::from([(PathBuf::from("bash/.bashrc"), hash(content))]),
+        };
+
+        let repo = Path::new("/repo");
+        assert_eq!(
+            freshness(repo, &store, &sync(b"set -o vi\n")),
+            Freshness::Current
+        );
+        assert_eq!(
+            freshness(repo, &store, &sync(b"set -o emacs\n")),
+            Freshness::Behind
+        );
+    }
+}
@@ -1,94 +1,611 @@
-mod cli;
-mod config;
-mod executor;
-mod plan;
-mod resolver;
-mod status;
-mod store;
+#[cfg(unix)]
+use doot::daemon;
+use doot::{
+    add, apply_once, backup, cli, config, config_edit, crash, doctor, error, executor, git,
+    history, interrupt, machines, messages, offline, patch, plan, reencrypt, report, sandbox, scan,
+    self_update, snapshot, stats, status, store, style, summary, sync_state, template, users,
+};
 
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 use cli::{Command, Target};
 use colored::Colorize;
 use config::Config;
 use executor::Executor;
+use history::Journal;
 use plan::{Plan, PlanBuilder};
-use status::{FileState, GroupStatus, StatusChecker};
-use store::create_store;
+use snapshot::Snapshot;
+use store::{create_store, CachingStore};
 
-fn main() -> Result<()> {
+fn main() {
     env_logger::init();
+    crash::install_panic_hook();
 
-    let args = cli::parse();
-    let config = Config::load(&args.config)?;
-    let store = create_store(config.mode);
+    if let Err(error) = run() {
+        // The executor already listed what was and wasn't applied.
+        if error::code(&error) == "interrupted" {
+            std::process::exit(interrupt::EXIT_CODE);
+        }
+        eprintln!("Error: {:?}", error);
+        crash::report_error(&error);
+        std::process::exit(1);
+    }
+}
 
-    match args.command {
-        Command::Import { target } => run_import(&config, &*store, &target, args.yes),
-        Command::Export { target } => run_export(&config, &*store, &target, args.yes),
-        Command::List => run_list(&config),
-        Command::Status { verbose, resolver } => run_status(&config, &*store, &resolver, verbose),
+fn run() -> Result<()> {
+    let mut args = cli::parse();
+    if args.ascii {
+        style::set_ascii(true);
+        colored::control::set_override(false);
+    }
+    if args.offline {
+        offline::enable();
+    }
+    if args.format == style::OutputFormat::Patch && !matches!(args.command, Command::Diff { .. }) {
+        anyhow::bail!("--format patch only applies to doot diff");
+    }
+    if args.format == style::OutputFormat::Json {
+        style::set_json(true);
+        colored::control::set_override(false);
     }
+    // Updating doot doesn't need a repo.
+    if let Command::SelfUpdate { check } = &args.command {
+        return run_self_update(*check, args.yes);
+    }
+    if let Some(root) = &args.sandbox {
+        if args.destdir.is_some() {
+            anyhow::bail!("--sandbox and --destdir can't be combined");
+        }
+        sandbox::enter(root)?;
+    }
+    // The config and groups to use are the cloned repo's.
+    if let Command::Clone { url, dir, .. } = &args.command {
+        let dir = dir.clone().unwrap_or_else(|| git::default_dir(url));
+        run_clone(url, &dir)?;
+    }
+    // Group directories and the config are found relative to the repo.
+    if let Command::ApplyOnce {
+        repo: Some(repo), ..
+    } = &args.command
+    {
+        std::env::set_current_dir(repo)
+            .with_context(|| format!("Failed to enter repo: {}", repo.display()))?;
+    }
+    let mut config = Config::load(&args.config)?;
+    if let Some(resolver) = args.command.resolver_mut() {
+        *resolver = config.canonical_resolver(resolver).to_string();
+    }
+    config.destdir = args.destdir.clone();
+    config.user = args.users.first().cloned();
+    config.configure_environment()?;
+    crash::set_config(&config);
+    // Hashes are kept between runs, so unchanged files are only `stat`ed.
+    let cache = CachingStore::load(create_store(config.mode, config.hash, config.sudo_paths()))?
+        .sensitive(config.sensitive_paths());
+    let store: &dyn store::Store = &cache;
+
+    let result = match &args.command {
+        Command::Import { target } => run_import(&config, store, target, &args),
+        Command::Export { target } => run_export(&config, store, target, &args),
+        Command::Prune { target } => run_prune(&config, store, target, &args),
+        Command::Diff { target } => run_diff(&config, store, target, &args),
+        Command::Patch {
+            action:
+                cli::PatchCommand::Apply {
+                    group,
+                    patch,
+                    resolver,
+                },
+        } => run_patch_apply(&config, store, group, patch, resolver.as_deref(), &args),
+        Command::Clone {
+            plan: Some(plan),
+            resolver,
+            ..
+        } => {
+            let resolver = match resolver {
+                Some(resolver) => resolver.clone(),
+                None => machines::local()?.resolver.context(
+                    "Pass --resolver, or set a default with `doot machine init --resolver`",
+                )?,
+            };
+            let target = Target::Plan {
+                require_all_groups: false,
+                name: plan.clone(),
+                resolver,
+            };
+            run_export(&config, store, &target, &args)
+        }
+        Command::Clone { plan: None, .. } => {
+            let mut plans: Vec<_> = config.plans.keys().cloned().collect();
+            plans.sort();
+            println!(
+                "Export a plan ({}) with `doot export plan PLAN RESOLVER`.",
+                plans.join(", ")
+            );
+            Ok(())
+        }
+        Command::ApplyOnce { plan, resolver, .. } => {
+            apply_once::run(&config, store, plan, resolver, &args)
+        }
+        Command::Plan {
+            action:
+                cli::PlanCommand::Diff {
+                    against_last_run,
+                    plans,
+                },
+        } => snapshot::run(plans, *against_last_run, args.tag.as_deref()),
+        Command::List => run_list(&config),
+        Command::Status {
+            verbose,
+            all_resolvers,
+            group,
+            plan,
+            resolver,
+        } => {
+            let scope = match (group, plan) {
+                (None, None) => status::Scope::All,
+                (Some(group), None) => status::Scope::Group(group.clone()),
+                (None, Some(plan)) => status::Scope::Plan(plan.clone()),
+                (Some(_), Some(_)) => anyhow::bail!("Pass either --group or --plan"),
+            };
+            match (resolver, all_resolvers) {
+                (None, true) if style::is_json() => {
+                    anyhow::bail!(
+                        "--format json shows one resolver; pass it instead of --all-resolvers"
+                    )
+                }
+                (None, true) if scope == status::Scope::All => {
+                    status::write_matrix(&mut io::stdout().lock(), &config, store)
+                }
+                (None, true) => anyhow::bail!("--all-resolvers shows every group and plan"),
+                (Some(resolver), false) => {
+                    run_status(&config, store, &args.config, resolver, &scope, *verbose)
+                }
+                (None, false) => match machines::local()?.resolver {
+                    Some(resolver) => {
+                        run_status(&config, store, &args.config, &resolver, &scope, *verbose)
+                    }
+                    None => anyhow::bail!(
+                        "Pass a resolver or --all-resolvers, or set a default with \
+                     `doot machine init --resolver`"
+                    ),
+                },
+                _ => anyhow::bail!("Pass either a resolver or --all-resolvers"),
+            }
+        }
+        Command::SelfUpdate { .. } => unreachable!("handled before loading the config"),
+        Command::Reencrypt { group } => reencrypt::run(&config, group.as_deref()),
+        Command::Daemon => run_daemon(&args.config),
+        Command::Report {
+            format,
+            output,
+            resolver,
+        } => report::run(&config, store, resolver, *format, output.as_deref()),
+        Command::Stats { resolver } => stats::run(&config, store, resolver),
+        Command::Doctor { resolver } => doctor::run(&config, &args.config, resolver, args.yes),
+        Command::Scan { depth, resolver } => scan::run(&config, resolver, *depth),
+        Command::Add {
+            group,
+            resolver,
+            path,
+        } => run_add(&config, store, group, resolver, path, &args),
+        Command::History => run_history(args.tag.as_deref()),
+        Command::Undo => run_undo(store, args.tag.as_deref(), args.yes),
+        Command::Resume => run_resume(&config, store, &args),
+        Command::Machine {
+            action:
+                cli::MachineCommand::Init {
+                    name,
+                    resolver,
+                    profile,
+                    repo,
+                },
+        } => run_machine_init(name, resolver.clone(), profile.clone(), *repo),
+        Command::Mv {
+            action: cli::MvCommand::Resolver { old, new },
+        } => config_edit::run_mv_resolver(&config, &args.config, old, new, args.dry_run),
+        Command::Set {
+            action: cli::SetCommand::Resolver { group, name, path },
+        } => config_edit::run_set_resolver(&config, &args.config, group, name, path, args.dry_run),
+    };
+    let saved = cache.save();
+    result.and(saved)
 }
 
 fn run_import(
     config: &Config,
     store: &dyn store::Store,
     target: &Target,
-    skip_confirm: bool,
+    args: &cli::Args,
 ) -> Result<()> {
-    let groups = resolve_groups(config, target)?;
+    let (plan, operation) = plan_import(config, store, target, args)?;
+    let applied = execute_plan(config, store, &plan, &operation, args, true)?;
+    if applied && !args.quiet {
+        summary::print(&plan, true);
+    }
+    if applied && config.git.auto_commit {
+        commit_import(&plan, &operation, &get_resolver_name(target))?;
+    }
+
+    check_timed_out(&plan)
+}
+
+/// Clones the dotfiles repo at `url` into `dir` and enters it.
+fn run_clone(url: &str, dir: &Path) -> Result<()> {
+    // Local paths can be cloned offline.
+    if !Path::new(url).exists() {
+        offline::ensure_online(&format!("Cloning {}", url))?;
+    }
+    git::clone(url, dir)?;
+    std::env::set_current_dir(dir)
+        .with_context(|| format!("Failed to enter repo: {}", dir.display()))?;
+    println!("Cloned {} into {}", url, dir.display());
+    Ok(())
+}
+
+/// Commits the repo files `plan` changed (`git.auto_commit`).
+fn commit_import(plan: &Plan, operation: &str, resolver: &str) -> Result<()> {
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    let files: Vec<PathBuf> = plan
+        .groups
+        .iter()
+        .filter(|group| group.skipped.is_none())
+        .flat_map(|group| &group.entries)
+        .filter(|entry| entry.status != plan::FileStatus::Same)
+        .filter_map(|entry| entry.destination.strip_prefix(&repo).ok())
+        .map(Path::to_path_buf)
+        .collect();
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!("{} from {}\n\n", operation, resolver);
+    for file in &files {
+        message.push_str(&format!("- {}\n", file.display()));
+    }
+    git::commit(&repo, &files, &message).context("Failed to commit the imported files")?;
+    if !style::is_json() {
+        println!("Committed {} file(s) to the repo.", files.len());
+    }
+    Ok(())
+}
+
+/// Plans importing `target`, returning the plan and the operation's name.
+fn plan_import(
+    config: &Config,
+    store: &dyn store::Store,
+    target: &Target,
+    args: &cli::Args,
+) -> Result<(Plan, String)> {
+    if args.users.len() > 1 {
+        anyhow::bail!("Import accepts at most one --user");
+    }
+
     let resolver_name = get_resolver_name(target);
+    let (groups, skipped) = select_groups(config, target, &resolver_name, true)?;
     let operation = get_operation_name("Import", target);
+    doctor::preflight(config, &groups, &resolver_name)?;
 
     let mut plan = Plan::new();
+    for (group_name, reason) in skipped {
+        plan.add_skipped(group_name, reason);
+    }
 
     let plan_builder = PlanBuilder::new(store);
     for group_name in groups {
-        let resolved_path = config.get_resolver(&group_name, &resolver_name)?;
-        let resolved_path = resolver::resolve_path(resolved_path)?;
+        let group = config.get_group(&group_name)?;
+        let resolved_path = config.resolve_group_path(&group_name, &resolver_name)?;
         let group_dir = get_group_dir(&group_name)?;
         let ignore_path = group_dir.join(".dootignore");
+        let layers = get_group_layers(config, &group_name)?;
 
-        let entries = plan_builder.build_import(&group_dir, &resolved_path, &ignore_path)?;
-        plan.add_group(group_name, entries);
+        match plan_builder.build_group_import(group, &layers, &resolved_path, &ignore_path) {
+            Ok(entries) => plan.add_group(group_name, entries),
+            Err(error) => plan.add_skipped(group_name, timed_out(error)?),
+        }
     }
 
-    let executor = Executor::new(store, config.mode);
-    executor.run(&plan, &operation, skip_confirm)?;
+    plan.mark_sensitive(config);
+    plan.attach_hooks(config, true);
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    sync_state::mark_conflicts(&repo, &mut plan, store, true)?;
+    Ok((plan, operation))
+}
 
-    Ok(())
+/// Shows the changes an import of `target` would make to the repo, as diffs
+/// or, with `--format patch`, as a patch to apply elsewhere.
+fn run_diff(
+    config: &Config,
+    store: &dyn store::Store,
+    target: &Target,
+    args: &cli::Args,
+) -> Result<()> {
+    let (plan, _) = plan_import(config, store, target, args)?;
+    match args.format {
+        style::OutputFormat::Patch => {
+            let repo = std::env::current_dir().context("Failed to get current directory")?;
+            patch::write(&mut std::io::stdout().lock(), store, &plan, &repo)?;
+        }
+        style::OutputFormat::Text if plan.has_changes() => Executor::new(store, config.mode)
+            .with_diff_limits(config.diff.clone())
+            .with_highlighting(config.highlight.clone(), config.syntaxes_dir()?)
+            .with_paths(args.show_paths)
+            .show_diffs(&plan)?,
+        style::OutputFormat::Text => println!("The system matches the repo."),
+        style::OutputFormat::Json => anyhow::bail!("doot diff prints text or a patch, not json"),
+    }
+    check_timed_out(&plan)
+}
+
+const TIMED_OUT: &str = "timed out";
+
+/// Reason to skip a group whose planning failed with `error`, if a store
+/// timed out; other errors still stop the run.
+fn timed_out(error: anyhow::Error) -> Result<String> {
+    if error::code(&error) == "timeout" {
+        Ok(format!("{}: {:#}", TIMED_OUT, error))
+    } else {
+        Err(error)
+    }
+}
+
+/// Fails a run that left out groups because their store timed out, after the
+/// other groups were synced.
+fn check_timed_out(plan: &Plan) -> Result<()> {
+    let groups: Vec<_> = plan
+        .groups
+        .iter()
+        .filter(|g| g.skipped.as_ref().is_some_and(|r| r.starts_with(TIMED_OUT)))
+        .map(|g| g.group_name.as_str())
+        .collect();
+    if groups.is_empty() {
+        return Ok(());
+    }
+    Err(error::DootError::Timeout(format!("Timed out planning {}", groups.join(", "))).into())
 }
 
 fn run_export(
     config: &Config,
     store: &dyn store::Store,
     target: &Target,
-    skip_confirm: bool,
+    args: &cli::Args,
 ) -> Result<()> {
-    let groups = resolve_groups(config, target)?;
+    let (mut plan, operation) = plan_export(config, store, target, args)?;
+    if args.prune {
+        for (group_name, entry) in export_orphans(config, target, &plan)? {
+            if let Some(group) = plan.groups.iter_mut().find(|g| g.group_name == group_name) {
+                group.entries.push(entry);
+            }
+        }
+    }
+
+    let applied = execute_plan(config, store, &plan, &operation, args, false)?;
+    if applied && !args.quiet {
+        summary::print(&plan, false);
+    }
+
+    check_timed_out(&plan)
+}
+
+/// Removes the files earlier exports of `target` wrote that its groups no
+/// longer have.
+fn run_prune(
+    config: &Config,
+    store: &dyn store::Store,
+    target: &Target,
+    args: &cli::Args,
+) -> Result<()> {
+    let (mut plan, _) = plan_export(config, store, target, args)?;
+    let mut orphans = export_orphans(config, target, &plan)?;
+    for group in &mut plan.groups {
+        group.entries = orphans
+            .extract_if(.., |(group_name, _)| *group_name == group.group_name)
+            .map(|(_, entry)| entry)
+            .collect();
+        // Removing stale files is not an export; its hooks don't apply.
+        group.pre_hook = None;
+        group.post_hook = None;
+    }
+
+    let operation = get_operation_name("Prune", target);
+    execute_plan(config, store, &plan, &operation, args, false)?;
+    Ok(())
+}
+
+/// Files earlier exports of `target` wrote that its groups no longer have,
+/// from the groups' sync state.
+fn export_orphans(
+    config: &Config,
+    target: &Target,
+    plan: &Plan,
+) -> Result<Vec<(String, plan::FileEntry)>> {
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
     let resolver_name = get_resolver_name(target);
+    sync_state::orphans(&repo, plan, |group| {
+        config.resolve_group_path(group, &resolver_name).ok()
+    })
+}
+
+/// Plans exporting `target`, returning the plan and the operation's name.
+fn plan_export(
+    config: &Config,
+    store: &dyn store::Store,
+    target: &Target,
+    args: &cli::Args,
+) -> Result<(Plan, String)> {
+    let resolver_name = get_resolver_name(target);
+    let (groups, skipped) = select_groups(config, target, &resolver_name, false)?;
     let operation = get_operation_name("Export", target);
+    doctor::preflight(config, &groups, &resolver_name)?;
 
     let mut plan = Plan::new();
+    for (group_name, reason) in skipped {
+        plan.add_skipped(group_name, reason);
+    }
 
-    let plan_builder = PlanBuilder::new(store);
-    for group_name in groups {
-        let resolved_path = config.get_resolver(&group_name, &resolver_name)?;
-        let resolved_path = resolver::resolve_path(resolved_path)?;
-        let group_dir = get_group_dir(&group_name)?;
+    if args.users.len() > 1 {
+        let plan_builder = PlanBuilder::new(store);
+        for user in &args.users {
+            let owner = users::lookup(user)?;
+            let mut config = config.clone();
+            config.user = Some(user.clone());
+            template::configure(&config.vars, user);
+
+            for group_name in &groups {
+                let name = format!("{} ({})", group_name, user);
+                match plan::export_entries(&config, &plan_builder, group_name, &resolver_name) {
+                    Ok(entries) => {
+                        let owner = group_owner(&config, group_name)?.unwrap_or(owner.clone());
+                        plan.add_owned_group(name, entries, owner)
+                    }
+                    Err(error) => plan.add_skipped(name, timed_out(error)?),
+                }
+            }
+        }
+    } else {
+        let planned = export_entries(config, store, &args.config, &groups, &resolver_name)?;
+        for (group_name, entries) in groups.into_iter().zip(planned) {
+            let entries = match entries {
+                Ok(entries) => entries,
+                Err(error) => {
+                    plan.add_skipped(group_name, timed_out(error)?);
+                    continue;
+                }
+            };
+            let owner = match group_owner(config, &group_name)? {
+                Some(owner) => Some(owner),
+                None => args
+                    .users
+                    .first()
+                    .map(|user| users::lookup(user))
+                    .transpose()?,
+            };
+            match owner {
+                Some(owner) => plan.add_owned_group(group_name, entries, owner),
+                None => plan.add_group(group_name, entries),
+            }
+        }
+    }
+
+    plan.mark_sensitive(config);
+    plan.attach_hooks(config, false);
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    sync_state::mark_conflicts(&repo, &mut plan, store, false)?;
+    Ok((plan, operation))
+}
+
+/// The configured `owner` of `group_name`, which wins over `--user`.
+fn group_owner(config: &Config, group_name: &str) -> Result<Option<users::User>> {
+    config
+        .get_group(group_name)?
+        .owner
+        .as_deref()
+        .map(users::lookup_owner)
+        .transpose()
+}
+
+/// Displays, confirms and applies `plan`, recording the run in the history
+/// (and with `track_machines`, in this machine's file in the repo). With
+/// `--dry-run`, the changes are only recorded in memory and listed. Returns
+/// whether the plan was applied.
+fn execute_plan(
+    config: &Config,
+    store: &dyn store::Store,
+    plan: &Plan,
+    operation: &str,
+    args: &cli::Args,
+    import: bool,
+) -> Result<bool> {
+    let snapshot = Snapshot::of(plan, operation);
+    if let Some(path) = &args.save_plan {
+        snapshot.save(path)?;
+    }
+    let executor = |store| -> Result<Executor> {
+        Ok(Executor::new(store, config.mode)
+            .with_diff_limits(config.diff.clone())
+            .with_highlighting(config.highlight.clone(), config.syntaxes_dir()?)
+            .with_paths(args.show_paths)
+            .with_force(args.force))
+    };
 
-        let entries = plan_builder.build_export(&group_dir, &resolved_path)?;
-        plan.add_group(group_name, entries);
+    if args.dry_run {
+        let recording = store::RecordingStore::new(store);
+        executor(&recording)?
+            .with_dry_run(true)
+            .run(plan, operation, true)?;
+        let changes: Vec<_> = recording
+            .mutations()
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        if style::is_json() {
+            print_run_report(&snapshot, false, &changes)?;
+        } else {
+            for change in changes {
+                println!("  {}", change.dimmed());
+            }
+        }
+        return Ok(false);
     }
 
-    let executor = Executor::new(store, config.mode);
-    executor.run(&plan, &operation, skip_confirm)?;
+    let backups = backup::Backups::from_config(config, args.no_backup)?;
+    let journal = Journal::begin(operation, args.tag.clone(), &backups)?;
+    journal.set_plan(snapshot.clone());
+    let executor = executor(store)?.with_journal(&journal);
+    let result = executor.run(plan, operation, args.yes);
+    eprintln!("{}", executor.tally());
+    journal.finish()?;
+    if *result.as_ref().unwrap_or(&false) || !plan.has_changes() {
+        let repo = std::env::current_dir().context("Failed to get current directory")?;
+        sync_state::record(&repo, plan, store, import)?;
+    }
+    if config.track_machines && *result.as_ref().unwrap_or(&false) {
+        let repo = std::env::current_dir().context("Failed to get current directory")?;
+        machines::record(&repo, store, plan, operation, import)?;
+    }
+    if let (true, Ok(applied)) = (style::is_json(), &result) {
+        print_run_report(&snapshot, *applied, &[])?;
+    }
+    result
+}
 
+/// Prints the plan of a run and whether it was applied as JSON
+/// (`--format json`), with the changes a dry run would have made.
+fn print_run_report(snapshot: &Snapshot, applied: bool, changes: &[String]) -> Result<()> {
+    let mut report = serde_json::to_value(snapshot)?;
+    report["applied"] = applied.into();
+    if !changes.is_empty() {
+        report["changes"] = changes.into();
+    }
+    println!("{}", serde_json::to_string_pretty(&report)?);
     Ok(())
 }
 
+/// Entries exporting each of `groups`, planned by a daemon serving this
+/// repository if one is running, so its warm hashes are used.
+fn export_entries(
+    config: &Config,
+    store: &dyn store::Store,
+    config_path: &Path,
+    groups: &[String],
+    resolver_name: &str,
+) -> Result<Vec<Result<Vec<plan::FileEntry>>>> {
+    #[cfg(unix)]
+    if let Some(planned) = daemon::request_export(config, config_path, resolver_name, groups)? {
+        return Ok(planned);
+    }
+    #[cfg(not(unix))]
+    let _ = config_path;
+
+    let plan_builder = PlanBuilder::new(store);
+    Ok(groups
+        .iter()
+        .map(|group_name| plan::export_entries(config, &plan_builder, group_name, resolver_name))
+        .collect())
+}
+
 fn resolve_groups(config: &Config, target: &Target) -> Result<Vec<String>> {
     match target {
         Target::Group { name, .. } => {
@@ -99,6 +616,68 @@ fn resolve_groups(config: &Config, target: &Target) -> Result<Vec<String>> {
     }
 }
 
+/// Groups of a run and the groups left out of it, with the reason.
+type GroupSelection = (Vec<String>, Vec<(String, String)>);
+
+/// Splits the groups of `target` into those to import (or export) and those
+/// to skip, with the reason. A single group that can't be synced that way is
+/// an error instead, and with `--require-all-groups` a plan that would skip
+/// any fails.
+fn select_groups(
+    config: &Config,
+    target: &Target,
+    resolver: &str,
+    import: bool,
+) -> Result<GroupSelection> {
+    let groups = resolve_groups(config, target)?;
+    let require_all_groups = match target {
+        Target::Group { name, .. } => {
+            config.check_direction(name, import)?;
+            if let Some(reason) = plan::missing_mount(config, name, resolver)? {
+                return Err(error::DootError::Resolver(format!(
+                    "Group '{}' can't be synced with '{}': {}",
+                    name, resolver, reason
+                ))
+                .into());
+            }
+            return Ok((groups, Vec::new()));
+        }
+        Target::Plan {
+            require_all_groups, ..
+        } => *require_all_groups,
+    };
+
+    let mut selected = Vec::new();
+    let mut skipped = Vec::new();
+    for group_name in groups {
+        let direction = config.get_group(&group_name)?.direction;
+        let reason = match direction.forbids(import) {
+            Some(direction) => Some(direction.to_string()),
+            None => plan::skip_reason(config, &group_name, resolver)?,
+        };
+        match reason {
+            Some(reason) => skipped.push((group_name, reason)),
+            None => selected.push(group_name),
+        }
+    }
+    skipped.sort();
+
+    if require_all_groups && !skipped.is_empty() {
+        let list: Vec<String> = skipped
+            .iter()
+            .map(|(group_name, reason)| format!("  {}: {}", group_name, reason))
+            .collect();
+        anyhow::bail!(
+            "{} group(s) would be skipped with resolver '{}':\n{}",
+            skipped.len(),
+            resolver,
+            list.join("\n")
+        );
+    }
+
+    Ok((selected, skipped))
+}
+
 fn get_resolver_name(target: &Target) -> String {
     match target {
         Target::Group { resolver, .. } | Target::Plan { resolver, .. } => resolver.clone(),
@@ -117,6 +696,11 @@ fn get_group_dir(group_name: &str) -> Result<PathBuf> {
     Ok(cwd.join(group_name))
 }
 
+fn get_group_layers(config: &Config, group_name: &str) -> Result<Vec<plan::Layer>> {
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    plan::group_layers(config, group_name, &cwd)
+}
+
 fn run_list(config: &Config) -> Result<()> {
     let mut plans: Vec<_> = config.plans.keys().collect();
     plans.sort();
@@ -127,15 +711,19 @@ fn run_list(config: &Config) -> Result<()> {
     println!("Plans");
     for (i, plan) in plans.iter().enumerate() {
         let is_last = i == plans.len() - 1;
-        let prefix = if is_last { "└── " } else { "├── " };
-        let child_prefix = if is_last { "    " } else { "│   " };
+        let prefix = style::branch(is_last);
+        let child_prefix = style::trunk(is_last);
 
         let plan_groups = config.plans.get(*plan).unwrap();
         match plan_groups {
             None => println!("{prefix}{plan} (all groups)"),
             Some(group_list) => {
                 println!("{prefix}{plan}");
-                println!("{child_prefix}└── {}", group_list.join(", "));
+                println!(
+                    "{child_prefix}{}{}",
+                    style::branch(true),
+                    group_list.join(", ")
+                );
             }
         }
     }
@@ -144,102 +732,304 @@ fn run_list(config: &Config) -> Result<()> {
     println!("Groups");
     for (i, group) in groups.iter().enumerate() {
         let is_last = i == groups.len() - 1;
-        let prefix = if is_last { "└── " } else { "├── " };
-        let child_prefix = if is_last { "    " } else { "│   " };
+        let prefix = style::branch(is_last);
+        let child_prefix = style::trunk(is_last);
 
-        println!("{prefix}{group}");
+        let group_config = config.groups.get(*group).unwrap();
+        match group_config.kind {
+            config::GroupKind::Files => println!("{prefix}{group}"),
+            config::GroupKind::Crontab => println!("{prefix}{group} (crontab)"),
+            config::GroupKind::Env => println!("{prefix}{group} (env)"),
+        }
 
-        let resolvers = config.groups.get(*group).unwrap();
+        let resolvers = &group_config.resolvers;
         let mut resolver_names: Vec<_> = resolvers.keys().collect();
         resolver_names.sort();
 
         for (j, resolver) in resolver_names.iter().enumerate() {
             let is_last_resolver = j == resolver_names.len() - 1;
-            let resolver_prefix = if is_last_resolver {
-                "└── "
-            } else {
-                "├── "
-            };
+            let resolver_prefix = style::branch(is_last_resolver);
             let path = resolvers.get(*resolver).unwrap();
-            println!("{child_prefix}{resolver_prefix}{resolver} → {path}");
+            println!(
+                "{child_prefix}{resolver_prefix}{resolver} {} {path}",
+                style::arrow()
+            );
+        }
+    }
+
+    if !config.aliases.is_empty() {
+        println!();
+        println!("Aliases");
+        for (i, (name, expansion)) in config.aliases.iter().enumerate() {
+            let prefix = style::branch(i == config.aliases.len() - 1);
+            println!("{prefix}{name} {} {expansion}", style::arrow());
         }
     }
 
     Ok(())
 }
 
-fn run_status(
+fn run_history(tag: Option<&str>) -> Result<()> {
+    let runs = history::load_runs(tag)?;
+    if runs.is_empty() {
+        println!("No runs recorded.");
+        return Ok(());
+    }
+
+    for run in runs.iter().rev() {
+        let tag = run
+            .tag
+            .as_ref()
+            .map(|t| format!(" [{}]", t))
+            .unwrap_or_default();
+        let undone = if run.undone {
+            " (undone)"
+        } else if run.interrupted {
+            " (interrupted)"
+        } else {
+            ""
+        };
+        println!(
+            "{}  {}  {}{} - {} files{}",
+            run.id.dimmed(),
+            history::format_timestamp(run.timestamp),
+            run.operation,
+            tag.cyan(),
+            run.entries.len(),
+            undone.dimmed()
+        );
+    }
+
+    Ok(())
+}
+
+fn run_undo(store: &dyn store::Store, tag: Option<&str>, skip_confirm: bool) -> Result<()> {
+    let runs = history::load_runs(tag)?;
+    let Some(mut run) = runs.into_iter().rev().find(|r| !r.undone) else {
+        println!("No runs to undo.");
+        return Ok(());
+    };
+
+    println!(
+        "\nUndo {} from {}:\n",
+        run.operation,
+        history::format_timestamp(run.timestamp)
+    );
+    for entry in &run.entries {
+        let action = if entry.created { "remove" } else { "restore" };
+        println!("    {} ({})", entry.relative_path.display(), action);
+    }
+
+    if !skip_confirm && !confirm("\nProceed?")? {
+        println!("\nAborted.");
+        return Ok(());
+    }
+
+    println!();
+    history::undo(store, &mut run)?;
+    println!("\nDone!");
+    Ok(())
+}
+
+/// Makes the changes the most recent run (with `--tag`) didn't get to before
+/// it was interrupted, recording them with that run.
+fn run_resume(config: &Config, store: &dyn store::Store, args: &cli::Args) -> Result<()> {
+    let runs = history::load_runs(args.tag.as_deref())?;
+    let Some(run) = runs.into_iter().rev().find(|r| !r.undone) else {
+        println!("No runs to resume.");
+        return Ok(());
+    };
+    if !run.interrupted {
+        println!("The most recent run wasn't interrupted; nothing to resume.");
+        return Ok(());
+    }
+
+    let mut plan = history::pending_plan(store, &run)?;
+    plan.mark_sensitive(config);
+    let operation = format!(
+        "Resume {} from {}",
+        run.operation,
+        history::format_timestamp(run.timestamp)
+    );
+    let journal = Journal::reopen(run, &backup::Backups::from_config(config, args.no_backup)?)?;
+    let executor = Executor::new(store, config.mode)
+        .with_journal(&journal)
+        .with_paths(args.show_paths);
+    let result = executor.run(&plan, &operation, true);
+    eprintln!("{}", executor.tally());
+    journal.finish()?;
+    result.map(|_| ())
+}
+
+fn run_add(
     config: &Config,
     store: &dyn store::Store,
+    group_name: &str,
     resolver: &str,
-    verbose: bool,
+    path: &Path,
+    args: &cli::Args,
+) -> Result<()> {
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    let (mut plan, note) = add::plan(config, store, &repo, group_name, resolver, path)?;
+    plan.mark_sensitive(config);
+    plan.attach_hooks(config, true);
+    let operation = format!("Add {} to group '{}'", path.display(), group_name);
+    if execute_plan(config, store, &plan, &operation, args, true)? && !style::is_json() {
+        if let Some(note) = note {
+            println!("\n{}", note);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the patch at `patch_path` to the repo files of `group_name`, then
+/// offers to export the files it changed.
+fn run_patch_apply(
+    config: &Config,
+    store: &dyn store::Store,
+    group_name: &str,
+    patch_path: &Path,
+    resolver: Option<&str>,
+    args: &cli::Args,
+) -> Result<()> {
+    config.get_group(group_name)?;
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    let text = std::fs::read_to_string(patch_path)
+        .with_context(|| format!("Failed to read: {}", patch_path.display()))?;
+    let mut plan = patch::plan(store, &repo, group_name, patch_path, &text)?;
+    plan.mark_sensitive(config);
+    plan.attach_hooks(config, true);
+    let operation = format!("Apply {} to group '{}'", patch_path.display(), group_name);
+    if !execute_plan(config, store, &plan, &operation, args, true)?
+        || style::is_json()
+        || config.check_direction(group_name, false).is_err()
+    {
+        return Ok(());
+    }
+
+    let resolver = match resolver {
+        Some(resolver) => resolver.to_string(),
+        None => match machines::local()?.resolver {
+            Some(resolver) => resolver,
+            None => {
+                println!(
+                    "\nExport the changes with `doot export group {} RESOLVER`.",
+                    group_name
+                );
+                return Ok(());
+            }
+        },
+    };
+    let touched: Vec<&Path> = plan.groups[0]
+        .entries
+        .iter()
+        .map(|entry| entry.destination.as_path())
+        .collect();
+    let mut entries =
+        plan::export_entries(config, &PlanBuilder::new(store), group_name, &resolver)?;
+    entries.retain(|entry| touched.contains(&entry.source.as_path()));
+
+    let mut export = Plan::new();
+    export.add_group(group_name.to_string(), entries);
+    export.mark_sensitive(config);
+    export.attach_hooks(config, false);
+    let operation = format!("Export the patched files of group '{}'", group_name);
+    execute_plan(config, store, &export, &operation, args, false)?;
+    Ok(())
+}
+
+fn run_machine_init(
+    name: &str,
+    resolver: Option<String>,
+    profile: Option<String>,
+    repo: bool,
 ) -> Result<()> {
-    let checker = StatusChecker::new(config, store, resolver.to_string());
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+    let machine = machines::init(name, resolver, profile, repo.then_some(cwd.as_path()))?;
+    println!(
+        "Registered this machine as '{}' ({})",
+        machine.name(),
+        machine.id
+    );
+    if let Some(resolver) = &machine.resolver {
+        println!("Default resolver: {}", resolver);
+    }
+    if let Some(profile) = &machine.profile {
+        println!("Profile: {}", profile);
+    }
+    Ok(())
+}
 
-    let group_results = checker.check_all_groups()?;
-    let plan_results = checker.check_all_plans(&group_results);
+fn confirm(prompt: &str) -> Result<bool> {
+    print!(
+        "{} {} ",
+        prompt,
+        messages::text(messages::Message::YesNoChoices)
+    );
+    io::stdout().flush()?;
 
-    println!("Plans ({})", resolver);
-    for (i, plan) in plan_results.iter().enumerate() {
-        let is_last = i == plan_results.len() - 1;
-        let prefix = if is_last { "└── " } else { "├── " };
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(messages::is_yes(&input))
+}
 
-        print_status_line(prefix, &plan.name, &plan.status);
+fn run_self_update(check_only: bool, skip_confirm: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let Some(release) = self_update::check()? else {
+        println!("doot {} is up to date.", current);
+        return Ok(());
+    };
+
+    println!(
+        "doot {} is available (installed: {}).",
+        release.tag_name, current
+    );
+    if check_only {
+        return Ok(());
     }
 
-    println!();
-    println!("Groups ({})", resolver);
-    for (i, group) in group_results.iter().enumerate() {
-        let is_last = i == group_results.len() - 1;
-        let prefix = if is_last { "└── " } else { "├── " };
-        let child_prefix = if is_last { "    " } else { "│   " };
-
-        print_status_line(prefix, &group.name, &group.status);
-
-        if verbose && group.status != GroupStatus::Skipped {
-            for (j, file) in group.files.iter().enumerate() {
-                let is_last_file = j == group.files.len() - 1;
-                let file_prefix = if is_last_file {
-                    "└── "
-                } else {
-                    "├── "
-                };
-
-                print_file_status_line(child_prefix, file_prefix, &file.relative_path, &file.state);
-            }
-        }
+    if !skip_confirm && !confirm("\nUpdate?")? {
+        println!("\nAborted.");
+        return Ok(());
     }
 
+    self_update::install(&release)?;
+    println!("\nUpdated to {}.", release.tag_name);
     Ok(())
 }
 
-fn print_status_line(prefix: &str, name: &str, status: &GroupStatus) {
-    match status {
-        GroupStatus::InSync => {
-            println!("{prefix}[{}] {}", "✓".blue(), name);
-        }
-        GroupStatus::OutOfSync => {
-            println!("{prefix}[{}] {}", "~".yellow(), name);
-        }
-        GroupStatus::New => {
-            println!("{prefix}[{}] {}", "+".green(), name);
-        }
-        GroupStatus::Skipped => {
-            println!("{prefix}{}", name.dimmed());
-        }
+fn run_daemon(config_path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    return daemon::serve(config_path);
+
+    #[cfg(not(unix))]
+    {
+        let _ = config_path;
+        anyhow::bail!("The daemon is only supported on Unix");
     }
 }
 
-fn print_file_status_line(child_prefix: &str, file_prefix: &str, path: &str, state: &FileState) {
-    match state {
-        FileState::InSync => {
-            println!("{child_prefix}{file_prefix}[{}] {}", "✓".blue(), path);
-        }
-        FileState::Modified => {
-            println!("{child_prefix}{file_prefix}[{}] {}", "~".yellow(), path);
-        }
-        FileState::New => {
-            println!("{child_prefix}{file_prefix}[{}] {}", "+".green(), path);
-        }
+fn run_status(
+    config: &Config,
+    store: &dyn store::Store,
+    config_path: &Path,
+    resolver: &str,
+    scope: &status::Scope,
+    verbose: bool,
+) -> Result<()> {
+    #[cfg(unix)]
+    if daemon::request_status(config, config_path, resolver, scope, verbose)? {
+        return Ok(());
     }
+    #[cfg(not(unix))]
+    let _ = config_path;
+
+    status::write(
+        &mut io::stdout().lock(),
+        config,
+        store,
+        resolver,
+        scope,
+        verbose,
+    )
 }
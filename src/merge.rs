@@ -0,0 +1,555 @@
+use crate::config::{CompareMode, MergeStrategy};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Structured file formats doot can merge, detected from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    /// INI-style files with `[section]` headers (also used for extensionless
+    /// files like `.gitconfig`)
+    Ini,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Format::Json,
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("toml") => Format::Toml,
+            _ => Format::Ini,
+        }
+    }
+
+    /// How merged content is compared to what is on disk. Applications
+    /// reformat JSON and YAML when rewriting them, so those are compared by
+    /// value.
+    pub fn compare_mode(self) -> Option<CompareMode> {
+        match self {
+            Format::Json => Some(CompareMode::SemanticJson),
+            Format::Yaml => Some(CompareMode::SemanticYaml),
+            Format::Toml | Format::Ini => None,
+        }
+    }
+}
+
+impl From<MergeStrategy> for Format {
+    fn from(strategy: MergeStrategy) -> Self {
+        match strategy {
+            MergeStrategy::Ini => Format::Ini,
+            MergeStrategy::Toml => Format::Toml,
+        }
+    }
+}
+
+/// Copies the managed `keys` from `from` into `base`, leaving everything else
+/// in `base` untouched. Keys are top-level keys for JSON, YAML and TOML and
+/// section names for INI. Managed keys missing from `from` are removed from `base`.
+pub fn managed(
+    format: Format,
+    base: Option<&[u8]>,
+    from: &[u8],
+    keys: &[String],
+) -> Result<Vec<u8>> {
+    match format {
+        Format::Json => {
+            let mut base: serde_json::Map<String, serde_json::Value> = match base {
+                Some(base) => serde_json::from_slice(base).context("Failed to parse JSON")?,
+                None => serde_json::Map::new(),
+            };
+            let from: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_slice(from).context("Failed to parse JSON")?;
+
+            for key in keys {
+                match from.get(key) {
+                    Some(value) => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                    None => {
+                        base.shift_remove(key);
+                    }
+                }
+            }
+
+            let mut content = serde_json::to_vec_pretty(&base)?;
+            content.push(b'\n');
+            Ok(content)
+        }
+        Format::Yaml => {
+            let mut base: serde_yaml::Mapping = match base {
+                Some(base) => serde_yaml::from_slice(base).context("Failed to parse YAML")?,
+                None => serde_yaml::Mapping::new(),
+            };
+            let from: serde_yaml::Mapping =
+                serde_yaml::from_slice(from).context("Failed to parse YAML")?;
+
+            for key in keys {
+                match from.get(key.as_str()) {
+                    Some(value) => {
+                        base.insert(key.as_str().into(), value.clone());
+                    }
+                    None => {
+                        base.shift_remove(key.as_str());
+                    }
+                }
+            }
+
+            Ok(serde_yaml::to_string(&base)?.into_bytes())
+        }
+        Format::Toml => {
+            let mut base = parse_toml(base.unwrap_or_default())?;
+            let from = parse_toml(from)?;
+
+            for key in keys {
+                match from.get(key) {
+                    Some(item) => {
+                        base.insert(key, item.clone());
+                    }
+                    None => {
+                        base.remove(key);
+                    }
+                }
+            }
+
+            Ok(base.to_string().into_bytes())
+        }
+        Format::Ini => {
+            let mut base = Ini::parse(&String::from_utf8_lossy(base.unwrap_or_default()));
+            let from = Ini::parse(&String::from_utf8_lossy(from));
+
+            for key in keys {
+                match from.section(key) {
+                    Some(section) => base.replace_section(section.clone()),
+                    None => base.sections.retain(|s| &s.name != key),
+                }
+            }
+
+            Ok(base.to_string().into_bytes())
+        }
+    }
+}
+
+/// Merges `from` into `base` key by key: keys from `from` win, keys only in
+/// `base` are kept.
+pub fn overlay(format: Format, base: Option<&[u8]>, from: &[u8]) -> Result<Vec<u8>> {
+    match format {
+        Format::Toml => {
+            let mut base = parse_toml(base.unwrap_or_default())?;
+            overlay_toml(base.as_table_mut(), parse_toml(from)?.as_table());
+            Ok(base.to_string().into_bytes())
+        }
+        Format::Ini => {
+            let mut base = Ini::parse(&String::from_utf8_lossy(base.unwrap_or_default()));
+            for section in Ini::parse(&String::from_utf8_lossy(from)).sections {
+                base.overlay_section(section);
+            }
+            Ok(base.to_string().into_bytes())
+        }
+        Format::Json | Format::Yaml => anyhow::bail!("Only INI and TOML files can be merged"),
+    }
+}
+
+fn block_markers(name: &str) -> (String, String) {
+    (
+        format!("# >>> doot:{} >>>", name),
+        format!("# <<< doot:{} <<<", name),
+    )
+}
+
+/// Returns the line range of the block named `name`, markers included.
+fn find_block(lines: &[&str], name: &str) -> Option<std::ops::Range<usize>> {
+    let (begin, end) = block_markers(name);
+    let start = lines.iter().position(|l| l.trim() == begin)?;
+    let len = lines[start..].iter().position(|l| l.trim() == end)?;
+    Some(start..start + len + 1)
+}
+
+/// Inserts `block` between doot markers into `base`, replacing the block
+/// from a previous export or appending it at the end.
+pub fn insert_block(name: &str, base: Option<&[u8]>, block: &[u8]) -> Vec<u8> {
+    let base = String::from_utf8_lossy(base.unwrap_or_default());
+    let block = String::from_utf8_lossy(block);
+    let (begin, end) = block_markers(name);
+
+    let mut lines: Vec<&str> = base.lines().collect();
+    let mut replacement = vec![begin.as_str()];
+    replacement.extend(block.lines());
+    replacement.push(end.as_str());
+
+    match find_block(&lines, name) {
+        Some(range) => {
+            lines.splice(range, replacement);
+        }
+        None => lines.extend(replacement),
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content.into_bytes()
+}
+
+/// Returns the content of the block named `name`, without its markers.
+pub fn extract_block(name: &str, content: &[u8]) -> Option<Vec<u8>> {
+    let content = String::from_utf8_lossy(content);
+    let lines: Vec<&str> = content.lines().collect();
+    let range = find_block(&lines, name)?;
+
+    let mut block = String::new();
+    for line in &lines[range.start + 1..range.end - 1] {
+        block.push_str(line);
+        block.push('\n');
+    }
+    Some(block.into_bytes())
+}
+
+fn parse_toml(content: &[u8]) -> Result<toml_edit::DocumentMut> {
+    String::from_utf8_lossy(content)
+        .parse()
+        .context("Failed to parse TOML")
+}
+
+fn overlay_toml(base: &mut dyn toml_edit::TableLike, from: &dyn toml_edit::TableLike) {
+    for (key, item) in from.iter() {
+        match (
+            base.get_mut(key).and_then(|i| i.as_table_like_mut()),
+            item.as_table_like(),
+        ) {
+            (Some(base), Some(from)) => overlay_toml(base, from),
+            _ => {
+                base.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Joins `fragments` (matched by `pattern`) into one file below a header
+/// saying where it came from.
+pub fn concat(pattern: &str, fragments: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = format!(
+        "# Generated by doot from {}. Edit the fragments in the repo instead.\n",
+        pattern
+    )
+    .into_bytes();
+    for fragment in fragments {
+        out.extend_from_slice(fragment);
+        if !fragment.is_empty() && !fragment.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// Builds a shell snippet that sources each of `files` in order. Fish gets
+/// `source`, other shells the POSIX `.`, picked by the extension of `target`.
+pub fn loader(target: &Path, pattern: &str, files: &[PathBuf]) -> Vec<u8> {
+    let fish = target.extension().is_some_and(|e| e == "fish");
+    let mut out = format!(
+        "# Generated by doot for the files matching {}. Add files there instead.\n",
+        pattern
+    );
+    for file in files {
+        let path = file.to_string_lossy();
+        // Single quotes keep `$` and backticks literal in every shell.
+        out.push_str(&if fish {
+            format!(
+                "source '{}'\n",
+                path.replace('\\', "\\\\").replace('\'', "\\'")
+            )
+        } else {
+            format!(". '{}'\n", path.replace('\'', "'\\''"))
+        });
+    }
+    out.into_bytes()
+}
+
+/// Splits `content` before every line starting with `marker`. Fragments are
+/// named `<index>-<rest of the marker line>` so that joining them in name
+/// order gives back `content`; lines before the first marker go to
+/// `00-preamble`.
+pub fn split(content: &[u8], marker: &str) -> Vec<(String, Vec<u8>)> {
+    let text = String::from_utf8_lossy(content);
+    let mut sections: Vec<(Option<&str>, String)> = vec![(None, String::new())];
+    for line in text.split_inclusive('\n') {
+        match line.strip_prefix(marker) {
+            Some(rest) => sections.push((Some(rest), line.to_string())),
+            None => sections.last_mut().unwrap().1.push_str(line),
+        }
+    }
+
+    let width = sections.len().to_string().len().max(2);
+    sections
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (_, body))| *index > 0 || !body.is_empty())
+        .map(|(index, (rest, body))| {
+            let name = rest.map_or_else(|| "preamble".to_string(), fragment_name);
+            (format!("{index:0width$}-{name}"), body.into_bytes())
+        })
+        .collect()
+}
+
+/// Turns the rest of a marker line into a file name.
+fn fragment_name(rest: &str) -> String {
+    let name: String = rest
+        .trim()
+        .chars()
+        .take(64)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "fragment".to_string()
+    } else {
+        name
+    }
+}
+
+/// A section of an INI file: its header (if any) and body lines, kept
+/// verbatim so comments and formatting survive a merge.
+#[derive(Debug, Clone)]
+pub(crate) struct Section {
+    pub name: String,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Ini {
+    /// Sections in file order; lines before the first header form a section
+    /// with an empty name
+    pub sections: Vec<Section>,
+}
+
+impl Ini {
+    pub fn parse(text: &str) -> Self {
+        let mut sections = vec![Section {
+            name: String::new(),
+            lines: Vec::new(),
+        }];
+
+        for line in text.lines() {
+            if let Some(name) = section_header(line) {
+                sections.push(Section {
+                    name,
+                    lines: Vec::new(),
+                });
+            }
+            sections.last_mut().unwrap().lines.push(line.to_string());
+        }
+
+        sections.retain(|s| !s.name.is_empty() || !s.lines.is_empty());
+        Self { sections }
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Replaces the section with the same name, or appends it.
+    pub fn replace_section(&mut self, section: Section) {
+        match self.sections.iter_mut().find(|s| s.name == section.name) {
+            Some(existing) => *existing = section,
+            None => self.sections.push(section),
+        }
+    }
+
+    /// Merges the keys of `section` into the section with the same name,
+    /// replacing existing keys and appending new ones.
+    fn overlay_section(&mut self, section: Section) {
+        let Some(existing) = self.sections.iter_mut().find(|s| s.name == section.name) else {
+            self.sections.push(section);
+            return;
+        };
+
+        for line in section.lines {
+            let Some(key) = ini_key(&line) else {
+                continue;
+            };
+            match existing.lines.iter().position(|l| ini_key(l) == Some(key)) {
+                Some(index) => existing.lines[index] = line,
+                None => {
+                    let at = existing
+                        .lines
+                        .iter()
+                        .rposition(|l| !l.trim().is_empty())
+                        .map_or(existing.lines.len(), |i| i + 1);
+                    existing.lines.insert(at, line);
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Ini {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in self.sections.iter().flat_map(|s| &s.lines) {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key of a `key = value` (or bare `key`) line.
+fn ini_key(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with(['#', ';']) || section_header(line).is_some() {
+        return None;
+    }
+    Some(line.split_once('=').map_or(line, |(key, _)| key).trim())
+}
+
+fn section_header(line: &str) -> Option<String> {
+    let line = line.trim();
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+    Some(name.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn json_managed_keys_keep_runtime_state() {
+        let base = br#"{"editor.fontSize": 12, "window.zoomLevel": 1, "telemetry": false}"#;
+        let from = br#"{"editor.fontSize": 14, "telemetry": true}"#;
+
+        let merged = managed(
+            Format::Json,
+            Some(base),
+            from,
+            &keys(&["editor.fontSize", "telemetry"]),
+        )
+        .unwrap();
+        let merged: serde_json::Value = serde_json::from_slice(&merged).unwrap();
+        assert_eq!(
+            merged,
+            serde_json::json!({"editor.fontSize": 14, "window.zoomLevel": 1, "telemetry": true})
+        );
+    }
+
+    #[test]
+    fn yaml_managed_key_missing_from_source_is_removed() {
+        let base = b"theme: dark\nrecent: [a, b]\n";
+        let from = b"recent: []\n";
+
+        let merged = managed(Format::Yaml, Some(base), from, &keys(&["theme"])).unwrap();
+        assert_eq!(merged, b"recent:\n- a\n- b\n");
+    }
+
+    #[test]
+    fn ini_overlay_repo_wins_per_key() {
+        let base = "[alias]\n\tco = checkout\n\tst = status\n\n[credential]\n\thelper = store\n";
+        let from =
+            "[alias]\n\t# short\n\tst = status -sb\n\tlg = log --oneline\n[user]\n\tname = Me\n";
+
+        let merged = overlay(Format::Ini, Some(base.as_bytes()), from.as_bytes()).unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "[alias]\n\tco = checkout\n\tst = status -sb\n\tlg = log --oneline\n\n[credential]\n\thelper = store\n[user]\n\tname = Me\n"
+        );
+    }
+
+    #[test]
+    fn toml_overlay_merges_tables() {
+        let base = "# machine\n[core]\neditor = \"vi\"\npager = \"less\"\n";
+        let from = "[core]\neditor = \"nvim\"\n[ui]\ncolor = true\n";
+
+        let merged = overlay(Format::Toml, Some(base.as_bytes()), from.as_bytes()).unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "# machine\n[core]\neditor = \"nvim\"\npager = \"less\"\n[ui]\ncolor = true\n"
+        );
+    }
+
+    #[test]
+    fn block_is_appended_then_refreshed() {
+        let hosts = b"127.0.0.1 localhost\n";
+        let first = insert_block("hosts", Some(hosts), b"10.0.0.2 nas\n");
+        assert_eq!(
+            String::from_utf8_lossy(&first),
+            "127.0.0.1 localhost\n# >>> doot:hosts >>>\n10.0.0.2 nas\n# <<< doot:hosts <<<\n"
+        );
+
+        let edited = [first.as_slice(), b"::1 localhost\n"].concat();
+        let second = insert_block("hosts", Some(&edited), b"10.0.0.3 nas\n");
+        assert_eq!(
+            String::from_utf8_lossy(&second),
+            "127.0.0.1 localhost\n# >>> doot:hosts >>>\n10.0.0.3 nas\n# <<< doot:hosts <<<\n::1 localhost\n"
+        );
+        assert_eq!(extract_block("hosts", &second).unwrap(), b"10.0.0.3 nas\n");
+        assert_eq!(extract_block("bash", &second), None);
+    }
+
+    #[test]
+    fn concat_adds_header_and_missing_newlines() {
+        let out = concat(
+            "conf.d/*.zsh",
+            &[b"a".to_vec(), Vec::new(), b"b\n".to_vec()],
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# Generated by doot from conf.d/*.zsh. Edit the fragments in the repo instead.\na\nb\n"
+        );
+    }
+
+    #[test]
+    fn loader_sources_files_in_order() {
+        let files = [
+            PathBuf::from("/home/me/conf.d/aliases.zsh"),
+            PathBuf::from("/home/me/conf.d/it's.zsh"),
+        ];
+        assert_eq!(
+            String::from_utf8(loader(Path::new("loader.zsh"), "conf.d/*.zsh", &files)).unwrap(),
+            "# Generated by doot for the files matching conf.d/*.zsh. Add files there instead.\n\
+             . '/home/me/conf.d/aliases.zsh'\n\
+             . '/home/me/conf.d/it'\\''s.zsh'\n"
+        );
+        let fish = loader(
+            Path::new("conf.d/doot.fish"),
+            "functions/*.fish",
+            &files[1..],
+        );
+        assert!(String::from_utf8(fish)
+            .unwrap()
+            .ends_with("source '/home/me/conf.d/it\\'s.zsh'\n"));
+    }
+
+    #[test]
+    fn split_round_trips() {
+        let content = b"# ssh\nHost github.com\n  User git\nHost *\n  ForwardAgent no";
+        let fragments = split(content, "Host ");
+        let names: Vec<_> = fragments.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["00-preamble", "01-github.com", "02-_"]);
+
+        let joined: Vec<u8> = fragments.into_iter().flat_map(|(_, body)| body).collect();
+        assert_eq!(joined, content);
+    }
+
+    #[test]
+    fn ini_managed_sections() {
+        let base = "[user]\n\temail = me@work\n[credential]\n\thelper = osxkeychain\n";
+        let from = "# repo\n[user]\n\temail = me@home\n[alias]\n\tco = checkout\n";
+
+        let merged = managed(
+            Format::Ini,
+            Some(base.as_bytes()),
+            from.as_bytes(),
+            &keys(&["user", "alias"]),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "[user]\n\temail = me@home\n[credential]\n\thelper = osxkeychain\n[alias]\n\tco = checkout\n"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::sync::OnceLock;
+
+/// Languages with a message catalog, selected from the locale environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    German,
+    Spanish,
+}
+
+impl Language {
+    /// Picks the language of a locale name like `de_DE.UTF-8`.
+    pub fn from_locale(locale: &str) -> Self {
+        let code = locale.split(['_', '.', '@']).next().unwrap_or_default();
+        match code {
+            "de" => Language::German,
+            "es" => Language::Spanish,
+            _ => Language::English,
+        }
+    }
+
+    /// Letter accepted (besides `y`) to answer a prompt with yes.
+    fn yes(self) -> &'static str {
+        match self {
+            Language::English => "y",
+            Language::German => "j",
+            Language::Spanish => "s",
+        }
+    }
+}
+
+/// User-facing strings that have translations. Messages with `{}` take
+/// arguments, see [`format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    Proceed,
+    ProceedChoices,
+    YesNoChoices,
+    InvalidOption,
+    NoFiles,
+    Same,
+    SameUnmanaged,
+    Create,
+    Overwrite,
+    Remove,
+    Conflict,
+    Summary,
+    SummaryRemove,
+    SummaryConflict,
+    NothingToDo,
+    Executing,
+    Created,
+    Updated,
+    Removed,
+    Skipped,
+    SkippedGroup,
+    Done,
+    DryRun,
+    Aborted,
+    Interrupted,
+    NotApplied,
+}
+
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::Proceed => "Proceed?",
+        Message::ProceedChoices => "[y/N/d]",
+        Message::YesNoChoices => "[y/N]",
+        Message::InvalidOption => {
+            "Invalid option. Use 'y' to proceed, 'n' to abort, or 'd' to show diffs."
+        }
+        Message::NoFiles => "(no files)",
+        Message::Same => "same",
+        Message::SameUnmanaged => "same, unmanaged after creation",
+        Message::Create => "create",
+        Message::Overwrite => "overwrite",
+        Message::Remove => "remove",
+        Message::Conflict => "conflict",
+        Message::Summary => "Summary: {} same, {} to create, {} to overwrite",
+        Message::SummaryRemove => ", {} to remove",
+        Message::SummaryConflict => ", {} in conflict",
+        Message::NothingToDo => "Nothing to do.",
+        Message::Executing => "Executing...",
+        Message::Created => "Created",
+        Message::Updated => "Updated",
+        Message::Removed => "Removed",
+        Message::Skipped => "Skipped",
+        Message::SkippedGroup => "skipped",
+        Message::Done => "Done!",
+        Message::DryRun => "Dry run: nothing was changed.",
+        Message::Aborted => "Aborted.",
+        Message::Interrupted => "Interrupted after {} of {} changes.",
+        Message::NotApplied => "Not applied",
+    }
+}
+
+fn german(message: Message) -> &'static str {
+    match message {
+        Message::Proceed => "Fortfahren?",
+        Message::ProceedChoices => "[j/N/d]",
+        Message::YesNoChoices => "[j/N]",
+        Message::InvalidOption => {
+            "Ungültige Eingabe. 'j' zum Fortfahren, 'n' zum Abbrechen, 'd' für Unterschiede."
+        }
+        Message::NoFiles => "(keine Dateien)",
+        Message::Same => "unverändert",
+        Message::SameUnmanaged => "unverändert, nach Erstellung nicht verwaltet",
+        Message::Create => "neu",
+        Message::Overwrite => "überschreiben",
+        Message::Remove => "entfernen",
+        Message::Conflict => "Konflikt",
+        Message::Summary => "Zusammenfassung: {} unverändert, {} neu, {} zu überschreiben",
+        Message::SummaryRemove => ", {} zu entfernen",
+        Message::SummaryConflict => ", {} im Konflikt",
+        Message::NothingToDo => "Nichts zu tun.",
+        Message::Executing => "Wird ausgeführt...",
+        Message::Created => "Erstellt",
+        Message::Updated => "Aktualisiert",
+        Message::Removed => "Entfernt",
+        Message::Skipped => "Übersprungen",
+        Message::SkippedGroup => "übersprungen",
+        Message::Done => "Fertig!",
+        Message::DryRun => "Probelauf: nichts wurde geändert.",
+        Message::Aborted => "Abgebrochen.",
+        Message::Interrupted => "Unterbrochen nach {} von {} Änderungen.",
+        Message::NotApplied => "Nicht angewendet",
+    }
+}
+
+fn spanish(message: Message) -> &'static str {
+    match message {
+        Message::Proceed => "¿Continuar?",
+        Message::ProceedChoices => "[s/N/d]",
+        Message::YesNoChoices => "[s/N]",
+        Message::InvalidOption => {
+            "Opción no válida. Use 's' para continuar, 'n' para cancelar o 'd' para ver diferencias."
+        }
+        Message::NoFiles => "(sin archivos)",
+        Message::Same => "sin cambios",
+        Message::SameUnmanaged => "sin cambios, no gestionado tras crearse",
+        Message::Create => "crear",
+        Message::Overwrite => "sobrescribir",
+        Message::Remove => "eliminar",
+        Message::Conflict => "conflicto",
+        Message::Summary => "Resumen: {} sin cambios, {} por crear, {} por sobrescribir",
+        Message::SummaryRemove => ", {} por eliminar",
+        Message::SummaryConflict => ", {} en conflicto",
+        Message::NothingToDo => "Nada que hacer.",
+        Message::Executing => "Ejecutando...",
+        Message::Created => "Creado",
+        Message::Updated => "Actualizado",
+        Message::Removed => "Eliminado",
+        Message::Skipped => "Omitido",
+        Message::SkippedGroup => "omitido",
+        Message::Done => "¡Hecho!",
+        Message::DryRun => "Simulación: no se cambió nada.",
+        Message::Aborted => "Cancelado.",
+        Message::Interrupted => "Interrumpido tras {} de {} cambios.",
+        Message::NotApplied => "No aplicado",
+    }
+}
+
+/// Returns the language of the user's locale (`LC_ALL`, `LC_MESSAGES`, then
+/// `LANG`).
+pub fn language() -> Language {
+    static LANGUAGE: OnceLock<Language> = OnceLock::new();
+    *LANGUAGE.get_or_init(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map_or(Language::English, |locale| Language::from_locale(&locale))
+    })
+}
+
+fn lookup(language: Language, message: Message) -> &'static str {
+    match language {
+        Language::English => english(message),
+        Language::German => german(message),
+        Language::Spanish => spanish(message),
+    }
+}
+
+/// Returns `message` in the user's language.
+pub fn text(message: Message) -> &'static str {
+    lookup(language(), message)
+}
+
+/// Returns `message` in the user's language with each `{}` replaced by the
+/// next argument.
+pub fn format(message: Message, args: &[&dyn std::fmt::Display]) -> String {
+    let mut out = String::new();
+    for (i, part) in text(message).split("{}").enumerate() {
+        if let Some(arg) = i.checked_sub(1).and_then(|i| args.get(i)) {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// Returns whether `answer` to a prompt means yes.
+pub fn is_yes(answer: &str) -> bool {
+    let answer = answer.trim().to_lowercase();
+    answer == "y" || answer == language().yes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[Message] = &[
+        Message::Proceed,
+        Message::ProceedChoices,
+        Message::YesNoChoices,
+        Message::InvalidOption,
+        Message::NoFiles,
+        Message::Same,
+        Message::SameUnmanaged,
+        Message::Create,
+        Message::Overwrite,
+        Message::Remove,
+        Message::Conflict,
+        Message::Summary,
+        Message::SummaryRemove,
+        Message::SummaryConflict,
+        Message::NothingToDo,
+        Message::Executing,
+        Message::Created,
+        Message::Updated,
+        Message::Removed,
+        Message::Skipped,
+        Message::SkippedGroup,
+        Message::Done,
+        Message::DryRun,
+        Message::Aborted,
+        Message::Interrupted,
+        Message::NotApplied,
+    ];
+
+    #[test]
+    fn language_from_locale() {
+        assert_eq!(Language::from_locale("de_DE.UTF-8"), Language::German);
+        assert_eq!(Language::from_locale("es"), Language::Spanish);
+        assert_eq!(Language::from_locale("C.UTF-8"), Language::English);
+    }
+
+    #[test]
+    fn catalogs_keep_placeholders() {
+        for language in [Language::German, Language::Spanish] {
+            for &message in ALL {
+                let english = english(message);
+                let translated = lookup(language, message);
+                assert!(!translated.is_empty());
+                assert_eq!(
+                    translated.matches("{}").count(),
+                    english.matches("{}").count(),
+                    "{:?} in {:?}",
+                    message,
+                    language
+                );
+            }
+        }
+    }
+}
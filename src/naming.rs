@@ -0,0 +1,86 @@
+//! Mapping between file names in the repo and at the destination.
+
+use crate::config::FilenameStyle;
+use std::path::{Component, Path, PathBuf};
+
+/// Prefix standing in for a leading dot in repo file names (`dot_prefix`).
+const DOT_PREFIX: &str = "dot_";
+
+/// Maps a path relative to the resolved path to its name in the repo.
+pub fn to_repo(style: FilenameStyle, relative: &Path) -> PathBuf {
+    map(style, relative, |name| {
+        name.strip_prefix('.')
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| format!("{DOT_PREFIX}{rest}"))
+    })
+}
+
+/// Maps a path relative to the group directory to its name at the destination.
+pub fn to_destination(style: FilenameStyle, relative: &Path) -> PathBuf {
+    map(style, relative, |name| {
+        name.strip_prefix(DOT_PREFIX)
+            .filter(|rest| !rest.is_empty())
+            .map(|rest| format!(".{rest}"))
+    })
+}
+
+/// Whether the file at `relative` is hidden by its name on Unix.
+pub fn is_dotfile(relative: &Path) -> bool {
+    relative
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// `relative` without `extension`, if it ends in it, as for `.tmpl` files.
+pub fn strip_extension(relative: &Path, extension: &str) -> Option<PathBuf> {
+    (relative.extension()? == extension).then(|| relative.with_extension(""))
+}
+
+/// `relative` with `extension` added after any it already has.
+pub fn add_extension(relative: &Path, extension: &str) -> PathBuf {
+    let mut name = relative.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn map(style: FilenameStyle, relative: &Path, rename: impl Fn(&str) -> Option<String>) -> PathBuf {
+    if style == FilenameStyle::Plain {
+        return relative.to_path_buf();
+    }
+
+    relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => name
+                .to_str()
+                .and_then(&rename)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(name)),
+            other => PathBuf::from(other.as_os_str()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_prefix_maps_every_component_both_ways() {
+        let destination = Path::new(".config/nvim/.netrwhist");
+        let repo = to_repo(FilenameStyle::DotPrefix, destination);
+        assert_eq!(repo, PathBuf::from("dot_config/nvim/dot_netrwhist"));
+        assert_eq!(to_destination(FilenameStyle::DotPrefix, &repo), destination);
+    }
+
+    #[test]
+    fn plain_style_and_bare_prefixes_are_left_alone() {
+        let path = Path::new(".bashrc");
+        assert_eq!(to_repo(FilenameStyle::Plain, path), path);
+        assert_eq!(
+            to_destination(FilenameStyle::DotPrefix, Path::new("dot_")),
+            PathBuf::from("dot_")
+        );
+    }
+}
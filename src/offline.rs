@@ -0,0 +1,27 @@
+use crate::error::DootError;
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Forbids network access for the rest of the run (`--offline`).
+pub fn enable() {
+    OFFLINE.store(true, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    OFFLINE.load(Ordering::SeqCst)
+}
+
+/// Fails right away if `--offline` is set, rather than letting `what` wait
+/// for a network that isn't there.
+pub fn ensure_online(what: &str) -> Result<()> {
+    if is_enabled() {
+        return Err(DootError::Offline(format!(
+            "{} needs the network, which --offline forbids",
+            what
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,398 @@
+use crate::age;
+use crate::error::DootError;
+use crate::plan::{FileEntry, FileStatus, Plan};
+use crate::store::Store;
+use anyhow::{Context, Result};
+use similar::TextDiff;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// The changes a unified diff makes to one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FilePatch {
+    /// Path relative to the repo, without the `a/` or `b/` prefix
+    path: PathBuf,
+    created: bool,
+    deleted: bool,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hunk {
+    /// Line the hunk starts at in the old file, counting from 1
+    old_start: usize,
+    /// Lines before and after the hunk is applied, with their newlines
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// Writes the changes an import `plan` makes to `repo` as a unified diff with
+/// `a/` and `b/` paths relative to the repo, for `git apply` or `patch -p1`.
+/// Sensitive groups are left out, and named on stderr.
+pub fn write(out: &mut dyn Write, store: &dyn Store, plan: &Plan, repo: &Path) -> Result<()> {
+    for group in plan.groups.iter().filter(|g| g.skipped.is_none()) {
+        if group.sensitive {
+            eprintln!("Left out sensitive group '{}'", group.group_name);
+            continue;
+        }
+        for entry in &group.entries {
+            if entry.status == FileStatus::Same {
+                continue;
+            }
+            // Files from an override directory outside the repo aren't part of it.
+            let Ok(path) = entry.destination.strip_prefix(repo) else {
+                continue;
+            };
+            // A patch would hold the plaintext; it's only kept in memory.
+            if age::is_encrypted(path) {
+                eprintln!("Left out encrypted file {}", path.display());
+                continue;
+            }
+            let old = match entry.status {
+                FileStatus::Create => Vec::new(),
+                _ => store.read(&entry.destination)?,
+            };
+            let new = match entry.status {
+                FileStatus::Remove => Vec::new(),
+                _ => entry.source_content(store)?,
+            };
+            write_file(out, &path.to_string_lossy(), &entry.status, &old, &new)?;
+        }
+    }
+    Ok(())
+}
+
+/// Plans applying the unified diff `text` to the files of `group` in `repo`,
+/// refusing paths outside the group's directory and hunks that don't match.
+pub fn plan(
+    store: &dyn Store,
+    repo: &Path,
+    group: &str,
+    patch_path: &Path,
+    text: &str,
+) -> Result<Plan> {
+    let mut entries = Vec::new();
+    for file in parse(text)? {
+        let relative = file
+            .path
+            .strip_prefix(group)
+            .ok()
+            .filter(|relative| {
+                !relative.as_os_str().is_empty()
+                    && relative
+                        .components()
+                        .all(|c| matches!(c, Component::Normal(_)))
+            })
+            .with_context(|| {
+                format!(
+                    "{} is outside group '{}'; patch paths start with the group directory",
+                    file.path.display(),
+                    group
+                )
+            })?;
+        let destination = repo.join(&file.path);
+
+        let exists = store.exists(&destination);
+        if file.created && exists {
+            return Err(DootError::Conflict(format!(
+                "{} already exists in the repo",
+                file.path.display()
+            ))
+            .into());
+        }
+        let old = if exists {
+            let content = store.read(&destination)?;
+            String::from_utf8(content)
+                .map_err(|_| anyhow::anyhow!("{} is not text", file.path.display()))?
+        } else if file.created {
+            String::new()
+        } else {
+            return Err(
+                DootError::Conflict(format!("{} is not in the repo", file.path.display())).into(),
+            );
+        };
+        let new = file.apply(&old)?;
+
+        let status = match (file.deleted, exists) {
+            (true, _) => FileStatus::Remove,
+            (false, true) => FileStatus::Overwrite,
+            (false, false) => FileStatus::Create,
+        };
+        let mut entry = FileEntry::new(
+            relative.to_path_buf(),
+            patch_path.to_path_buf(),
+            destination,
+            status,
+        );
+        if !file.deleted {
+            entry.generated = Some(new.into_bytes());
+        }
+        entries.push(entry);
+    }
+
+    let mut plan = Plan::new();
+    plan.add_group(group.to_string(), entries);
+    Ok(plan)
+}
+
+/// Reads the files and hunks of a unified diff, as written by `diff -u`,
+/// `git diff` or `doot diff --format patch`. Lines outside of hunks, such as
+/// `diff --git` and `index` headers, are ignored.
+fn parse(text: &str) -> Result<Vec<FilePatch>> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = text.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        if let Some(old_name) = line.strip_prefix("--- ") {
+            let new_name = lines
+                .next()
+                .and_then(|line| line.strip_prefix("+++ "))
+                .context("Malformed patch: `---` line without a `+++` line")?;
+            let (old_name, new_name) = (file_name(old_name), file_name(new_name));
+            let created = old_name.is_none();
+            let deleted = new_name.is_none();
+            let path = new_name
+                .or(old_name)
+                .context("Malformed patch: both sides are /dev/null")?;
+            files.push(FilePatch {
+                path,
+                created,
+                deleted,
+                hunks: Vec::new(),
+            });
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            let file = files
+                .last_mut()
+                .context("Malformed patch: hunk before any file header")?;
+            let (old_start, mut old_left, mut new_left) = hunk_range(header)
+                .with_context(|| format!("Malformed hunk header: @@ {}", header.trim_end()))?;
+            let mut hunk = Hunk {
+                old_start,
+                old: Vec::new(),
+                new: Vec::new(),
+            };
+            while old_left + new_left > 0 {
+                let line = lines
+                    .next()
+                    .with_context(|| format!("Patch for {} ends early", file.path.display()))?;
+                // Some editors strip the space of empty context lines.
+                let (tag, content) = match line {
+                    "\n" => (' ', "\n"),
+                    _ => {
+                        let mut chars = line.chars();
+                        (chars.next().unwrap_or(' '), chars.as_str())
+                    }
+                };
+                let content = content.to_string();
+                match tag {
+                    ' ' if old_left > 0 && new_left > 0 => {
+                        hunk.old.push(content.clone());
+                        hunk.new.push(content);
+                        old_left -= 1;
+                        new_left -= 1;
+                    }
+                    '-' if old_left > 0 => {
+                        hunk.old.push(content);
+                        old_left -= 1;
+                    }
+                    '+' if new_left > 0 => {
+                        hunk.new.push(content);
+                        new_left -= 1;
+                    }
+                    '\\' => {}
+                    _ => anyhow::bail!(
+                        "Malformed hunk in the patch for {}: {}",
+                        file.path.display(),
+                        line.trim_end()
+                    ),
+                }
+                // The last line of a side may be followed by this marker.
+                if lines.peek().is_some_and(|l| l.starts_with("\\ ")) {
+                    lines.next();
+                    let side = match tag {
+                        '-' => &mut hunk.old,
+                        '+' => &mut hunk.new,
+                        _ => {
+                            strip_newline(&mut hunk.old);
+                            &mut hunk.new
+                        }
+                    };
+                    strip_newline(side);
+                }
+            }
+            file.hunks.push(hunk);
+        }
+    }
+    if files.is_empty() {
+        anyhow::bail!("The patch doesn't change any files");
+    }
+    Ok(files)
+}
+
+/// Path named on a `---` or `+++` line, or `None` for `/dev/null`.
+fn file_name(name: &str) -> Option<PathBuf> {
+    // Timestamps written by `diff -u` follow a tab.
+    let name = name.trim_end_matches(['\n', '\r']);
+    let name = name.split('\t').next().unwrap_or(name);
+    if name == "/dev/null" {
+        return None;
+    }
+    let name = name
+        .strip_prefix("a/")
+        .or_else(|| name.strip_prefix("b/"))
+        .unwrap_or(name);
+    Some(PathBuf::from(name))
+}
+
+/// Start in the old file and line counts of both sides from `-1,3 +1,4 @@`.
+fn hunk_range(header: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = header.split_whitespace();
+    let parse = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = parse(ranges.next()?.strip_prefix('-')?)?;
+    let (_, new_count) = parse(ranges.next()?.strip_prefix('+')?)?;
+    Some((old_start, old_count, new_count))
+}
+
+fn strip_newline(lines: &mut [String]) {
+    if let Some(last) = lines.last_mut() {
+        if last.ends_with('\n') {
+            last.pop();
+        }
+    }
+}
+
+impl FilePatch {
+    /// Applies the hunks to `old`, in order. A hunk whose lines moved since
+    /// the patch was made is applied where they are now, nearest to where
+    /// they were.
+    fn apply(&self, old: &str) -> Result<String> {
+        let lines: Vec<&str> = old.split_inclusive('\n').collect();
+        let mut result = String::new();
+        let mut cursor = 0;
+        for (index, hunk) in self.hunks.iter().enumerate() {
+            let expected = hunk.old_start.saturating_sub(1).max(cursor);
+            let matches = |at: usize| {
+                at + hunk.old.len() <= lines.len()
+                    && hunk.old.iter().zip(&lines[at..]).all(|(a, b)| a == b)
+            };
+            let found = (cursor..=lines.len())
+                .filter(|&at| matches(at))
+                .min_by_key(|at| at.abs_diff(expected))
+                .ok_or_else(|| {
+                    DootError::Conflict(format!(
+                        "Hunk {} of the patch for {} doesn't match the repo file",
+                        index + 1,
+                        self.path.display()
+                    ))
+                })?;
+            lines[cursor..found]
+                .iter()
+                .for_each(|line| result.push_str(line));
+            hunk.new.iter().for_each(|line| result.push_str(line));
+            cursor = found + hunk.old.len();
+        }
+        lines[cursor..]
+            .iter()
+            .for_each(|line| result.push_str(line));
+        Ok(result)
+    }
+}
+
+fn write_file(
+    out: &mut dyn Write,
+    path: &str,
+    status: &FileStatus,
+    old: &[u8],
+    new: &[u8],
+) -> Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let (old_name, new_name) = match status {
+        FileStatus::Create => ("/dev/null".to_string(), format!("b/{}", path)),
+        FileStatus::Remove => (format!("a/{}", path), "/dev/null".to_string()),
+        _ => (format!("a/{}", path), format!("b/{}", path)),
+    };
+
+    writeln!(out, "diff --git a/{} b/{}", path, path)?;
+    match status {
+        FileStatus::Create => writeln!(out, "new file mode 100644")?,
+        FileStatus::Remove => writeln!(out, "deleted file mode 100644")?,
+        _ => {}
+    }
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        writeln!(out, "Binary files {} and {} differ", old_name, new_name)?;
+        return Ok(());
+    };
+    write!(
+        out,
+        "{}",
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .header(&old_name, &new_name)
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+
+    fn patch(status: FileStatus, old: &[u8], new: &[u8]) -> String {
+        let mut out = Vec::new();
+        write_file(&mut out, "bash/.bashrc", &status, old, new).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_git_style_headers() {
+        assert_eq!(
+            patch(FileStatus::Overwrite, b"a\nb\n", b"a\nc\n"),
+            "diff --git a/bash/.bashrc b/bash/.bashrc\n\
+             --- a/bash/.bashrc\n\
+             +++ b/bash/.bashrc\n\
+             @@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+        assert_eq!(
+            patch(FileStatus::Create, b"", b"a\n"),
+            "diff --git a/bash/.bashrc b/bash/.bashrc\n\
+             new file mode 100644\n\
+             --- /dev/null\n\
+             +++ b/bash/.bashrc\n\
+             @@ -0,0 +1 @@\n+a\n"
+        );
+        assert!(patch(FileStatus::Remove, &[0xff], b"").contains("Binary files"));
+        assert_eq!(patch(FileStatus::Overwrite, b"a\n", b"a\n"), "");
+    }
+
+    #[test]
+    fn applies_patches_within_the_group() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\nno newline";
+        let text = patch(FileStatus::Overwrite, old.as_bytes(), new.as_bytes());
+
+        // Lines added above the hunk since the patch was made.
+        let moved = format!("first\n{}", old);
+        let store = MemoryStore::new().with_file("/repo/bash/.bashrc", moved.as_bytes());
+        let repo = Path::new("/repo");
+        let plan = super::plan(&store, repo, "bash", Path::new("fix.patch"), &text).unwrap();
+        let entry = &plan.groups[0].entries[0];
+        assert_eq!(entry.relative_path, Path::new(".bashrc"));
+        assert_eq!(
+            entry.generated.as_deref(),
+            Some(format!("first\n{}", new).as_bytes())
+        );
+
+        let store = MemoryStore::new().with_file("/repo/bash/.bashrc", b"x\n");
+        assert!(super::plan(&store, repo, "bash", Path::new("fix.patch"), &text).is_err());
+        assert!(super::plan(&store, repo, "nvim", Path::new("fix.patch"), &text).is_err());
+        let escape = text.replace("bash/.bashrc", "bash/../.ssh/config");
+        assert!(super::plan(&store, repo, "bash", Path::new("fix.patch"), &escape).is_err());
+    }
+}
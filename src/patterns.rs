@@ -0,0 +1,67 @@
+use anyhow::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Maps gitignore-style patterns (as in `.dootignore`) to values, matched
+/// against paths relative to a group root.
+#[derive(Debug, Default)]
+pub struct PatternMatcher<T> {
+    rules: Vec<(Gitignore, T)>,
+}
+
+impl<T: Clone> PatternMatcher<T> {
+    pub fn new(patterns: &BTreeMap<String, T>) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (pattern, value) in patterns {
+            let mut builder = GitignoreBuilder::new("");
+            builder.add_line(None, pattern)?;
+            rules.push((builder.build()?, value.clone()));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Returns the value of the first pattern matching `relative_path` or one
+    /// of its parent directories.
+    pub fn find(&self, relative_path: &Path) -> Option<&T> {
+        self.rules
+            .iter()
+            .find(|(matcher, _)| {
+                matcher
+                    .matched_path_or_any_parents(relative_path, false)
+                    .is_ignore()
+            })
+            .map(|(_, value)| value)
+    }
+}
+
+impl PatternMatcher<()> {
+    pub fn from_list(patterns: &[String]) -> Result<Self> {
+        Self::new(&patterns.iter().map(|p| (p.clone(), ())).collect())
+    }
+
+    pub fn matches(&self, relative_path: &Path) -> bool {
+        self.find(relative_path).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_by_extension_and_directory() {
+        let patterns = BTreeMap::from([
+            ("*.json".to_string(), 1),
+            (".ssh/".to_string(), 2),
+            ("/top.conf".to_string(), 3),
+        ]);
+        let matcher = PatternMatcher::new(&patterns).unwrap();
+
+        assert_eq!(matcher.find(Path::new("a/settings.json")), Some(&1));
+        assert_eq!(matcher.find(Path::new(".ssh/config")), Some(&2));
+        assert_eq!(matcher.find(Path::new("top.conf")), Some(&3));
+        assert_eq!(matcher.find(Path::new("nested/top.conf")), None);
+        assert_eq!(matcher.find(Path::new(".bashrc")), None);
+    }
+}
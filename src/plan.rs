@@ -1,30 +1,135 @@
-use crate::store::Store;
-use anyhow::Result;
+use crate::age;
+use crate::compare;
+use crate::config::{
+    CompareMode, Config, CreateParents, FilenameStyle, Group, GroupKind, MergeStrategy,
+};
+use crate::env_file;
+use crate::error::DootError;
+use crate::merge;
+use crate::naming;
+use crate::patterns::PatternMatcher;
+use crate::process;
+use crate::store::{container, crontab, Store};
+use crate::template;
+use crate::users::User;
+use anyhow::{Context, Result};
+use ignore::gitignore::GitignoreBuilder;
 use ignore::WalkBuilder;
-use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileStatus {
     Same,
     Create,
     Overwrite,
+    /// The destination has no source anymore and is deleted
+    Remove,
+    /// Source and destination both changed since the last sync; only
+    /// overwritten with `--force`
+    Conflict,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
     pub relative_path: PathBuf,
     pub source: PathBuf,
     pub destination: PathBuf,
     pub status: FileStatus,
+    /// Content produced by doot itself rather than read from `source`
+    pub generated: Option<Vec<u8>>,
+    /// Only written if the destination is missing (`create_only`)
+    pub create_only: bool,
+    /// Left read-only after it is written (`readonly` groups)
+    pub readonly: bool,
+    /// Given the Windows hidden attribute after it is written (`hidden_attribute`)
+    pub hidden: bool,
+    /// Name in the group directory, if it differs from `relative_path`
+    /// (`filename_style`)
+    pub repo_path: Option<PathBuf>,
+    /// Label of the layer the repo side of this file lives in, if it is not
+    /// the group's own directory
+    pub layer: Option<String>,
+    /// Directories above the destination with the mode they get if this
+    /// write creates them (`dir_modes`), outermost first
+    pub dir_modes: Vec<(PathBuf, u32)>,
+    /// Note shown next to the file in the plan (`notes`)
+    pub note: Option<String>,
+}
+
+impl FileEntry {
+    /// Entry copying `source` to `destination`, with no other settings.
+    pub fn new(
+        relative_path: PathBuf,
+        source: PathBuf,
+        destination: PathBuf,
+        status: FileStatus,
+    ) -> Self {
+        Self {
+            relative_path,
+            source,
+            destination,
+            status,
+            generated: None,
+            create_only: false,
+            readonly: false,
+            hidden: false,
+            repo_path: None,
+            layer: None,
+            dir_modes: Vec::new(),
+            note: None,
+        }
+    }
+
+    /// Whether the repo side of this file is encrypted with age.
+    pub fn is_encrypted(&self) -> bool {
+        crate::age::is_encrypted(&self.source) || crate::age::is_encrypted(&self.destination)
+    }
+
+    pub fn source_content(&self, store: &dyn Store) -> Result<Vec<u8>> {
+        match &self.generated {
+            Some(content) => Ok(content.clone()),
+            None => store.read(&self.source),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct GroupPlan {
     pub group_name: String,
     pub entries: Vec<FileEntry>,
+    /// User that written files are handed over to (`--user`)
+    pub owner: Option<User>,
+    /// Why the group is left out of this run, if it is
+    pub skipped: Option<String>,
+    /// Hashes of the group's files must not be stored (`sensitive` groups)
+    pub sensitive: bool,
+    /// Commands run before the group's first change and after its last
+    pub pre_hook: Option<Hook>,
+    pub post_hook: Option<Hook>,
+}
+
+/// A shell command from a group's `hooks`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    /// Its key in `hooks`, e.g. `post_export`
+    pub name: &'static str,
+    pub command: String,
 }
 
 impl GroupPlan {
+    /// Name of the group in the config; groups exported for several users
+    /// are named `group (user)`.
+    pub fn config_name(&self) -> &str {
+        self.owner
+            .as_ref()
+            .and_then(|owner| self.group_name.strip_suffix(&format!(" ({})", owner.name)))
+            .unwrap_or(&self.group_name)
+    }
+
     pub fn has_changes(&self) -> bool {
         self.entries.iter().any(|e| e.status != FileStatus::Same)
     }
@@ -34,7 +139,7 @@ impl GroupPlan {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Plan {
     pub groups: Vec<GroupPlan>,
 }
@@ -48,9 +153,73 @@ impl Plan {
         self.groups.push(GroupPlan {
             group_name,
             entries,
+            owner: None,
+            skipped: None,
+            sensitive: false,
+            pre_hook: None,
+            post_hook: None,
+        });
+    }
+
+    /// Lists `group_name` as left out of the run for `reason`.
+    pub fn add_skipped(&mut self, group_name: String, reason: String) {
+        self.groups.push(GroupPlan {
+            group_name,
+            entries: Vec::new(),
+            owner: None,
+            skipped: Some(reason),
+            sensitive: false,
+            pre_hook: None,
+            post_hook: None,
+        });
+    }
+
+    pub fn add_owned_group(&mut self, group_name: String, entries: Vec<FileEntry>, owner: User) {
+        self.groups.push(GroupPlan {
+            group_name,
+            entries,
+            owner: Some(owner),
+            skipped: None,
+            sensitive: false,
+            pre_hook: None,
+            post_hook: None,
         });
     }
 
+    /// Flags the groups that are `sensitive` in `config`.
+    pub fn mark_sensitive(&mut self, config: &Config) {
+        for group in &mut self.groups {
+            group.sensitive = config
+                .groups
+                .get(group.config_name())
+                .is_some_and(|g| g.sensitive);
+        }
+    }
+
+    /// Gives each group the `hooks` from `config` for an import or export.
+    pub fn attach_hooks(&mut self, config: &Config, import: bool) {
+        for group in &mut self.groups {
+            let Some(hooks) = config.groups.get(group.config_name()).map(|g| &g.hooks) else {
+                continue;
+            };
+            let (pre, post) = match import {
+                true => (
+                    ("pre_import", &hooks.pre_import),
+                    ("post_import", &hooks.post_import),
+                ),
+                false => (
+                    ("pre_export", &hooks.pre_export),
+                    ("post_export", &hooks.post_export),
+                ),
+            };
+            let hook = |(name, command): (&'static str, &Option<String>)| {
+                command.clone().map(|command| Hook { name, command })
+            };
+            group.pre_hook = hook(pre);
+            group.post_hook = hook(post);
+        }
+    }
+
     pub fn has_changes(&self) -> bool {
         self.groups.iter().any(|g| g.has_changes())
     }
@@ -63,7 +232,456 @@ impl Plan {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.groups.iter().all(|g| g.entries.is_empty())
+        self.groups
+            .iter()
+            .all(|g| g.entries.is_empty() && g.skipped.is_none())
+    }
+}
+
+/// A repo directory contributing files to a group. Files in later layers
+/// shadow files with the same path in earlier ones.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// Shown next to files from this layer; `None` for the group's own directory
+    pub label: Option<String>,
+    pub dir: PathBuf,
+}
+
+impl Layer {
+    pub fn own(dir: PathBuf) -> Self {
+        Self { label: None, dir }
+    }
+}
+
+/// Returns the layers of `group_name` in the repo at `repo`, lowest priority
+/// first: the directories of the groups it `extends`, the group's own
+/// directory, then its directory in `overrides`.
+pub fn group_layers(config: &Config, group_name: &str, repo: &Path) -> Result<Vec<Layer>> {
+    let mut layers = vec![Layer::own(repo.join(group_name))];
+
+    let mut chain = vec![group_name];
+    while let Some(base) = config.get_group(chain[chain.len() - 1])?.extends.as_deref() {
+        if chain.contains(&base) {
+            anyhow::bail!("Group '{}' extends itself via '{}'", group_name, base);
+        }
+        chain.push(base);
+        layers.insert(
+            0,
+            Layer {
+                label: Some(base.to_string()),
+                dir: repo.join(base),
+            },
+        );
+    }
+
+    if let Some(overrides) = config.overrides_dir()? {
+        layers.push(Layer {
+            label: Some("override".to_string()),
+            dir: overrides.join(group_name),
+        });
+    }
+
+    Ok(layers)
+}
+
+/// Returns the group's own directory among `layers`.
+fn own_dir(layers: &[Layer]) -> &Path {
+    layers
+        .iter()
+        .find(|l| l.label.is_none())
+        .or(layers.first())
+        .map_or(Path::new(""), |l| &l.dir)
+}
+
+/// Fails if `path` on the system side of a group lies inside one of the
+/// group's repo directories, or is the repo itself or a directory in it that
+/// contains them, where syncing would copy files onto themselves or delete
+/// them from the repo. Ancestors outside the repo, like `~` for a repo in
+/// `~/dotfiles`, are fine.
+fn check_overlap(layers: &[Layer], path: &Path) -> Result<()> {
+    if crate::store::is_pseudo_path(path) {
+        return Ok(());
+    }
+    let path = real_path(path);
+    for layer in layers {
+        let dir = real_path(&layer.dir);
+        let repo = dir.parent().unwrap_or(&dir);
+        if path.starts_with(&dir) || (dir.starts_with(&path) && path.starts_with(repo)) {
+            return Err(DootError::Conflict(format!(
+                "{} overlaps the repo directory {}, so files would be synced onto themselves. \
+                 Check that the resolver doesn't expand to the repo or a group directory.",
+                path.display(),
+                dir.display()
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// `path` with symlinks and `..` resolved as far as it exists.
+fn real_path(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return missing.iter().rev().fold(real, |p, name| p.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Parses `dir_modes` (directory relative to the resolved path, `.` for the
+/// path itself, to an octal mode like `"700"`), outermost directories first.
+pub fn parse_dir_modes(dir_modes: &BTreeMap<String, String>) -> Result<Vec<(PathBuf, u32)>> {
+    let mut parsed = dir_modes
+        .iter()
+        .map(|(dir, mode)| {
+            let mode = u32::from_str_radix(mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .with_context(|| format!("Invalid mode '{}' for directory '{}'", mode, dir))?;
+            let dir: PathBuf = Path::new(dir)
+                .components()
+                .filter(|c| !matches!(c, Component::CurDir))
+                .collect();
+            Ok((dir, mode))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    parsed.sort_by_key(|(dir, _)| dir.components().count());
+    Ok(parsed)
+}
+
+/// Joins a directory from `dir_modes` onto `root`, without a trailing
+/// separator for the root itself.
+pub fn join_dir(root: &Path, dir: &Path) -> PathBuf {
+    if dir.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(dir)
+    }
+}
+
+/// Returns why `group_name` is left out of a plan run with `resolver`, if it
+/// is: the group has no such resolver, the mount the resolver requires is
+/// absent, or the resolver is optional and doesn't resolve to an existing path
+/// on this machine.
+pub fn skip_reason(config: &Config, group_name: &str, resolver: &str) -> Result<Option<String>> {
+    let group = config.get_group(group_name)?;
+    if !group.resolvers.contains_key(resolver) {
+        return Ok(Some(format!("no resolver '{}'", resolver)));
+    }
+    if let Some(reason) = missing_mount(config, group_name, resolver)? {
+        return Ok(Some(reason));
+    }
+    if !group.optional_resolvers.contains(resolver) {
+        return Ok(None);
+    }
+
+    Ok(match config.resolve_group_path(group_name, resolver) {
+        Err(e) => Some(format!("{:#}", e)),
+        Ok(path)
+            if group.kind == GroupKind::Files
+                && !crate::store::is_pseudo_path(&path)
+                && !path.exists() =>
+        {
+            Some(format!("{} does not exist", path.display()))
+        }
+        Ok(_) => None,
+    })
+}
+
+/// Returns why `resolver` of `group_name` can't be used, if its
+/// `requires_mount` mount point has nothing mounted on it.
+pub fn missing_mount(config: &Config, group_name: &str, resolver: &str) -> Result<Option<String>> {
+    let Some(mount) = config.get_group(group_name)?.requires_mount.get(resolver) else {
+        return Ok(None);
+    };
+    let mount = crate::resolver::resolve_path(mount)?;
+    Ok((!is_mount_point(&mount)).then(|| format!("{} is not mounted", mount.display())))
+}
+
+/// Whether a filesystem is mounted on `path`: it's on another device than
+/// its parent directory.
+#[cfg(unix)]
+fn is_mount_point(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let parent = path.parent().unwrap_or(path);
+    match (std::fs::metadata(path), std::fs::metadata(parent)) {
+        (Ok(path), Ok(parent)) => path.dev() != parent.dev() || path.ino() == parent.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_mount_point(path: &Path) -> bool {
+    path.exists()
+}
+
+/// The entries exporting `group_name` with `resolver`, from the group
+/// directories in the current directory.
+pub fn export_entries(
+    config: &Config,
+    builder: &PlanBuilder,
+    group_name: &str,
+    resolver: &str,
+) -> Result<Vec<FileEntry>> {
+    let group = config.get_group(group_name)?;
+    let resolved_path = config.resolve_group_path(group_name, resolver)?;
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    let layers = group_layers(config, group_name, &repo)?;
+
+    let entries = builder.build_group_export(group, resolver, &layers, &resolved_path)?;
+    check_parents(group, &entries)?;
+    Ok(entries)
+}
+
+/// Fails if an export of `group` would have to create the directory of one
+/// of `entries` when its `create_parents` is `never`.
+pub fn check_parents(group: &Group, entries: &[FileEntry]) -> Result<()> {
+    if group.create_parents != CreateParents::Never {
+        return Ok(());
+    }
+    for entry in entries.iter().filter(|e| e.status == FileStatus::Create) {
+        if crate::store::is_pseudo_path(&entry.destination) {
+            continue;
+        }
+        if let Some(parent) = entry.destination.parent().filter(|p| !p.exists()) {
+            return Err(DootError::Conflict(format!(
+                "Directory missing: {} (create_parents is never)",
+                parent.display()
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Name of the file holding the desired crontab in a `crontab` group.
+pub const CRONTAB_FILE: &str = "crontab";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Import,
+    Export,
+}
+
+/// A destination file assembled from repo fragments (`concat`).
+struct ConcatRule {
+    target: PathBuf,
+    pattern: String,
+    fragments: PatternMatcher<()>,
+    /// Whether a generated header goes on top; split files are joined as is
+    header: bool,
+}
+
+/// A destination file sourcing the exported files matching a pattern
+/// (`loaders`).
+struct LoaderRule {
+    target: PathBuf,
+    pattern: String,
+    files: PatternMatcher<()>,
+}
+
+/// A destination file split into repo fragments on import (`split`).
+struct SplitRule {
+    target: PathBuf,
+    marker: String,
+    into: PathBuf,
+}
+
+/// Files matching `first` are written before those matching `then`
+/// (`order`).
+struct OrderRule {
+    first: PatternMatcher<()>,
+    then: PatternMatcher<()>,
+}
+
+/// Per-file rules of a group, compiled once per plan.
+struct GroupRules {
+    direction: Direction,
+    readonly: bool,
+    hidden: bool,
+    filename_style: FilenameStyle,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    /// Explicit list of files replacing the directory walk
+    files: Option<Vec<PathBuf>>,
+    /// Name used in the markers of managed blocks
+    block_name: String,
+    blocks: PatternMatcher<()>,
+    create_only: PatternMatcher<()>,
+    compare: PatternMatcher<CompareMode>,
+    managed_keys: PatternMatcher<Vec<String>>,
+    merge: PatternMatcher<MergeStrategy>,
+    notes: PatternMatcher<String>,
+    format: PatternMatcher<String>,
+    /// Directories relative to the resolved path and their modes
+    dir_modes: Vec<(PathBuf, u32)>,
+    concat: Vec<ConcatRule>,
+    loaders: Vec<LoaderRule>,
+    split: Vec<SplitRule>,
+    order: Vec<OrderRule>,
+}
+
+impl GroupRules {
+    fn new(group: &Group, group_dir: &Path, direction: Direction) -> Result<Self> {
+        Ok(Self {
+            direction,
+            readonly: group.readonly && direction == Direction::Export,
+            hidden: group.hidden_attribute && direction == Direction::Export,
+            filename_style: group.filename_style,
+            max_depth: group.max_depth,
+            follow_symlinks: group.follow_symlinks,
+            files: group
+                .files
+                .as_ref()
+                .map(|files| files.iter().map(PathBuf::from).collect()),
+            block_name: group_dir
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            blocks: PatternMatcher::from_list(&group.blocks)?,
+            create_only: PatternMatcher::from_list(&group.create_only)?,
+            compare: PatternMatcher::new(&group.compare)?,
+            managed_keys: PatternMatcher::new(&group.managed_keys)?,
+            merge: PatternMatcher::new(&group.merge)?,
+            notes: PatternMatcher::new(&group.notes)?,
+            format: PatternMatcher::new(&group.format)?,
+            dir_modes: match direction {
+                Direction::Export => parse_dir_modes(&group.dir_modes)?,
+                Direction::Import => Vec::new(),
+            },
+            concat: group
+                .concat
+                .iter()
+                .map(|(target, pattern)| (target, pattern.clone(), true))
+                .chain(group.split.iter().map(|(target, split)| {
+                    let pattern = format!("/{}/", split.into.trim_matches('/'));
+                    (target, pattern, false)
+                }))
+                .map(|(target, pattern, header)| {
+                    Ok(ConcatRule {
+                        target: PathBuf::from(target),
+                        fragments: PatternMatcher::from_list(std::slice::from_ref(&pattern))?,
+                        pattern,
+                        header,
+                    })
+                })
+                .collect::<Result<_>>()?,
+            loaders: group
+                .loaders
+                .iter()
+                .map(|(target, pattern)| {
+                    Ok(LoaderRule {
+                        target: PathBuf::from(target),
+                        files: PatternMatcher::from_list(std::slice::from_ref(pattern))?,
+                        pattern: pattern.clone(),
+                    })
+                })
+                .collect::<Result<_>>()?,
+            split: group
+                .split
+                .iter()
+                .map(|(target, split)| SplitRule {
+                    target: PathBuf::from(target),
+                    marker: split.marker.clone(),
+                    into: PathBuf::from(split.into.trim_matches('/')),
+                })
+                .collect(),
+            order: group
+                .order
+                .iter()
+                .flat_map(|(pattern, order)| {
+                    let before = order.before.iter().map(move |other| (pattern, other));
+                    let after = order.after.iter().map(move |other| (other, pattern));
+                    before.chain(after)
+                })
+                .map(|(first, then)| {
+                    Ok(OrderRule {
+                        first: PatternMatcher::from_list(std::slice::from_ref(first))?,
+                        then: PatternMatcher::from_list(std::slice::from_ref(then))?,
+                    })
+                })
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Puts `entries`, sorted by path, in an order that satisfies the `order`
+    /// rules: each file goes at the first free spot in path order once the
+    /// files it waits for are placed. The executor writes them in this order.
+    fn order(&self, entries: Vec<FileEntry>) -> Result<Vec<FileEntry>> {
+        if self.order.is_empty() {
+            return Ok(entries);
+        }
+        // Files that must be written before each file.
+        let mut waits = vec![0; entries.len()];
+        let mut unblocks = vec![Vec::new(); entries.len()];
+        for rule in &self.order {
+            for (i, first) in entries.iter().enumerate() {
+                if !rule.first.matches(&first.relative_path) {
+                    continue;
+                }
+                for (j, then) in entries.iter().enumerate() {
+                    if i != j && rule.then.matches(&then.relative_path) {
+                        unblocks[i].push(j);
+                        waits[j] += 1;
+                    }
+                }
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..entries.len()).filter(|i| waits[*i] == 0).collect();
+        let mut sequence = Vec::with_capacity(entries.len());
+        while let Some(i) = ready.pop_first() {
+            sequence.push(i);
+            for &j in &unblocks[i] {
+                waits[j] -= 1;
+                if waits[j] == 0 {
+                    ready.insert(j);
+                }
+            }
+        }
+        if let Some(stuck) = (0..entries.len()).find(|i| waits[*i] > 0) {
+            return Err(DootError::Config(format!(
+                "The `order` rules of group '{}' form a cycle through {}",
+                self.block_name,
+                entries[stuck].relative_path.display()
+            ))
+            .into());
+        }
+
+        let mut entries: Vec<Option<FileEntry>> = entries.into_iter().map(Some).collect();
+        Ok(sequence
+            .into_iter()
+            .filter_map(|i| entries[i].take())
+            .collect())
+    }
+
+    /// Configured directories at or above `relative` under `root`, as
+    /// absolute paths.
+    fn dir_modes_above(&self, root: &Path, relative: &Path) -> Vec<(PathBuf, u32)> {
+        let parent = relative.parent().unwrap_or(Path::new(""));
+        self.dir_modes
+            .iter()
+            .filter(|(dir, _)| parent.starts_with(dir))
+            .map(|(dir, mode)| (join_dir(root, dir), *mode))
+            .collect()
+    }
+
+    /// Whether `relative` is generated on export rather than synced.
+    fn is_generated_target(&self, relative: &Path) -> bool {
+        self.concat
+            .iter()
+            .any(|rule| rule.header && rule.target == relative)
+            || self.loaders.iter().any(|rule| rule.target == relative)
     }
 }
 
@@ -71,135 +689,760 @@ pub struct PlanBuilder<'a> {
     store: &'a dyn Store,
 }
 
+/// Builds the entries of each of `items` on rayon's thread pool, since
+/// hashing thousands of files one by one is what makes large groups slow.
+/// Errors are reported for the first failing item, as a sequential walk
+/// would.
+fn in_parallel<T: Sync>(
+    items: &[T],
+    build: impl Fn(&T) -> Result<Vec<FileEntry>> + Sync + Send,
+) -> Result<Vec<FileEntry>> {
+    let built: Vec<Result<Vec<FileEntry>>> = items.par_iter().map(build).collect();
+    let mut entries = Vec::new();
+    for result in built {
+        entries.extend(result?);
+    }
+    Ok(entries)
+}
+
 impl<'a> PlanBuilder<'a> {
     pub fn new(store: &'a dyn Store) -> Self {
         Self { store }
     }
 
-    pub fn build_import(
+    pub fn build_group_import(
         &self,
-        group_dir: &Path,
+        group: &Group,
+        layers: &[Layer],
         resolved_path: &Path,
         ignore_file: &Path,
     ) -> Result<Vec<FileEntry>> {
-        let mut entries = Vec::new();
-
-        let mut builder = WalkBuilder::new(resolved_path);
-        builder.standard_filters(false);
-        builder.add_ignore(ignore_file);
-        let walker = builder.build();
+        let group_dir = own_dir(layers);
+        match group.kind {
+            GroupKind::Files => {
+                check_overlap(layers, resolved_path)?;
+                let rules = GroupRules::new(group, group_dir, Direction::Import)?;
+                let entries = rules.order(self.build_import(
+                    &rules,
+                    layers,
+                    resolved_path,
+                    ignore_file,
+                )?)?;
+                for entry in &entries {
+                    check_overlap(layers, &entry.source)?;
+                }
+                Ok(entries)
+            }
+            GroupKind::Crontab => {
+                let source = crontab::path_for(&resolved_path.to_string_lossy());
+                if !self.store.exists(&source) {
+                    return Ok(Vec::new());
+                }
+                Ok(vec![
+                    self.crontab_entry(source, group_dir.join(CRONTAB_FILE))
+                ])
+            }
+            // Env files are generated from doot.yaml, so there is nothing to import.
+            GroupKind::Env => Ok(Vec::new()),
+        }
+    }
 
-        for entry in walker.filter_map(|e| e.ok()) {
-            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-                continue;
+    pub fn build_group_export(
+        &self,
+        group: &Group,
+        resolver: &str,
+        layers: &[Layer],
+        resolved_path: &Path,
+    ) -> Result<Vec<FileEntry>> {
+        let group_dir = own_dir(layers);
+        match group.kind {
+            GroupKind::Files => {
+                check_overlap(layers, resolved_path)?;
+                let rules = GroupRules::new(group, group_dir, Direction::Export)?;
+                let entries =
+                    rules.order(self.build_export(&rules, resolver, layers, resolved_path)?)?;
+                for entry in &entries {
+                    check_overlap(layers, &entry.destination)?;
+                }
+                Ok(entries)
+            }
+            GroupKind::Crontab => {
+                let source = group_dir.join(CRONTAB_FILE);
+                if !self.store.exists(&source) {
+                    return Ok(Vec::new());
+                }
+                let destination = crontab::path_for(&resolved_path.to_string_lossy());
+                Ok(vec![self.crontab_entry(source, destination)])
             }
+            GroupKind::Env => {
+                let variables = group.variables.get(resolver).cloned().unwrap_or_default();
+                let content = env_file::render(&variables);
+                Ok(vec![self.generated_entry(
+                    group_dir,
+                    resolved_path,
+                    content,
+                )])
+            }
+        }
+    }
 
-            let full_path = entry.path();
-            let relative = full_path.strip_prefix(resolved_path)?;
+    fn generated_entry(&self, group_dir: &Path, destination: &Path, content: Vec<u8>) -> FileEntry {
+        let relative_path = PathBuf::from(destination.file_name().unwrap_or_default());
+        let status = self.compute_generated_status(&content, destination);
+        FileEntry {
+            source: group_dir.join(&relative_path),
+            relative_path,
+            destination: destination.to_path_buf(),
+            status,
+            generated: Some(content),
+            create_only: false,
+            readonly: false,
+            hidden: false,
+            repo_path: None,
+            layer: None,
+            dir_modes: Vec::new(),
+            note: None,
+        }
+    }
 
-            let destination = group_dir.join(relative);
-            let status = self.compute_status(full_path, &destination);
+    fn crontab_entry(&self, source: PathBuf, destination: PathBuf) -> FileEntry {
+        let status = self.compute_status(&source, &destination);
+        FileEntry {
+            relative_path: PathBuf::from(CRONTAB_FILE),
+            source,
+            destination,
+            status,
+            generated: None,
+            create_only: false,
+            readonly: false,
+            hidden: false,
+            repo_path: None,
+            layer: None,
+            dir_modes: Vec::new(),
+            note: None,
+        }
+    }
 
-            entries.push(FileEntry {
-                relative_path: relative.to_path_buf(),
-                source: full_path.to_path_buf(),
-                destination,
-                status,
-            });
+    fn build_import(
+        &self,
+        rules: &GroupRules,
+        layers: &[Layer],
+        resolved_path: &Path,
+        ignore_file: &Path,
+    ) -> Result<Vec<FileEntry>> {
+        if let Some(files) = &rules.files {
+            return self.build_listed(rules, files, layers, resolved_path);
         }
 
+        // Match the group's ignore file relative to the resolved path, so that
+        // anchored patterns like `/.config/nvim` mean the same as on export.
+        let mut ignore = GitignoreBuilder::new(resolved_path);
+        ignore.add(ignore_file);
+        let ignore = ignore.build()?;
+
+        let files: Vec<PathBuf> = if container::is_container_path(resolved_path) {
+            container::list_files(resolved_path)?
+                .into_iter()
+                .map(|relative| resolved_path.join(relative))
+                .filter(|path| !ignore.matched_path_or_any_parents(path, false).is_ignore())
+                .collect()
+        } else {
+            let mut builder = WalkBuilder::new(resolved_path);
+            builder
+                .standard_filters(false)
+                .max_depth(rules.max_depth)
+                .follow_links(rules.follow_symlinks);
+            builder.filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+                !ignore.matched(entry.path(), is_dir).is_ignore()
+            });
+            builder
+                .build()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+                .map(|e| e.into_path())
+                .collect()
+        };
+
+        let mut entries = in_parallel(&files, |full_path| {
+            let relative = full_path.strip_prefix(resolved_path)?;
+            // Generated on export; there is no repo copy to update.
+            if rules.is_generated_target(relative) {
+                return Ok(Vec::new());
+            }
+            if let Some(rule) = rules.split.iter().find(|rule| rule.target == relative) {
+                return self.split_entries(rules, rule, layers, full_path);
+            }
+            let repo_relative = naming::to_repo(rules.filename_style, relative);
+            if let Some(entry) =
+                self.encrypted_entry(layers, relative, &repo_relative, full_path)?
+            {
+                return Ok(vec![entry]);
+            }
+            if let Some(entry) = self.templated_entry(layers, relative, &repo_relative, full_path) {
+                return Ok(vec![entry]);
+            }
+            let layer = self.import_layer(layers, &repo_relative);
+            let destination = layer.dir.join(repo_relative);
+            let entry = self.file_entry(rules, relative, full_path, destination)?;
+            Ok(entry
+                .map(|entry| FileEntry {
+                    layer: layer.label.clone(),
+                    ..entry
+                })
+                .into_iter()
+                .collect())
+        })?;
+
         entries.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
         Ok(entries)
     }
 
-    pub fn build_export(&self, group_dir: &Path, resolved_path: &Path) -> Result<Vec<FileEntry>> {
-        let mut entries = Vec::new();
+    fn build_export(
+        &self,
+        rules: &GroupRules,
+        resolver: &str,
+        layers: &[Layer],
+        resolved_path: &Path,
+    ) -> Result<Vec<FileEntry>> {
+        if let Some(files) = &rules.files {
+            return self.build_listed(rules, files, layers, resolved_path);
+        }
 
-        let walker = WalkBuilder::new(group_dir)
-            .standard_filters(false)
-            .add_custom_ignore_filename(".dootignore")
-            .build();
+        // Later layers shadow files of earlier ones.
+        let mut sources = BTreeMap::new();
+        for layer in layers {
+            let walker = WalkBuilder::new(&layer.dir)
+                .standard_filters(false)
+                .max_depth(rules.max_depth)
+                .follow_links(rules.follow_symlinks)
+                .add_custom_ignore_filename(".dootignore")
+                .build();
 
-        for entry in walker.filter_map(|e| e.ok()) {
-            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-                continue;
-            }
+            for entry in walker.filter_map(|e| e.ok()) {
+                if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+                    continue;
+                }
 
-            let full_path = entry.path();
-            let relative = full_path.strip_prefix(group_dir)?;
+                let repo_relative = entry.path().strip_prefix(&layer.dir)?;
+                let relative = naming::to_destination(rules.filename_style, repo_relative);
+                sources.insert(relative, (entry.into_path(), layer));
+            }
+        }
 
-            let destination = resolved_path.join(relative);
-            let status = self.compute_status(full_path, &destination);
+        let mut entries = Vec::new();
+        for rule in &rules.concat {
+            let fragments: Vec<PathBuf> = sources
+                .keys()
+                .filter(|relative| rule.fragments.matches(relative))
+                .cloned()
+                .collect();
+            let mut parts = Vec::new();
+            for fragment in fragments {
+                if let Some((source, _)) = sources.remove(&fragment) {
+                    parts.push(self.store.read(&source)?);
+                }
+            }
+            entries.push(self.concat_entry(rules, rule, own_dir(layers), resolved_path, &parts));
+        }
 
-            entries.push(FileEntry {
-                relative_path: relative.to_path_buf(),
-                source: full_path.to_path_buf(),
-                destination,
-                status,
-            });
+        for rule in &rules.loaders {
+            let files: Vec<PathBuf> = sources
+                .keys()
+                .filter(|relative| **relative != rule.target && rule.files.matches(relative))
+                .map(|relative| {
+                    let target = template::target(relative).or_else(|| age::target(relative));
+                    resolved_path.join(target.as_deref().unwrap_or(relative))
+                })
+                .collect();
+            let content = merge::loader(&rule.target, &rule.pattern, &files);
+            let source = own_dir(layers).join(&rule.target);
+            entries.push(self.rendered_entry(
+                rules,
+                rule.target.clone(),
+                source,
+                resolved_path,
+                content,
+            ));
         }
 
+        let sources: Vec<_> = sources.into_iter().collect();
+        entries.extend(in_parallel(&sources, |(relative, (source, layer))| {
+            let rendered = if let Some(target) = template::target(relative) {
+                let content = template::render(&self.store.read(source)?, resolver)
+                    .with_context(|| format!("Failed to render: {}", source.display()))?;
+                Some((target, content))
+            } else if let Some(target) = age::target(relative) {
+                let content = age::decrypt(&self.store.read(source)?)
+                    .with_context(|| format!("Failed to decrypt: {}", source.display()))?;
+                Some((target, content))
+            } else {
+                None
+            };
+            let entry = match rendered {
+                Some((target, content)) => {
+                    Some(self.rendered_entry(rules, target, source.clone(), resolved_path, content))
+                }
+                None => {
+                    let destination = resolved_path.join(relative);
+                    self.file_entry(rules, relative, source, destination)?
+                }
+            };
+            Ok(entry
+                .map(|entry| FileEntry {
+                    layer: layer.label.clone(),
+                    ..entry
+                })
+                .into_iter()
+                .collect())
+        })?);
         entries.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
         Ok(entries)
     }
 
-    fn compute_status(&self, source: &Path, destination: &Path) -> FileStatus {
-        if !self.store.exists(destination) {
-            FileStatus::Create
-        } else if self.store.compare(source, destination).unwrap_or(false) {
-            FileStatus::Same
+    /// Builds the entry writing `content` generated by doot to `target`, such
+    /// as a rendered template or a decrypted file.
+    fn rendered_entry(
+        &self,
+        rules: &GroupRules,
+        target: PathBuf,
+        source: PathBuf,
+        resolved_path: &Path,
+        content: Vec<u8>,
+    ) -> FileEntry {
+        let destination = resolved_path.join(&target);
+        FileEntry {
+            status: self.compute_generated_status(&content, &destination),
+            source,
+            destination,
+            generated: Some(content),
+            create_only: false,
+            readonly: rules.readonly,
+            hidden: rules.hidden && naming::is_dotfile(&target),
+            repo_path: None,
+            layer: None,
+            dir_modes: rules.dir_modes_above(resolved_path, &target),
+            note: rules.notes.find(&target).cloned(),
+            relative_path: target,
+        }
+    }
+
+    fn concat_entry(
+        &self,
+        rules: &GroupRules,
+        rule: &ConcatRule,
+        group_dir: &Path,
+        resolved_path: &Path,
+        parts: &[Vec<u8>],
+    ) -> FileEntry {
+        let content = if rule.header {
+            merge::concat(&rule.pattern, parts)
         } else {
-            FileStatus::Overwrite
+            parts.concat()
+        };
+        let destination = resolved_path.join(&rule.target);
+        FileEntry {
+            relative_path: rule.target.clone(),
+            source: group_dir.join(&rule.target),
+            status: self.compute_generated_status(&content, &destination),
+            destination,
+            generated: Some(content),
+            create_only: false,
+            readonly: rules.readonly,
+            hidden: rules.hidden && naming::is_dotfile(&rule.target),
+            repo_path: None,
+            layer: None,
+            dir_modes: rules.dir_modes_above(resolved_path, &rule.target),
+            note: rules.notes.find(&rule.target).cloned(),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
+    /// Builds entries writing the fragments of `source` into the group, and
+    /// removing fragments left over from earlier imports.
+    fn split_entries(
+        &self,
+        rules: &GroupRules,
+        rule: &SplitRule,
+        layers: &[Layer],
+        source: &Path,
+    ) -> Result<Vec<FileEntry>> {
+        let dir = own_dir(layers).join(naming::to_repo(rules.filename_style, &rule.into));
+        let mut stale: BTreeSet<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(read_dir) => read_dir
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_ok_and(|ft| ft.is_file()))
+                .map(|e| e.path())
+                .collect(),
+            Err(_) => BTreeSet::new(),
+        };
+
+        let entry = |name: &str, destination: PathBuf, status, generated| FileEntry {
+            relative_path: rule.into.join(name),
+            source: source.to_path_buf(),
+            destination,
+            status,
+            generated,
+            create_only: false,
+            readonly: false,
+            hidden: false,
+            repo_path: None,
+            layer: None,
+            dir_modes: Vec::new(),
+            note: None,
+        };
+
+        let mut entries = Vec::new();
+        for (name, content) in merge::split(&self.store.read(source)?, &rule.marker) {
+            let destination = dir.join(&name);
+            stale.remove(&destination);
+            let status = self.compute_generated_status(&content, &destination);
+            entries.push(entry(&name, destination, status, Some(content)));
+        }
+        for destination in stale {
+            let name = destination
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            entries.push(entry(&name, destination.clone(), FileStatus::Remove, None));
+        }
+        Ok(entries)
+    }
+
+    /// Builds the entry encrypting `source` into the encrypted file it is
+    /// decrypted from, if there is one. Both sides are compared as plaintext.
+    fn encrypted_entry(
+        &self,
+        layers: &[Layer],
+        relative: &Path,
+        repo_relative: &Path,
+        source: &Path,
+    ) -> Result<Option<FileEntry>> {
+        let encrypted = age::source(repo_relative);
+        let Some(layer) = self.export_layer(layers, &encrypted) else {
+            return Ok(None);
+        };
+        let destination = layer.dir.join(&encrypted);
+        let plaintext = self.store.read(source)?;
+        let previous = age::decrypt(&self.store.read(&destination)?)
+            .with_context(|| format!("Failed to decrypt: {}", destination.display()))?;
+        let (status, generated) = if plaintext == previous {
+            (FileStatus::Same, None)
+        } else {
+            (FileStatus::Overwrite, Some(age::encrypt(&plaintext)?))
+        };
+        Ok(Some(FileEntry {
+            relative_path: relative.to_path_buf(),
+            source: source.to_path_buf(),
+            destination,
+            status,
+            generated,
+            create_only: false,
+            readonly: false,
+            hidden: false,
+            repo_path: Some(encrypted),
+            layer: layer.label.clone(),
+            dir_modes: Vec::new(),
+            note: None,
+        }))
+    }
+
+    /// Builds the entry leaving the template `source` is rendered from as it
+    /// is, if there is one: rendered files can't be imported back into it.
+    fn templated_entry(
+        &self,
+        layers: &[Layer],
+        relative: &Path,
+        repo_relative: &Path,
+        source: &Path,
+    ) -> Option<FileEntry> {
+        let template = template::source(repo_relative);
+        let layer = self.export_layer(layers, &template)?;
+        Some(FileEntry {
+            relative_path: relative.to_path_buf(),
+            source: source.to_path_buf(),
+            destination: layer.dir.join(&template),
+            status: FileStatus::Same,
+            generated: None,
+            create_only: false,
+            readonly: false,
+            hidden: false,
+            repo_path: Some(template.clone()),
+            layer: layer.label.clone(),
+            dir_modes: Vec::new(),
+            note: Some(format!(
+                "Rendered from {}; edit the template instead",
+                template.display()
+            )),
+        })
+    }
 
-    struct MockStore {
-        files: HashMap<PathBuf, Vec<u8>>,
+    /// Returns the layer an imported file is written to: the layer it already
+    /// comes from, if that is the group's own directory or one shadowing it.
+    fn import_layer<'l>(&self, layers: &'l [Layer], relative: &Path) -> &'l Layer {
+        let own = layers.iter().position(|l| l.label.is_none()).unwrap_or(0);
+        layers[own..]
+            .iter()
+            .rev()
+            .find(|l| self.store.exists(&l.dir.join(relative)))
+            .unwrap_or(&layers[own])
+    }
+
+    /// Returns the layer `relative` is exported from.
+    fn export_layer<'l>(&self, layers: &'l [Layer], relative: &Path) -> Option<&'l Layer> {
+        layers
+            .iter()
+            .rev()
+            .find(|l| self.store.exists(&l.dir.join(relative)))
     }
 
-    impl MockStore {
-        fn new() -> Self {
-            Self {
-                files: HashMap::new(),
+    /// Builds entries for an explicit file list. Every listed file must exist.
+    fn build_listed(
+        &self,
+        rules: &GroupRules,
+        files: &[PathBuf],
+        layers: &[Layer],
+        resolved_path: &Path,
+    ) -> Result<Vec<FileEntry>> {
+        let mut entries = Vec::new();
+
+        for relative in files {
+            let repo_relative = naming::to_repo(rules.filename_style, relative);
+            let (source, destination, layer) = match rules.direction {
+                Direction::Export => {
+                    let layer = self.export_layer(layers, &repo_relative).with_context(|| {
+                        format!(
+                            "Listed file is missing: {}",
+                            own_dir(layers).join(&repo_relative).display()
+                        )
+                    })?;
+                    (
+                        layer.dir.join(&repo_relative),
+                        resolved_path.join(relative),
+                        layer,
+                    )
+                }
+                Direction::Import => {
+                    let source = resolved_path.join(relative);
+                    anyhow::ensure!(
+                        self.store.exists(&source),
+                        "Listed file is missing: {}",
+                        source.display()
+                    );
+                    let layer = self.import_layer(layers, &repo_relative);
+                    (source, layer.dir.join(&repo_relative), layer)
+                }
+            };
+
+            if let Some(mut entry) = self.file_entry(rules, relative, &source, destination)? {
+                entry.layer = layer.label.clone();
+                entries.push(entry);
             }
         }
 
-        fn with_file(mut self, path: &str, content: &[u8]) -> Self {
-            self.files.insert(PathBuf::from(path), content.to_vec());
-            self
-        }
+        entries.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        Ok(entries)
     }
 
-    impl Store for MockStore {
-        fn name(&self) -> &'static str {
-            "mock"
+    fn file_entry(
+        &self,
+        rules: &GroupRules,
+        relative: &Path,
+        source: &Path,
+        destination: PathBuf,
+    ) -> Result<Option<FileEntry>> {
+        let create_only = rules.create_only.matches(relative);
+        if create_only && self.store.exists(&destination) {
+            return Ok(Some(FileEntry {
+                relative_path: relative.to_path_buf(),
+                source: source.to_path_buf(),
+                destination,
+                status: FileStatus::Same,
+                generated: None,
+                create_only,
+                readonly: false,
+                hidden: false,
+                repo_path: None,
+                layer: None,
+                dir_modes: Vec::new(),
+                note: None,
+            }));
         }
 
-        fn read(&self, path: &Path) -> Result<Vec<u8>> {
-            self.files
-                .get(path)
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("File not found"))
+        let merged = if rules.blocks.matches(relative) {
+            let name = &rules.block_name;
+            let content = match rules.direction {
+                Direction::Export => self.merge_into(source, &destination, |base, block| {
+                    Ok(merge::insert_block(name, base, block))
+                })?,
+                Direction::Import => {
+                    // Files without the block have nothing to import.
+                    let Some(block) = merge::extract_block(name, &self.store.read(source)?) else {
+                        return Ok(None);
+                    };
+                    block
+                }
+            };
+            Some((content, None))
+        } else if let Some(keys) = rules.managed_keys.find(relative) {
+            let format = merge::Format::from_path(relative);
+            let content = self.merge_into(source, &destination, |base, from| {
+                merge::managed(format, base, from, keys)
+            })?;
+            Some((content, format.compare_mode()))
+        } else if let Some(strategy) = rules
+            .merge
+            .find(relative)
+            .filter(|_| rules.direction == Direction::Export)
+        {
+            // Merging only applies on export; imports capture the whole file.
+            let content = self.merge_into(source, &destination, |base, from| {
+                merge::overlay((*strategy).into(), base, from)
+            })?;
+            Some((content, None))
+        } else {
+            None
+        };
+
+        // Formatters only apply on import, so the repo copy stays formatted
+        // however the system copy was edited.
+        let merged = match rules
+            .format
+            .find(relative)
+            .filter(|_| rules.direction == Direction::Import)
+        {
+            Some(formatter) => {
+                let (content, mode) = match merged {
+                    Some(merged) => merged,
+                    None => (
+                        self.store.read(source)?,
+                        rules.compare.find(relative).copied(),
+                    ),
+                };
+                let formatted = process::filter(formatter, &content)
+                    .with_context(|| format!("Failed to format: {}", source.display()))?;
+                Some((formatted, mode))
+            }
+            None => merged,
+        };
+
+        let (status, generated) = match merged {
+            Some((content, mode)) => {
+                let status = self.compute_content_status(&content, &destination, mode);
+                (status, Some(content))
+            }
+            None => {
+                let mode = rules.compare.find(relative).copied();
+                (self.compute_status_with(source, &destination, mode), None)
+            }
+        };
+
+        let dir_modes = destination
+            .ancestors()
+            .nth(relative.components().count())
+            .map(|root| rules.dir_modes_above(root, relative))
+            .unwrap_or_default();
+
+        Ok(Some(FileEntry {
+            relative_path: relative.to_path_buf(),
+            source: source.to_path_buf(),
+            destination,
+            status,
+            generated,
+            create_only,
+            readonly: rules.readonly,
+            hidden: rules.hidden && naming::is_dotfile(relative),
+            repo_path: Some(naming::to_repo(rules.filename_style, relative))
+                .filter(|repo_path| repo_path != relative),
+            layer: None,
+            dir_modes,
+            note: rules.notes.find(relative).cloned(),
+        }))
+    }
+
+    /// Merges `source` into the current content of `destination` with `merge`.
+    fn merge_into(
+        &self,
+        source: &Path,
+        destination: &Path,
+        merge: impl FnOnce(Option<&[u8]>, &[u8]) -> Result<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        let existing = if self.store.exists(destination) {
+            Some(self.store.read(destination)?)
+        } else {
+            None
+        };
+
+        merge(existing.as_deref(), &self.store.read(source)?)
+            .with_context(|| format!("Failed to merge: {}", source.display()))
+    }
+
+    fn compute_content_status(
+        &self,
+        content: &[u8],
+        destination: &Path,
+        mode: Option<CompareMode>,
+    ) -> FileStatus {
+        let Some(mode) = mode else {
+            return self.compute_generated_status(content, destination);
+        };
+
+        if !self.store.exists(destination) {
+            return FileStatus::Create;
         }
+        match self.store.read(destination) {
+            Ok(existing) if compare::equivalent(mode, content, &existing) => FileStatus::Same,
+            _ => FileStatus::Overwrite,
+        }
+    }
 
-        fn write(&self, _path: &Path, _content: &[u8]) -> Result<()> {
-            Ok(())
+    fn compute_generated_status(&self, content: &[u8], destination: &Path) -> FileStatus {
+        if !self.store.exists(destination) {
+            FileStatus::Create
+        } else if self
+            .store
+            .read(destination)
+            .is_ok_and(|existing| existing == content)
+        {
+            FileStatus::Same
+        } else {
+            FileStatus::Overwrite
         }
+    }
+
+    fn compute_status_with(
+        &self,
+        source: &Path,
+        destination: &Path,
+        mode: Option<CompareMode>,
+    ) -> FileStatus {
+        let Some(mode) = mode else {
+            return self.compute_status(source, destination);
+        };
 
-        fn exists(&self, path: &Path) -> bool {
-            self.files.contains_key(path)
+        if !self.store.exists(destination) {
+            return FileStatus::Create;
+        }
+        match (self.store.read(source), self.store.read(destination)) {
+            (Ok(a), Ok(b)) if compare::equivalent(mode, &a, &b) => FileStatus::Same,
+            _ => FileStatus::Overwrite,
         }
+    }
 
-        fn remove(&self, _path: &Path) -> Result<()> {
-            Ok(())
+    fn compute_status(&self, source: &Path, destination: &Path) -> FileStatus {
+        if !self.store.exists(destination) {
+            FileStatus::Create
+        } else if self.store.compare(source, destination).unwrap_or(false) {
+            FileStatus::Same
+        } else {
+            FileStatus::Overwrite
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{entry, MemoryStore};
 
     #[test]
     fn plan_tracks_changes_across_groups() {
@@ -207,22 +1450,17 @@ mod tests {
 
         plan.add_group(
             "group1".to_string(),
-            vec![FileEntry {
-                relative_path: PathBuf::from("file1"),
-                source: PathBuf::from("/src/file1"),
-                destination: PathBuf::from("/dst/file1"),
-                status: FileStatus::Same,
-            }],
+            vec![entry("file1", "/src/file1", "/dst/file1", FileStatus::Same)],
         );
 
         plan.add_group(
             "group2".to_string(),
-            vec![FileEntry {
-                relative_path: PathBuf::from("file2"),
-                source: PathBuf::from("/src/file2"),
-                destination: PathBuf::from("/dst/file2"),
-                status: FileStatus::Create,
-            }],
+            vec![entry(
+                "file2",
+                "/src/file2",
+                "/dst/file2",
+                FileStatus::Create,
+            )],
         );
 
         assert!(plan.has_changes());
@@ -230,25 +1468,489 @@ mod tests {
         assert_eq!(plan.total_count_by_status(FileStatus::Create), 1);
     }
 
+    #[test]
+    fn mark_sensitive_follows_config() {
+        let mut config = crate::testing::config(
+            "nux",
+            Path::new("/home/me"),
+            &[("ssh", &["config"]), ("bash", &[".bashrc"])],
+        );
+        config.groups.get_mut("ssh").unwrap().sensitive = true;
+        let alice = User {
+            name: "alice".to_string(),
+            uid: 1000,
+            gid: 1000,
+        };
+
+        let mut plan = Plan::new();
+        plan.add_owned_group("ssh (alice)".to_string(), Vec::new(), alice);
+        plan.add_group("bash".to_string(), Vec::new());
+        plan.mark_sensitive(&config);
+        assert!(plan.groups[0].sensitive);
+        assert!(!plan.groups[1].sensitive);
+    }
+
     #[test]
     fn plan_with_no_changes() {
         let mut plan = Plan::new();
         plan.add_group(
             "group".to_string(),
-            vec![FileEntry {
-                relative_path: PathBuf::from("file"),
-                source: PathBuf::from("/src/file"),
-                destination: PathBuf::from("/dst/file"),
-                status: FileStatus::Same,
-            }],
+            vec![entry("file", "/src/file", "/dst/file", FileStatus::Same)],
         );
 
         assert!(!plan.has_changes());
     }
 
+    #[test]
+    fn order_rules_move_files_ahead() {
+        let order = |rules: &[(&str, Option<&str>, Option<&str>)]| {
+            let group = Group {
+                order: rules
+                    .iter()
+                    .map(|(pattern, before, after)| {
+                        let order = crate::config::Order {
+                            before: before.map(str::to_string),
+                            after: after.map(str::to_string),
+                        };
+                        (pattern.to_string(), order)
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            let rules = GroupRules::new(&group, Path::new("/repo/sh"), Direction::Export).unwrap();
+            let entries = ["a.sh", "env.sh", "rc.sh", "z/late.sh"]
+                .iter()
+                .map(|path| entry(path, path, path, FileStatus::Create))
+                .collect();
+            rules.order(entries).map(|entries| {
+                entries
+                    .iter()
+                    .map(|e| e.relative_path.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            order(&[("z/", Some("a.sh"), None)]).unwrap(),
+            ["env.sh", "rc.sh", "z/late.sh", "a.sh"]
+        );
+        assert_eq!(
+            order(&[("env.sh", None, Some("rc.sh"))]).unwrap(),
+            ["a.sh", "rc.sh", "env.sh", "z/late.sh"]
+        );
+        assert!(order(&[("env.sh", Some("rc.sh"), Some("rc.sh"))]).is_err());
+    }
+
+    #[test]
+    fn create_only_never_overwrites() {
+        let store = MemoryStore::new()
+            .with_file("/repo/zsh/local.zsh", b"# seed\n")
+            .with_file("/home/local.zsh", b"export MACHINE=1\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            create_only: vec!["local.zsh".to_string()],
+            ..Default::default()
+        };
+        let rules = GroupRules::new(&group, Path::new("/repo/zsh"), Direction::Export).unwrap();
+
+        let entry = builder
+            .file_entry(
+                &rules,
+                Path::new("local.zsh"),
+                Path::new("/repo/zsh/local.zsh"),
+                PathBuf::from("/home/local.zsh"),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.status, FileStatus::Same);
+        assert!(entry.create_only);
+
+        let missing = builder
+            .file_entry(
+                &rules,
+                Path::new("local.zsh"),
+                Path::new("/repo/zsh/local.zsh"),
+                PathBuf::from("/other/local.zsh"),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(missing.status, FileStatus::Create);
+    }
+
+    fn own(dir: &str) -> Vec<Layer> {
+        vec![Layer::own(PathBuf::from(dir))]
+    }
+
+    #[test]
+    fn override_layer_shadows_group_dir() {
+        let store = MemoryStore::new()
+            .with_file("/repo/git/.gitconfig", b"[user]\n")
+            .with_file("/mine/git/.gitconfig", b"[user]\n\tname = Me\n")
+            .with_file("/home/.gitconfig", b"[user]\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            files: Some(vec![".gitconfig".to_string()]),
+            ..Default::default()
+        };
+        let mut layers = own("/repo/git");
+        layers.push(Layer {
+            label: Some("override".to_string()),
+            dir: PathBuf::from("/mine/git"),
+        });
+
+        let entries = builder
+            .build_group_export(&group, "nux", &layers, Path::new("/home"))
+            .unwrap();
+        assert_eq!(entries[0].source, PathBuf::from("/mine/git/.gitconfig"));
+        assert_eq!(entries[0].layer.as_deref(), Some("override"));
+        assert_eq!(entries[0].status, FileStatus::Overwrite);
+
+        let entries = builder
+            .build_group_import(&group, &layers, Path::new("/home"), Path::new(""))
+            .unwrap();
+        assert_eq!(
+            entries[0].destination,
+            PathBuf::from("/mine/git/.gitconfig")
+        );
+    }
+
+    #[test]
+    fn extends_puts_base_groups_below_own_dir() {
+        let config = Config::parse(
+            r#"
+version: v1
+groups:
+  shell:
+    nux: "~"
+  bash:
+    nux: "~"
+    extends: shell
+  loop:
+    nux: "~"
+    extends: loop
+"#,
+        )
+        .unwrap();
+
+        let layers = group_layers(&config, "bash", Path::new("/repo")).unwrap();
+        let dirs: Vec<_> = layers.iter().map(|l| l.dir.clone()).collect();
+        assert_eq!(
+            dirs,
+            [PathBuf::from("/repo/shell"), PathBuf::from("/repo/bash")]
+        );
+        assert_eq!(layers[0].label.as_deref(), Some("shell"));
+        assert_eq!(own_dir(&layers), Path::new("/repo/bash"));
+
+        assert!(group_layers(&config, "loop", Path::new("/repo")).is_err());
+    }
+
+    #[test]
+    fn listed_files_replace_walk() {
+        let store = MemoryStore::new()
+            .with_file("/repo/ssh/config", b"Host *\n")
+            .with_file("/repo/ssh/known_hosts", b"");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            files: Some(vec!["config".to_string()]),
+            ..Default::default()
+        };
+
+        let entries = builder
+            .build_group_export(&group, "nux", &own("/repo/ssh"), Path::new("/home/.ssh"))
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].destination, PathBuf::from("/home/.ssh/config"));
+        assert_eq!(entries[0].status, FileStatus::Create);
+
+        let group = Group {
+            files: Some(vec!["id_ed25519".to_string()]),
+            ..Default::default()
+        };
+        let result =
+            builder.build_group_export(&group, "nux", &own("/repo/ssh"), Path::new("/home/.ssh"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn dot_prefix_maps_listed_files() {
+        let store = MemoryStore::new().with_file("/repo/sh/dot_bashrc", b"set -o vi\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            files: Some(vec![".bashrc".to_string()]),
+            filename_style: FilenameStyle::DotPrefix,
+            hidden_attribute: true,
+            ..Default::default()
+        };
+
+        let entries = builder
+            .build_group_export(&group, "nux", &own("/repo/sh"), Path::new("/home"))
+            .unwrap();
+        assert_eq!(entries[0].source, PathBuf::from("/repo/sh/dot_bashrc"));
+        assert_eq!(entries[0].destination, PathBuf::from("/home/.bashrc"));
+        assert!(entries[0].hidden);
+        assert_eq!(entries[0].repo_path, Some(PathBuf::from("dot_bashrc")));
+    }
+
+    #[test]
+    fn notes_attach_to_matching_files() {
+        let store = MemoryStore::new()
+            .with_file("/repo/ssh/.ssh/config", b"Host *\n")
+            .with_file("/repo/ssh/.profile", b"umask 022\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            files: Some(vec![".ssh/config".to_string(), ".profile".to_string()]),
+            notes: BTreeMap::from([(".ssh/*".to_string(), "private".to_string())]),
+            ..Default::default()
+        };
+
+        let entries = builder
+            .build_group_export(&group, "nux", &own("/repo/ssh"), Path::new("/home"))
+            .unwrap();
+        let note = |name: &str| {
+            entries
+                .iter()
+                .find(|e| e.relative_path == Path::new(name))
+                .and_then(|e| e.note.clone())
+        };
+        assert_eq!(note(".ssh/config"), Some("private".to_string()));
+        assert_eq!(note(".profile"), None);
+    }
+
+    #[test]
+    fn dir_modes_apply_to_enclosing_directories() {
+        let store = MemoryStore::new().with_file("/repo/gpg/private/key", b"secret\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            files: Some(vec!["private/key".to_string()]),
+            dir_modes: BTreeMap::from([
+                (".".to_string(), "700".to_string()),
+                ("private".to_string(), "0750".to_string()),
+                ("other".to_string(), "755".to_string()),
+            ]),
+            ..Default::default()
+        };
+
+        let entries = builder
+            .build_group_export(&group, "nux", &own("/repo/gpg"), Path::new("/home/.gnupg"))
+            .unwrap();
+        assert_eq!(
+            entries[0].dir_modes,
+            vec![
+                (PathBuf::from("/home/.gnupg"), 0o700),
+                (PathBuf::from("/home/.gnupg/private"), 0o750),
+            ]
+        );
+        assert!(parse_dir_modes(&BTreeMap::from([(".".to_string(), "9".to_string())])).is_err());
+    }
+
+    #[test]
+    fn refuses_resolver_inside_group_directory() {
+        let store = MemoryStore::new().with_file("/repo/sh/.bashrc", b"set -o vi\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            files: Some(vec![".bashrc".to_string()]),
+            ..Default::default()
+        };
+
+        for resolved in ["/repo/sh", "/repo/sh/nested", "/repo"] {
+            let err = builder
+                .build_group_export(&group, "nux", &own("/repo/sh"), Path::new(resolved))
+                .unwrap_err();
+            assert!(err.to_string().contains("onto themselves"));
+        }
+        for resolved in ["/repo/other", "/"] {
+            assert!(builder
+                .build_group_export(&group, "nux", &own("/repo/sh"), Path::new(resolved))
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn crontab_export_targets_pseudo_path() {
+        let store = MemoryStore::new()
+            .with_file("/repo/cron/crontab", b"@daily backup\n")
+            .with_file("crontab:alice", b"@daily backup\n");
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            kind: GroupKind::Crontab,
+            ..Default::default()
+        };
+
+        let entries = builder
+            .build_group_export(&group, "nux", &own("/repo/cron"), Path::new("alice"))
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].destination, PathBuf::from("crontab:alice"));
+        assert_eq!(entries[0].status, FileStatus::Same);
+    }
+
+    #[test]
+    fn crontab_import_skips_missing_crontab() {
+        let store = MemoryStore::new();
+        let builder = PlanBuilder::new(&store);
+        let group = Group {
+            kind: GroupKind::Crontab,
+            ..Default::default()
+        };
+
+        let entries = builder
+            .build_group_import(
+                &group,
+                &own("/repo/cron"),
+                Path::new(""),
+                Path::new("/repo/cron/.dootignore"),
+            )
+            .unwrap();
+
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn env_group_generates_file_for_resolver() {
+        let store = MemoryStore::new().with_file("/home/env.conf", b"stale\n");
+        let builder = PlanBuilder::new(&store);
+        let mut group = Group {
+            kind: GroupKind::Env,
+            ..Default::default()
+        };
+        group.variables.insert(
+            "nux".to_string(),
+            [("EDITOR".to_string(), "nvim".to_string())].into(),
+        );
+
+        let entries = builder
+            .build_group_export(
+                &group,
+                "nux",
+                &own("/repo/env"),
+                Path::new("/home/env.conf"),
+            )
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].relative_path, PathBuf::from("env.conf"));
+        assert_eq!(entries[0].status, FileStatus::Overwrite);
+        let content = entries[0].source_content(&store).unwrap();
+        assert!(String::from_utf8(content)
+            .unwrap()
+            .contains("EDITOR=nvim\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn groups_needing_a_mount_are_skipped_without_it() {
+        let mut config =
+            crate::testing::config("usb", Path::new("/"), &[("keys", &["id_ed25519"])]);
+        let unmounted = crate::testing::TempDir::new("mount");
+        let mut require = |mount: &Path| {
+            config
+                .groups
+                .get_mut("keys")
+                .unwrap()
+                .requires_mount
+                .insert("usb".to_string(), mount.display().to_string());
+            skip_reason(&config, "keys", "usb").unwrap()
+        };
+
+        assert_eq!(require(Path::new("/")), None);
+        assert_eq!(
+            require(&unmounted),
+            Some(format!("{} is not mounted", unmounted.display()))
+        );
+    }
+
+    #[test]
+    fn never_create_parents_refuses_missing_directories() {
+        let entries = [
+            entry(
+                ".bashrc",
+                "/repo/bash/.bashrc",
+                "/home/.bashrc",
+                FileStatus::Same,
+            ),
+            entry(
+                "x/y",
+                "/repo/bash/x/y",
+                "/doot-missing/x/y",
+                FileStatus::Create,
+            ),
+        ];
+        let mut group = Group::default();
+        assert!(check_parents(&group, &entries).is_ok());
+        group.create_parents = CreateParents::Never;
+        let error = check_parents(&group, &entries).unwrap_err();
+        assert_eq!(crate::error::code(&error), "conflict");
+        assert!(check_parents(&group, &entries[..1]).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_depth_and_symlinks_follow_group_options() {
+        let dir = crate::testing::TempDir::new("walk");
+        let repo = dir.join("repo/nvim");
+        std::fs::create_dir_all(repo.join("lua/plugins")).unwrap();
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(repo.join("init.lua"), b"").unwrap();
+        std::fs::write(repo.join("lua/plugins/git.lua"), b"").unwrap();
+        std::fs::write(dir.join("shared/keys.lua"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.join("shared"), repo.join("shared")).unwrap();
+
+        let store = crate::store::FileStore::new(Default::default());
+        let builder = PlanBuilder::new(&store);
+        let paths = |group: &Group| -> Vec<PathBuf> {
+            builder
+                .build_group_export(group, "nux", &[Layer::own(repo.clone())], &dir.join("home"))
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.relative_path)
+                .collect()
+        };
+
+        assert_eq!(
+            paths(&Group::default()),
+            [
+                PathBuf::from("init.lua"),
+                PathBuf::from("lua/plugins/git.lua")
+            ]
+        );
+        let shallow = Group {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(paths(&shallow), [PathBuf::from("init.lua")]);
+        let following = Group {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(&following),
+            [
+                PathBuf::from("init.lua"),
+                PathBuf::from("lua/plugins/git.lua"),
+                PathBuf::from("shared/keys.lua"),
+            ]
+        );
+    }
+
+    #[test]
+    fn status_same_when_semantically_equal() {
+        let store = MemoryStore::new()
+            .with_file("/src/settings.json", br#"{"a": 1, "b": 2}"#)
+            .with_file("/dst/settings.json", br#"{"b": 2, "a": 1}"#);
+        let builder = PlanBuilder::new(&store);
+
+        let status = builder.compute_status_with(
+            Path::new("/src/settings.json"),
+            Path::new("/dst/settings.json"),
+            Some(CompareMode::SemanticJson),
+        );
+        assert_eq!(status, FileStatus::Same);
+    }
+
     #[test]
     fn status_create_when_destination_missing() {
-        let store = MockStore::new().with_file("/src/file", b"content");
+        let store = MemoryStore::new().with_file("/src/file", b"content");
         let builder = PlanBuilder::new(&store);
 
         let status = builder.compute_status(Path::new("/src/file"), Path::new("/dst/file"));
@@ -257,7 +1959,7 @@ mod tests {
 
     #[test]
     fn status_same_when_content_matches() {
-        let store = MockStore::new()
+        let store = MemoryStore::new()
             .with_file("/src/file", b"content")
             .with_file("/dst/file", b"content");
         let builder = PlanBuilder::new(&store);
@@ -268,7 +1970,7 @@ mod tests {
 
     #[test]
     fn status_overwrite_when_content_differs() {
-        let store = MockStore::new()
+        let store = MemoryStore::new()
             .with_file("/src/file", b"new content")
             .with_file("/dst/file", b"old content");
         let builder = PlanBuilder::new(&store);
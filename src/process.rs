@@ -0,0 +1,249 @@
+use crate::config::ProcessEnv;
+use crate::error::DootError;
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Variables passed through to external commands unless `process_env.inherit`
+/// is set. Everything else is dropped so commands behave the same everywhere.
+pub const BASE_ENV: &[&str] = &[
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "PATH",
+    "SHELL",
+    "TERM",
+    "LANG",
+    "LC_ALL",
+    "TMPDIR",
+    "XDG_RUNTIME_DIR",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+static ENV: RwLock<Option<ProcessEnv>> = RwLock::new(None);
+
+/// Sets the environment policy used by [`command`] for the rest of the run.
+pub fn configure(env: &ProcessEnv) {
+    *ENV.write().unwrap() = Some(env.clone());
+}
+
+/// Creates a command for an external process doot spawns (hooks, filters,
+/// `crontab`, `systemctl`, ...) with the configured environment.
+pub fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+    let env = ENV.read().unwrap().clone().unwrap_or_default();
+    apply_env(&mut command, &env);
+    command
+}
+
+/// Runs `command_line` (a program and its arguments, split on whitespace)
+/// with `input` on stdin and returns its stdout.
+pub fn filter(command_line: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let mut words = command_line.split_whitespace();
+    let program = words.next().context("Empty command")?;
+    let mut command = command(program);
+    command.args(words);
+    pipe(command, command_line, input)
+}
+
+/// Runs `command`, described as `name` in errors, with `input` on stdin and
+/// returns its stdout.
+pub fn pipe(mut command: Command, name: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", name))?;
+
+    // Write from another thread so a large output can't block the input.
+    let mut stdin = child.stdin.take().context("Failed to open stdin")?;
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run `{}`", name))?;
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Failed to write to `{}`", name))?
+        .with_context(|| format!("Failed to write to `{}`", name))?;
+
+    anyhow::ensure!(
+        output.status.success(),
+        "`{}` failed: {}",
+        name,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(output.stdout)
+}
+
+/// Runs `command` with `input` on stdin, killing it if it runs longer than
+/// `timeout`.
+pub fn output_within(mut command: Command, input: &[u8], timeout: Duration) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    // Pipes are drained from threads so a full pipe can't stall the child.
+    let mut stdin = child.stdin.take().context("Failed to open stdin")?;
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let mut stdout = child.stdout.take().context("Failed to open stdout")?;
+    let reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        std::io::Read::read_to_end(&mut stdout, &mut buffer).map(|_| buffer)
+    });
+    let mut stderr = child.stderr.take().context("Failed to open stderr")?;
+    let error_reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        std::io::Read::read_to_end(&mut stderr, &mut buffer).map(|_| buffer)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(DootError::Timeout(format!(
+                "{} didn't finish within {}s",
+                program,
+                timeout.as_secs_f32()
+            ))
+            .into());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    // The child may exit without reading its input, which isn't an error.
+    let _ = writer.join();
+    let join = |reader: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+        reader
+            .join()
+            .map_err(|_| anyhow::anyhow!("Failed to read from {}", program))?
+            .with_context(|| format!("Failed to read from {}", program))
+    };
+    Ok(Output {
+        status,
+        stdout: join(reader)?,
+        stderr: join(error_reader)?,
+    })
+}
+
+/// Value of the variable `name` in the environment external commands are run
+/// with.
+pub fn variable(name: &str) -> Option<String> {
+    let env = ENV.read().unwrap().clone().unwrap_or_default();
+    if let Some(value) = env.set.get(name) {
+        return Some(value.clone());
+    }
+    let passed =
+        env.inherit || BASE_ENV.contains(&name) || env.pass.iter().any(|pass| pass == name);
+    passed.then(|| std::env::var(name).ok()).flatten()
+}
+
+/// Returns whether `program` is found in the `PATH` external commands are
+/// run with.
+pub fn is_available(program: &str) -> bool {
+    let env = ENV.read().unwrap().clone().unwrap_or_default();
+    let path = match env.set.get("PATH") {
+        Some(path) => Some(path.into()),
+        None => std::env::var_os("PATH"),
+    };
+
+    path.is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+fn apply_env(command: &mut Command, env: &ProcessEnv) {
+    if !env.inherit {
+        command.env_clear();
+        for name in BASE_ENV
+            .iter()
+            .copied()
+            .chain(env.pass.iter().map(String::as_str))
+        {
+            if let Some(value) = std::env::var_os(name) {
+                command.env(name, value);
+            }
+        }
+    }
+
+    for (name, value) in &env.set {
+        command.env(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn env_names(command: &Command) -> Vec<String> {
+        command
+            .get_envs()
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn filter_pipes_content_through_command() {
+        assert_eq!(filter("tr a-z A-Z", b"doot\n").unwrap(), b"DOOT\n");
+        assert!(filter("false", b"").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_within_kills_slow_commands() {
+        let mut fast = Command::new("cat");
+        fast.arg("-");
+        let output = output_within(fast, b"doot", Duration::from_secs(5)).unwrap();
+        assert_eq!(output.stdout, b"doot");
+
+        let mut slow = Command::new("sleep");
+        slow.arg("5");
+        let error = output_within(slow, b"", Duration::from_millis(50)).unwrap_err();
+        assert_eq!(crate::error::code(&error), "timeout");
+    }
+
+    #[test]
+    fn isolated_env_only_passes_declared_variables() {
+        let env = ProcessEnv {
+            inherit: false,
+            pass: Vec::new(),
+            set: BTreeMap::from([("DOOT_TEST".to_string(), "1".to_string())]),
+        };
+
+        let mut command = Command::new("true");
+        apply_env(&mut command, &env);
+
+        let names = env_names(&command);
+        assert!(names.contains(&"DOOT_TEST".to_string()));
+        assert!(names
+            .iter()
+            .all(|n| n == "DOOT_TEST" || BASE_ENV.contains(&n.as_str())));
+    }
+
+    #[test]
+    fn inherited_env_is_left_alone() {
+        let env = ProcessEnv {
+            inherit: true,
+            ..Default::default()
+        };
+
+        let mut command = Command::new("true");
+        apply_env(&mut command, &env);
+        assert!(env_names(&command).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Progress bar for runs that change many files.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::path::Path;
+
+/// Runs with fewer changes than this finish too quickly to need a bar.
+const MIN_CHANGES: usize = 50;
+
+/// Shows how far a run is, with the file being changed, below the lines the
+/// executor prints. Without a terminal (or with `--ascii`, or for short
+/// runs) there is no bar and lines are printed as they are.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    pub fn new(total: usize, enabled: bool) -> Self {
+        let shown = enabled
+            && total >= MIN_CHANGES
+            && !crate::style::is_ascii()
+            && std::io::stdout().is_terminal()
+            && std::io::stderr().is_terminal()
+            && std::env::var("TERM").map_or(true, |term| term != "dumb");
+        let bar = shown.then(|| {
+            let bar = ProgressBar::new(total as u64);
+            let style = ProgressStyle::with_template("{bar:30} {pos}/{len} {wide_msg}")
+                .expect("valid progress template")
+                .progress_chars("=> ");
+            bar.set_style(style);
+            bar
+        });
+        Self { bar }
+    }
+
+    /// Prints `line` above the bar.
+    pub fn println(&self, line: impl AsRef<str>) {
+        match &self.bar {
+            Some(bar) => bar.println(line),
+            None => println!("{}", line.as_ref()),
+        }
+    }
+
+    /// Names the file about to be changed.
+    pub fn start(&self, group: &str, path: &Path) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(format!("{}/{}", group, path.display()));
+        }
+    }
+
+    /// Counts a change as done, whether it succeeded or not.
+    pub fn advance(&self) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    /// Hides the bar while `f` prints on its own, such as a hook's output.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match &self.bar {
+            Some(bar) => bar.suspend(f),
+            None => f(),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
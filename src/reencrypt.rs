@@ -0,0 +1,127 @@
+use crate::age;
+use crate::config::Config;
+use crate::plan;
+use crate::style::Icon;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The `.age` files in the repo directories of `groups`, including the
+/// directories of the groups they extend.
+pub fn encrypted_files(config: &Config, repo: &Path, groups: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut dirs = BTreeSet::new();
+    for group in groups {
+        dirs.extend(
+            plan::group_layers(config, group, repo)?
+                .into_iter()
+                .map(|layer| layer.dir),
+        );
+    }
+
+    let mut files = BTreeSet::new();
+    for dir in &dirs {
+        files.extend(
+            ignore::WalkBuilder::new(dir)
+                .standard_filters(false)
+                .build()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+                .map(|e| e.into_path())
+                .filter(|path| age::is_encrypted(path)),
+        );
+    }
+    Ok(files.into_iter().collect())
+}
+
+/// Re-encrypts the `.age` files of `group`, or of every group, to the
+/// configured recipients.
+pub fn run(config: &Config, group: Option<&str>) -> Result<()> {
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    let groups: Vec<&str> = match group {
+        Some(name) => {
+            config.get_group(name)?;
+            vec![name]
+        }
+        None => config.groups.keys().map(String::as_str).collect(),
+    };
+    let files = encrypted_files(config, &repo, &groups)?;
+    if files.is_empty() {
+        println!("No encrypted files.");
+        return Ok(());
+    }
+
+    // Every file is re-encrypted and staged before any is replaced, so a
+    // wrong identity or recipient leaves the repo as it was.
+    let mut staged = Vec::new();
+    let result = files.iter().try_for_each(|path| {
+        let content =
+            std::fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))?;
+        let plaintext = age::decrypt(&content)
+            .with_context(|| format!("Failed to decrypt: {}", path.display()))?;
+        let encrypted = age::encrypt(&plaintext)
+            .with_context(|| format!("Failed to encrypt: {}", path.display()))?;
+        let staging = staging_path(path);
+        std::fs::write(&staging, encrypted)
+            .with_context(|| format!("Failed to write: {}", staging.display()))?;
+        staged.push(staging);
+        Ok(())
+    });
+    if let Err(e) = result {
+        for staging in &staged {
+            let _ = std::fs::remove_file(staging);
+        }
+        return Err(e);
+    }
+
+    for (staging, path) in staged.iter().zip(&files) {
+        std::fs::rename(staging, path)
+            .with_context(|| format!("Failed to replace: {}", path.display()))?;
+        println!(
+            "[{}] {}",
+            Icon::Modified.glyph().yellow(),
+            path.strip_prefix(&repo).unwrap_or(path).display()
+        );
+    }
+    println!(
+        "\nRe-encrypted {} file(s) to {} recipient(s).",
+        files.len(),
+        config.age.recipients.len()
+    );
+    Ok(())
+}
+
+/// Where the re-encrypted copy of `path` is written before it replaces it.
+fn staging_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_encrypted_files_of_groups_and_their_bases() {
+        let repo = crate::testing::TempDir::new("reencrypt");
+        for file in [
+            "base/.netrc.age",
+            "work/.ssh/id.age",
+            "work/.bashrc",
+            "other/.token.age",
+        ] {
+            std::fs::create_dir_all(repo.join(file).parent().unwrap()).unwrap();
+            std::fs::write(repo.join(file), "").unwrap();
+        }
+        let config = Config::parse(
+            "version: v1\ngroups:\n  base:\n    nux: \"~\"\n  work:\n    nux: \"~\"\n    extends: base\n  other:\n    nux: \"~\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            encrypted_files(&config, &repo, &["work"]).unwrap(),
+            [repo.join("base/.netrc.age"), repo.join("work/.ssh/id.age")]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::config::Config;
+use crate::history::{self, Run};
+use crate::plan::FileStatus;
+use crate::status::{FileState, GroupStatus, GroupStatusResult, StatusChecker};
+use crate::store::Store;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Runs listed under "Recent history".
+const RECENT_RUNS: usize = 10;
+
+/// Output formats of `doot report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(ReportFormat::Markdown),
+            _ => Err(format!("unknown report format '{}', expected 'md'", s)),
+        }
+    }
+}
+
+pub fn run(
+    config: &Config,
+    store: &dyn Store,
+    resolver: &str,
+    format: ReportFormat,
+    output: Option<&Path>,
+) -> Result<()> {
+    let checker = StatusChecker::new(config, store, resolver.to_string());
+    let groups = checker.check_all_groups()?;
+    let runs = history::load_runs(None)?;
+
+    let mut content = Vec::new();
+    match format {
+        ReportFormat::Markdown => write_markdown(&mut content, resolver, &groups, &runs)?,
+    }
+
+    match output {
+        Some(path) => std::fs::write(path, content)
+            .with_context(|| format!("Failed to write: {}", path.display())),
+        None => Ok(std::io::stdout().write_all(&content)?),
+    }
+}
+
+/// Timestamp of the latest run that changed files of `group`.
+fn last_sync(runs: &[Run], group: &str) -> Option<u64> {
+    runs.iter()
+        .rev()
+        .filter(|run| !run.undone)
+        .find(|run| {
+            run.plan.as_ref().is_some_and(|plan| {
+                plan.groups.iter().any(|g| {
+                    g.name == group && g.entries.iter().any(|e| e.status != FileStatus::Same)
+                })
+            })
+        })
+        .map(|run| run.timestamp)
+}
+
+fn status_label(status: &GroupStatus) -> &'static str {
+    match status {
+        GroupStatus::InSync => "in sync",
+        GroupStatus::OutOfSync => "out of sync",
+        GroupStatus::New => "new",
+        GroupStatus::Skipped => "skipped",
+    }
+}
+
+/// Escapes characters that would break a Markdown table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn write_markdown(
+    out: &mut dyn Write,
+    resolver: &str,
+    groups: &[GroupStatusResult],
+    runs: &[Run],
+) -> Result<()> {
+    writeln!(out, "# doot report ({})", resolver)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Generated {}.",
+        history::format_timestamp(history::now())
+    )?;
+    writeln!(out)?;
+
+    writeln!(out, "## Groups")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "| Group | Status | Files | In sync | Modified | New | Last sync |"
+    )?;
+    writeln!(out, "|---|---|---:|---:|---:|---:|---|")?;
+    for group in groups {
+        let count = |state: FileState| group.files.iter().filter(|f| f.state == state).count();
+        let synced = last_sync(runs, &group.name)
+            .map(history::format_timestamp)
+            .unwrap_or_else(|| "never".to_string());
+        writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            cell(&group.name),
+            status_label(&group.status),
+            group.files.len(),
+            count(FileState::InSync),
+            count(FileState::Modified),
+            count(FileState::New),
+            synced
+        )?;
+    }
+    writeln!(out)?;
+
+    writeln!(out, "## Drift")?;
+    writeln!(out)?;
+    let mut drift = false;
+    for group in groups {
+        let changed: Vec<_> = group
+            .files
+            .iter()
+            .filter(|f| f.state != FileState::InSync)
+            .collect();
+        if changed.is_empty() && group.uncommitted.is_empty() && group.dir_drift.is_empty() {
+            continue;
+        }
+        drift = true;
+
+        writeln!(out, "### {}", group.name)?;
+        writeln!(out)?;
+        for file in changed {
+            let state = match file.state {
+                FileState::Modified => "modified",
+                FileState::New => "not on this machine",
+                FileState::InSync => "in sync",
+            };
+            writeln!(out, "- `{}`: {}", file.relative_path, state)?;
+        }
+        for path in &group.uncommitted {
+            writeln!(out, "- `{}`: uncommitted in the repo", path)?;
+        }
+        for dir in &group.dir_drift {
+            writeln!(
+                out,
+                "- `{}`: mode {:o}, expected {:o}",
+                dir.path.display(),
+                dir.mode,
+                dir.expected
+            )?;
+        }
+        writeln!(out)?;
+    }
+    if !drift {
+        writeln!(out, "No drift.")?;
+        writeln!(out)?;
+    }
+
+    writeln!(out, "## Recent history")?;
+    writeln!(out)?;
+    if runs.is_empty() {
+        writeln!(out, "No runs recorded.")?;
+        return Ok(());
+    }
+    writeln!(out, "| Time | Operation | Tag | Files | Undone |")?;
+    writeln!(out, "|---|---|---|---:|---|")?;
+    for run in runs.iter().rev().take(RECENT_RUNS) {
+        writeln!(
+            out,
+            "| {} | {} | {} | {} | {} |",
+            history::format_timestamp(run.timestamp),
+            cell(&run.operation),
+            cell(run.tag.as_deref().unwrap_or("")),
+            run.entries.len(),
+            if run.undone { "yes" } else { "no" }
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::FileStatusEntry;
+
+    #[test]
+    fn markdown_lists_groups_and_drift() {
+        let groups = vec![GroupStatusResult {
+            name: "bash".to_string(),
+            status: GroupStatus::OutOfSync,
+            files: vec![
+                FileStatusEntry {
+                    relative_path: ".bashrc".to_string(),
+                    state: FileState::Modified,
+                    layer: None,
+                    encrypted: false,
+                },
+                FileStatusEntry {
+                    relative_path: ".profile".to_string(),
+                    state: FileState::InSync,
+                    layer: None,
+                    encrypted: false,
+                },
+            ],
+            units: Vec::new(),
+            uncommitted: Vec::new(),
+            dir_drift: Vec::new(),
+        }];
+
+        let mut out = Vec::new();
+        write_markdown(&mut out, "nux", &groups, &[]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("| bash | out of sync | 2 | 1 | 1 | 0 | never |"));
+        assert!(out.contains("- `.bashrc`: modified"));
+        assert!(out.contains("No runs recorded."));
+    }
+}
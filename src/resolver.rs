@@ -1,16 +1,105 @@
+use crate::error::DootError;
 use anyhow::Result;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+/// Variables from the config's `env` section, already expanded.
+static VARIABLES: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// Defines the config's `env` variables for path expansion. Values are
+/// expanded in order, so they can use `~`, the environment and each other.
+pub fn configure(env: &BTreeMap<String, String>) -> Result<()> {
+    VARIABLES.write().unwrap().clear();
+    for (name, value) in env {
+        let expanded = resolve_path(value)?.to_string_lossy().into_owned();
+        VARIABLES.write().unwrap().insert(name.clone(), expanded);
+    }
+    Ok(())
+}
+
+/// Returns the config's `env` variables, expanded.
+pub fn variables() -> BTreeMap<String, String> {
+    VARIABLES.read().unwrap().clone()
+}
+
+fn home_dir() -> Option<String> {
+    dirs::home_dir().and_then(|home| home.to_str().map(String::from))
+}
 
 pub fn resolve_path(path: &str) -> Result<PathBuf> {
-    let expanded = shellexpand::full(path)
-        .map_err(|e| anyhow::anyhow!("Failed to expand path '{}': {}", path, e))?;
+    expand(path, false)
+}
+
+/// Expands `path`. With `lenient`, undefined variables expand to nothing
+/// (with a warning) instead of failing.
+pub fn expand(path: &str, lenient: bool) -> Result<PathBuf> {
+    let variables = variables();
+    let lookup = |name: &str| match variables.get(name) {
+        Some(value) => Ok(Some(value.clone())),
+        None => match std::env::var(name) {
+            Err(std::env::VarError::NotPresent) if lenient => {
+                warn_undefined(name);
+                Ok(Some(String::new()))
+            }
+            result => result.map(Some),
+        },
+    };
+    let expanded = shellexpand::full_with_context(path, home_dir, lookup).map_err(|e| {
+        let reason = match e.cause {
+            std::env::VarError::NotPresent => "is not set".to_string(),
+            cause => cause.to_string(),
+        };
+        DootError::Resolver(format!(
+            "Failed to expand path '{}': ${} {}. Define it in the `env` section of \
+             doot.yaml or in your shell profile.",
+            path, e.var_name, reason
+        ))
+    })?;
     Ok(PathBuf::from(expanded.as_ref()))
 }
 
+/// Warns once per run that `name` is undefined and expands to nothing.
+fn warn_undefined(name: &str) {
+    static WARNED: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+    if WARNED.write().unwrap().insert(name.to_string()) {
+        eprintln!("Warning: ${} is not set; expanding it to nothing", name);
+    }
+}
+
+/// Re-roots an absolute `path` under `root`, like `DESTDIR` in `make install`.
+pub fn stage(root: &Path, path: &Path) -> PathBuf {
+    let relative: PathBuf = path
+        .components()
+        .filter(|c| !matches!(c, Component::RootDir | Component::Prefix(_)))
+        .collect();
+    root.join(relative)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn config_variables_expand_in_paths() {
+        let env = BTreeMap::from([("DOOT_TEST_ROOT".to_string(), "~/dots".to_string())]);
+        configure(&env).unwrap();
+        let home = dirs::home_dir().unwrap();
+        let resolved = resolve_path("$DOOT_TEST_ROOT/nvim").unwrap();
+        configure(&BTreeMap::new()).unwrap();
+        assert_eq!(resolved, home.join("dots/nvim"));
+    }
+
+    #[test]
+    fn undefined_variable_is_named_in_error() {
+        let err = resolve_path("$DOOT_TEST_UNSET/x").unwrap_err().to_string();
+        assert!(err.contains("$DOOT_TEST_UNSET is not set"), "{}", err);
+        assert_eq!(
+            expand("$DOOT_TEST_UNSET/x", true).unwrap(),
+            PathBuf::from("/x")
+        );
+    }
+
     #[test]
     fn test_resolve_tilde() {
         let home = dirs::home_dir().unwrap();
@@ -24,4 +113,10 @@ mod tests {
         let resolved = resolve_path("~/.bashrc").unwrap();
         assert_eq!(resolved, home.join(".bashrc"));
     }
+
+    #[test]
+    fn test_stage_absolute_path() {
+        let staged = stage(Path::new("/mnt/root"), Path::new("/home/me/.bashrc"));
+        assert_eq!(staged, PathBuf::from("/mnt/root/home/me/.bashrc"));
+    }
 }
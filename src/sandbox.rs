@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Confines the rest of the run to `root`, set by `--sandbox`: `~` and `$HOME`
+/// become `root/home`, doot's state lives in `root/state`, and resolved paths
+/// outside the sandbox are re-rooted into it.
+///
+/// Must be called before doot spawns threads, since it changes the process
+/// environment.
+pub fn enter(root: &Path) -> Result<()> {
+    std::fs::create_dir_all(root)
+        .with_context(|| format!("Failed to create directory: {}", root.display()))?;
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve sandbox: {}", root.display()))?;
+
+    let home = root.join("home");
+    std::fs::create_dir_all(&home)
+        .with_context(|| format!("Failed to create directory: {}", home.display()))?;
+    std::env::set_var("HOME", &home);
+    for (variable, dir) in [
+        ("XDG_STATE_HOME", "state"),
+        ("XDG_CACHE_HOME", "cache"),
+        ("XDG_CONFIG_HOME", "home/.config"),
+        ("XDG_DATA_HOME", "home/.local/share"),
+    ] {
+        std::env::set_var(variable, root.join(dir));
+    }
+
+    let _ = ROOT.set(root);
+    Ok(())
+}
+
+/// Root of the sandbox, if the run is confined to one.
+pub fn root() -> Option<&'static Path> {
+    ROOT.get().map(PathBuf::as_path)
+}
+
+/// `path` if it is inside the sandbox `root`, otherwise `path` re-rooted
+/// under it.
+pub fn confine(root: &Path, path: &Path) -> PathBuf {
+    if path.starts_with(root) {
+        path.to_path_buf()
+    } else {
+        crate::resolver::stage(root, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confine_keeps_sandboxed_paths() {
+        let root = Path::new("/tmp/sandbox");
+        assert_eq!(
+            confine(root, Path::new("/tmp/sandbox/home/.bashrc")),
+            Path::new("/tmp/sandbox/home/.bashrc")
+        );
+        assert_eq!(
+            confine(root, Path::new("/etc/hosts")),
+            Path::new("/tmp/sandbox/etc/hosts")
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::config::{Config, GroupKind};
+use crate::naming;
+use crate::resolver;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Built-in knowledge base of applications and the dotfiles they own, relative
+/// to a home-like root.
+const KNOWN_APPS: &[(&str, &[&str])] = &[
+    (
+        "bash",
+        &[".bashrc", ".bash_profile", ".bash_aliases", ".bash_logout"],
+    ),
+    ("zsh", &[".zshrc", ".zprofile", ".zshenv", ".zlogin"]),
+    ("fish", &[".config/fish"]),
+    ("profile", &[".profile", ".inputrc"]),
+    ("vim", &[".vimrc", ".gvimrc"]),
+    ("nvim", &[".config/nvim"]),
+    ("emacs", &[".emacs", ".emacs.d/init.el", ".config/emacs"]),
+    ("git", &[".gitconfig", ".gitignore_global", ".config/git"]),
+    ("tmux", &[".tmux.conf", ".config/tmux"]),
+    ("screen", &[".screenrc"]),
+    ("ssh", &[".ssh/config"]),
+    ("gnupg", &[".gnupg/gpg.conf", ".gnupg/gpg-agent.conf"]),
+    ("alacritty", &[".config/alacritty"]),
+    ("kitty", &[".config/kitty"]),
+    ("wezterm", &[".wezterm.lua", ".config/wezterm"]),
+    ("starship", &[".config/starship.toml"]),
+    ("x11", &[".Xresources", ".xinitrc", ".xprofile"]),
+    ("i3", &[".config/i3"]),
+    ("sway", &[".config/sway"]),
+    ("htop", &[".config/htop"]),
+    ("npm", &[".npmrc"]),
+    ("cargo", &[".cargo/config.toml"]),
+];
+
+/// A proposed group: an application and the unmanaged paths found for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proposal {
+    pub group: String,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Finds known application dotfiles under `root` that no existing group
+/// manages. Only paths at most `depth` components deep are considered.
+pub fn propose(root: &Path, depth: usize, managed: &[PathBuf]) -> Vec<Proposal> {
+    let mut proposals = Vec::new();
+
+    for (app, candidates) in KNOWN_APPS {
+        let paths: Vec<PathBuf> = candidates
+            .iter()
+            .map(PathBuf::from)
+            .filter(|p| p.components().count() <= depth)
+            .filter(|p| root.join(p).exists())
+            .filter(|p| !managed.iter().any(|m| m.starts_with(p) || p.starts_with(m)))
+            .collect();
+
+        if !paths.is_empty() {
+            proposals.push(Proposal {
+                group: app.to_string(),
+                paths,
+            });
+        }
+    }
+
+    proposals
+}
+
+/// Whether `dir` holds dotfiles of a known application, as a group directory
+/// would.
+pub fn has_known_dotfiles(dir: &Path) -> bool {
+    KNOWN_APPS
+        .iter()
+        .flat_map(|(_, candidates)| candidates.iter())
+        .any(|candidate| dir.join(candidate).exists())
+}
+
+/// Renders a `.dootignore` that ignores everything except `paths`.
+pub fn dootignore_for(paths: &[PathBuf]) -> String {
+    let mut lines = vec!["/*".to_string()];
+    let mut unignored = Vec::new();
+
+    for path in paths {
+        let components: Vec<_> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        // Re-include every parent directory, then ignore its other children.
+        for i in 1..components.len() {
+            let parent = components[..i].join("/");
+            if !unignored.contains(&parent) {
+                lines.push(format!("!/{parent}/"));
+                lines.push(format!("/{parent}/*"));
+                unignored.push(parent);
+            }
+        }
+        lines.push(format!("!/{}", components.join("/")));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Scans the roots of `resolver_name` and interactively proposes new groups.
+pub fn run(config: &Config, resolver_name: &str, depth: usize) -> Result<()> {
+    let mut roots: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    let cwd = std::env::current_dir().context("Failed to get current directory")?;
+
+    for (group_name, group) in &config.groups {
+        if group.kind != GroupKind::Files || !group.resolvers.contains_key(resolver_name) {
+            continue;
+        }
+        let root = config.resolve_group_path(group_name, resolver_name)?;
+        let managed = list_files(&cwd.join(group_name))
+            .into_iter()
+            .map(|path| naming::to_destination(group.filename_style, &path));
+        roots.entry(root).or_default().extend(managed);
+    }
+
+    if roots.is_empty() {
+        roots.insert(resolver::resolve_path("~")?, Vec::new());
+    }
+
+    for (root, managed) in &roots {
+        let proposals: Vec<_> = propose(root, depth, managed)
+            .into_iter()
+            .filter(|p| !config.groups.contains_key(&p.group))
+            .collect();
+
+        if proposals.is_empty() {
+            println!("No unmanaged dotfiles found in {}.", root.display());
+            continue;
+        }
+
+        println!("\nUnmanaged dotfiles in {}:\n", root.display());
+        for proposal in proposals {
+            println!("  {}:", proposal.group);
+            for path in &proposal.paths {
+                println!("    {}", path.display());
+            }
+
+            if !confirm(&format!("Create group '{}'?", proposal.group))? {
+                println!();
+                continue;
+            }
+
+            let group_dir = cwd.join(&proposal.group);
+            std::fs::create_dir_all(&group_dir)
+                .with_context(|| format!("Failed to create directory: {}", group_dir.display()))?;
+            let ignore_path = group_dir.join(".dootignore");
+            std::fs::write(&ignore_path, dootignore_for(&proposal.paths))
+                .with_context(|| format!("Failed to write: {}", ignore_path.display()))?;
+
+            println!(
+                "\n  Created {}. Add it to doot.yaml:\n",
+                ignore_path.display()
+            );
+            println!("    {}:", proposal.group);
+            println!("      {}: \"{}\"", resolver_name, root.display());
+            println!(
+                "\n  Then run: doot import group {} {}\n",
+                proposal.group, resolver_name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn list_files(dir: &Path) -> Vec<PathBuf> {
+    ignore::WalkBuilder::new(dir)
+        .standard_filters(false)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+        .filter_map(|e| e.path().strip_prefix(dir).ok().map(Path::to_path_buf))
+        .filter(|p| p != Path::new(".dootignore"))
+        .collect()
+}
+
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("\n  {} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dootignore_whitelists_nested_paths() {
+        let paths = vec![PathBuf::from(".bashrc"), PathBuf::from(".config/nvim")];
+        assert_eq!(
+            dootignore_for(&paths),
+            "/*\n!/.bashrc\n!/.config/\n/.config/*\n!/.config/nvim\n"
+        );
+    }
+
+    #[test]
+    fn propose_skips_managed_and_deep_paths() {
+        let root = crate::testing::TempDir::new("scan");
+        std::fs::create_dir_all(root.join(".config/nvim")).unwrap();
+        std::fs::write(root.join(".bashrc"), "").unwrap();
+        std::fs::write(root.join(".tmux.conf"), "").unwrap();
+
+        let managed = vec![PathBuf::from(".tmux.conf")];
+        let proposals = propose(&root, 1, &managed);
+
+        assert_eq!(
+            proposals,
+            vec![Proposal {
+                group: "bash".to_string(),
+                paths: vec![PathBuf::from(".bashrc")],
+            }]
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Version of the JSON documents doot prints with `--format json` and saves
+/// with `--save-plan`, as their `schema_version`. It only goes up when a
+/// field is removed, renamed or changes meaning; new fields don't bump it.
+pub const VERSION: u32 = 1;
+
+/// Fails for a document at `path` written by a doot with a newer schema.
+/// Documents from before versioning (version 0) read as version 1.
+pub fn check(version: u32, path: &Path) -> Result<()> {
+    if version > VERSION {
+        anyhow::bail!(
+            "{} has schema version {}, but this doot reads up to {}; update doot",
+            path.display(),
+            version,
+            VERSION
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use crate::config::HashAlgorithm;
+use crate::process;
+use crate::store::hash_bytes;
+use crate::version::Version;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/onbjerg/doot/releases/latest";
+
+/// Release asset holding `sha256sum` output for the archives.
+const CHECKSUMS: &str = "checksums.txt";
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .with_context(|| format!("Release {} has no asset '{}'", self.tag_name, name))
+    }
+}
+
+/// Target triple of the release binary that runs on this machine.
+fn target() -> Option<&'static str> {
+    match (std::env::consts::ARCH, std::env::consts::OS) {
+        ("x86_64", "linux") => Some("x86_64-unknown-linux-gnu"),
+        ("aarch64", "linux") => Some("aarch64-unknown-linux-gnu"),
+        ("x86_64", "macos") => Some("x86_64-apple-darwin"),
+        ("aarch64", "macos") => Some("aarch64-apple-darwin"),
+        _ => None,
+    }
+}
+
+/// Returns the latest release if it is newer than the running binary.
+pub fn check() -> Result<Option<Release>> {
+    crate::offline::ensure_online("self-update")?;
+    require("curl")?;
+    let output = process::command("curl")
+        .args([
+            "-fsSL",
+            "-H",
+            "Accept: application/vnd.github+json",
+            LATEST_RELEASE,
+        ])
+        .output()
+        .context("Failed to run curl")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "Failed to fetch the latest release: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let release: Release =
+        serde_json::from_slice(&output.stdout).context("Failed to parse the latest release")?;
+
+    let latest = Version::parse(&release.tag_name)
+        .with_context(|| format!("Invalid release version: {}", release.tag_name))?;
+    Ok((latest > Version::current()).then_some(release))
+}
+
+/// Downloads `release` for this machine, verifies it against the release
+/// checksums and replaces the running executable with it.
+///
+/// The checksums come from the same release as the archive, so this only
+/// catches corrupted downloads; it doesn't prove who built the release.
+pub fn install(release: &Release) -> Result<()> {
+    require("tar")?;
+    let target = target().context("No release binary is built for this platform")?;
+    let archive_name = format!("doot-{}-{}.tar.gz", release.tag_name, target);
+    let archive = release.asset(&archive_name)?;
+    let checksums = release.asset(CHECKSUMS)?;
+
+    let dir = private_dir()?;
+    let result = download_and_replace(&dir, archive, checksums);
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+/// Creates a new directory in the temp dir that only the current user can
+/// enter, so other users can't swap the archive between its check and
+/// extraction. Fails rather than reuse a directory that already exists.
+fn private_dir() -> Result<PathBuf> {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    let dir = std::env::temp_dir().join(format!("doot-update-{}-{}", std::process::id(), nanos));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    Ok(dir)
+}
+
+fn download_and_replace(dir: &Path, archive: &Asset, checksums: &Asset) -> Result<()> {
+    let archive_path = download(dir, archive)?;
+    let checksums_path = download(dir, checksums)?;
+
+    let content = std::fs::read(&archive_path)
+        .with_context(|| format!("Failed to read: {}", archive_path.display()))?;
+    let checksums = std::fs::read_to_string(&checksums_path)
+        .with_context(|| format!("Failed to read: {}", checksums_path.display()))?;
+    let expected = expected_checksum(&checksums, &archive.name)
+        .with_context(|| format!("{} has no checksum for {}", CHECKSUMS, archive.name))?;
+    let actual = hash_bytes(HashAlgorithm::Sha256, &content);
+    anyhow::ensure!(
+        actual == expected,
+        "Checksum mismatch for {}: expected {}, got {}",
+        archive.name,
+        expected,
+        actual
+    );
+
+    let status = process::command("tar")
+        .arg("xzf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(dir)
+        .status()
+        .context("Failed to run tar")?;
+    anyhow::ensure!(status.success(), "Failed to extract {}", archive.name);
+
+    let current = std::env::current_exe().context("Failed to locate the doot executable")?;
+    let current = current.canonicalize().unwrap_or(current);
+    replace_executable(&dir.join("doot"), &current)
+}
+
+fn download(dir: &Path, asset: &Asset) -> Result<PathBuf> {
+    let path = dir.join(&asset.name);
+    let status = process::command("curl")
+        .args(["-fsSL", "-o"])
+        .arg(&path)
+        .arg(&asset.browser_download_url)
+        .status()
+        .context("Failed to run curl")?;
+    anyhow::ensure!(status.success(), "Failed to download {}", asset.name);
+    Ok(path)
+}
+
+/// Finds the digest of `name` in `sha256sum` output.
+fn expected_checksum<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|line| {
+        let (digest, file) = line.split_once(char::is_whitespace)?;
+        (file.trim_start().trim_start_matches('*') == name).then_some(digest)
+    })
+}
+
+/// Moves `new` over `current` through a file next to it, so the swap is a
+/// single rename on the same file system.
+fn replace_executable(new: &Path, current: &Path) -> Result<()> {
+    let staged = current.with_extension("new");
+    std::fs::copy(new, &staged)
+        .with_context(|| format!("Failed to write: {}", staged.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to change permissions: {}", staged.display()))?;
+    }
+
+    std::fs::rename(&staged, current)
+        .with_context(|| format!("Failed to replace: {}", current.display()))
+}
+
+fn require(program: &str) -> Result<()> {
+    anyhow::ensure!(
+        process::is_available(program),
+        "`{}` not found in PATH; it is needed to update doot",
+        program
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_checksum_of_asset() {
+        let checksums = "abc  doot-v1.2.0-x86_64-apple-darwin.tar.gz\n\
+                         def *doot-v1.2.0-x86_64-unknown-linux-gnu.tar.gz\n";
+        assert_eq!(
+            expected_checksum(checksums, "doot-v1.2.0-x86_64-unknown-linux-gnu.tar.gz"),
+            Some("def")
+        );
+        assert_eq!(expected_checksum(checksums, "doot"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn update_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = private_dir().unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        std::fs::remove_dir(&dir).unwrap();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::config::Group;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Directory of doot's own machine-local settings.
+pub const DIR: &str = "~/.config/doot";
+const FILE: &str = "settings.yaml";
+
+/// Name of the built-in group that syncs the settings (`manage_doot`).
+pub const GROUP: &str = "doot";
+
+/// doot's machine-local settings, from `~/.config/doot/settings.yaml`. What
+/// `doot machine init` registers takes precedence.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Resolver used when a command isn't given one
+    #[serde(default)]
+    pub resolver: Option<String>,
+    /// Role of the machine, such as `work`, shown in `status`
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Aliases added to those of `doot.yaml`, replacing any of the same name
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+}
+
+/// Loads the settings, or the defaults if there is no settings file.
+pub fn load() -> Result<Settings> {
+    let path = crate::resolver::resolve_path(DIR)?.join(FILE);
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return Ok(Settings::default());
+    };
+    serde_yaml::from_str(&content).with_context(|| format!("Failed to parse: {}", path.display()))
+}
+
+/// The built-in group syncing the settings file to `~/.config/doot` with
+/// each of `resolvers`.
+pub fn group(resolvers: impl IntoIterator<Item = String>) -> Group {
+    Group {
+        resolvers: resolvers
+            .into_iter()
+            .map(|resolver| (resolver, DIR.to_string()))
+            .collect::<HashMap<_, _>>(),
+        files: Some(vec![FILE.to_string()]),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,262 @@
+use crate::history;
+use crate::messages::{text, Message};
+use crate::plan::{FileStatus, Plan};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A computed plan, as saved with `--save-plan` and recorded with each run,
+/// for comparing with `doot plan diff`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// See `schema::VERSION`; 0 for plans saved before it existed
+    #[serde(default)]
+    pub schema_version: u32,
+    pub operation: String,
+    pub groups: Vec<GroupSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupSnapshot {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    pub entries: Vec<EntrySnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntrySnapshot {
+    pub path: PathBuf,
+    pub destination: PathBuf,
+    pub status: FileStatus,
+}
+
+impl Snapshot {
+    pub fn of(plan: &Plan, operation: &str) -> Self {
+        Self {
+            schema_version: crate::schema::VERSION,
+            operation: operation.to_string(),
+            groups: plan
+                .groups
+                .iter()
+                .map(|group| GroupSnapshot {
+                    name: group.group_name.clone(),
+                    skipped: group.skipped.clone(),
+                    entries: group
+                        .entries
+                        .iter()
+                        .map(|entry| EntrySnapshot {
+                            path: entry.relative_path.clone(),
+                            destination: entry.destination.clone(),
+                            status: entry.status.clone(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write: {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))?;
+        let snapshot: Self = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse plan: {}", path.display()))?;
+        crate::schema::check(snapshot.schema_version, path)?;
+        Ok(snapshot)
+    }
+
+    /// State of each group and file, keyed by group and path inside it (empty
+    /// for the group itself).
+    fn states(&self) -> BTreeMap<(String, PathBuf), String> {
+        let mut states = BTreeMap::new();
+        for group in &self.groups {
+            let group_state = match &group.skipped {
+                Some(reason) => format!("{} ({})", text(Message::SkippedGroup), reason),
+                None => "included".to_string(),
+            };
+            states.insert((group.name.clone(), PathBuf::new()), group_state);
+
+            for entry in &group.entries {
+                states.insert(
+                    (group.name.clone(), entry.path.clone()),
+                    format!(
+                        "{} {}",
+                        status_label(&entry.status),
+                        entry.destination.display()
+                    ),
+                );
+            }
+        }
+        states
+    }
+}
+
+fn status_label(status: &FileStatus) -> &'static str {
+    text(match status {
+        FileStatus::Same => Message::Same,
+        FileStatus::Create => Message::Create,
+        FileStatus::Overwrite => Message::Overwrite,
+        FileStatus::Remove => Message::Remove,
+        FileStatus::Conflict => Message::Conflict,
+    })
+}
+
+/// A group or file whose state differs between two plans; `None` where it
+/// isn't part of the plan.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Change {
+    pub group: String,
+    pub path: PathBuf,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Change> {
+    let mut old_states = old.states();
+    let mut changes = Vec::new();
+
+    for (key, new_state) in new.states() {
+        let old_state = old_states.remove(&key);
+        if old_state.as_ref() != Some(&new_state) {
+            changes.push(Change {
+                group: key.0,
+                path: key.1,
+                old: old_state,
+                new: Some(new_state),
+            });
+        }
+    }
+    for (key, old_state) in old_states {
+        changes.push(Change {
+            group: key.0,
+            path: key.1,
+            old: Some(old_state),
+            new: None,
+        });
+    }
+
+    changes.sort_by(|a, b| (&a.group, &a.path).cmp(&(&b.group, &b.path)));
+    changes
+}
+
+/// Plan recorded with the most recent run (with `tag`).
+fn last_run_plan(tag: Option<&str>) -> Result<Snapshot> {
+    let run = history::load_runs(tag)?.pop().context("No recorded runs")?;
+    run.plan
+        .with_context(|| format!("Run {} has no recorded plan", run.id))
+}
+
+pub fn run(plans: &[PathBuf], against_last_run: bool, tag: Option<&str>) -> Result<()> {
+    let (old, new) = match (plans, against_last_run) {
+        ([new], true) => (last_run_plan(tag)?, Snapshot::load(new)?),
+        ([old, new], false) => (Snapshot::load(old)?, Snapshot::load(new)?),
+        _ => anyhow::bail!("Pass two plans, or one plan with --against-last-run"),
+    };
+
+    println!(
+        "{}\n",
+        format!(
+            "Plan diff ({} {} {})",
+            old.operation,
+            crate::style::arrow(),
+            new.operation
+        )
+        .bold()
+    );
+
+    let changes = diff(&old, &new);
+    for change in &changes {
+        let name = if change.path.as_os_str().is_empty() {
+            change.group.clone()
+        } else {
+            format!("{}/{}", change.group, change.path.display())
+        };
+        let absent = || "(absent)".to_string();
+        println!(
+            "  {}: {} {} {}",
+            name.bold(),
+            change.old.clone().unwrap_or_else(absent).red(),
+            crate::style::arrow(),
+            change.new.clone().unwrap_or_else(absent).green()
+        );
+    }
+
+    if !changes.is_empty() {
+        anyhow::bail!("{} change(s) between the plans", changes.len());
+    }
+
+    println!("No differences.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(groups: Vec<GroupSnapshot>) -> Snapshot {
+        Snapshot {
+            schema_version: crate::schema::VERSION,
+            operation: "Export plan 'all'".to_string(),
+            groups,
+        }
+    }
+
+    fn group(name: &str, skipped: Option<&str>, entries: &[(&str, FileStatus)]) -> GroupSnapshot {
+        GroupSnapshot {
+            name: name.to_string(),
+            skipped: skipped.map(str::to_string),
+            entries: entries
+                .iter()
+                .map(|(path, status)| EntrySnapshot {
+                    path: PathBuf::from(path),
+                    destination: Path::new("/home").join(path),
+                    status: status.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diff_reports_status_and_membership_changes() {
+        let old = snapshot(vec![
+            group("bash", None, &[(".bashrc", FileStatus::Same)]),
+            group("vim", None, &[(".vimrc", FileStatus::Same)]),
+        ]);
+        let new = snapshot(vec![
+            group("bash", None, &[(".bashrc", FileStatus::Overwrite)]),
+            group("vim", Some("no resolver 'nux'"), &[]),
+        ]);
+
+        let changes = diff(&old, &new);
+        let keys: Vec<_> = changes
+            .iter()
+            .map(|c| (c.group.as_str(), c.path.to_str().unwrap()))
+            .collect();
+        assert_eq!(keys, [("bash", ".bashrc"), ("vim", ""), ("vim", ".vimrc")]);
+        assert!(changes[2].new.is_none());
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn load_checks_schema_version() {
+        let dir = crate::testing::TempDir::new("snapshot");
+        let path = dir.join("plan.json");
+
+        std::fs::write(&path, r#"{"operation": "Export", "groups": []}"#).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap().schema_version, 0);
+
+        let mut newer = snapshot(Vec::new());
+        newer.schema_version = crate::schema::VERSION + 1;
+        newer.save(&path).unwrap();
+        let error = Snapshot::load(&path).unwrap_err().to_string();
+        assert!(error.contains("update doot"), "{}", error);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::config::Mode;
+use crate::error::DootError;
+use crate::plan::{FileStatus, Plan};
+use crate::store;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Bytes a plan needs on one filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Need {
+    /// A directory on the filesystem, to name it in messages
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Adds up the bytes `plan` writes to each filesystem, keyed by device.
+/// Overwrites only count their growth, since files are rewritten in place;
+/// symlinks in link mode count nothing. With `backups`, the previous
+/// content backed up of each changed file counts towards the
+/// filesystem of that directory.
+pub fn required(plan: &Plan, mode: Mode, backups: Option<&Path>) -> BTreeMap<u64, Need> {
+    let mut needs: BTreeMap<u64, Need> = BTreeMap::new();
+    let mut add = |path: &Path, bytes: u64| {
+        if bytes == 0 {
+            return;
+        }
+        if let Some((device, dir)) = device_of(path) {
+            needs
+                .entry(device)
+                .or_insert(Need {
+                    path: dir,
+                    bytes: 0,
+                })
+                .bytes += bytes;
+        }
+    };
+
+    for entry in plan.groups.iter().flat_map(|g| &g.entries) {
+        if entry.status == FileStatus::Same || store::is_pseudo_path(&entry.destination) {
+            continue;
+        }
+        let old = std::fs::metadata(&entry.destination).map_or(0, |m| m.len());
+        if let Some(backups) = backups {
+            add(backups, old);
+        }
+
+        let links = mode == Mode::Link
+            && entry.generated.is_none()
+            && !store::is_pseudo_path(&entry.source);
+        if entry.status == FileStatus::Remove || links {
+            continue;
+        }
+        let new = match &entry.generated {
+            Some(content) => content.len() as u64,
+            None => std::fs::metadata(&entry.source).map_or(0, |m| m.len()),
+        };
+        add(&entry.destination, new.saturating_sub(old));
+    }
+    needs
+}
+
+/// Refuses a plan that needs more space on a filesystem than is free there,
+/// before anything is written.
+pub fn check(plan: &Plan, mode: Mode, backups: Option<&Path>) -> Result<()> {
+    for need in required(plan, mode, backups).values() {
+        let Some(free) = free_bytes(&need.path) else {
+            continue;
+        };
+        if need.bytes > free {
+            return Err(DootError::Conflict(format!(
+                "Not enough space on the filesystem of {}: the plan writes {}, {} is free",
+                need.path.display(),
+                format_bytes(need.bytes),
+                format_bytes(free)
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// The device of the closest existing directory holding `path`, and that
+/// directory.
+fn device_of(path: &Path) -> Option<(u64, PathBuf)> {
+    let dir = path.ancestors().skip(1).find(|dir| dir.is_dir())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let device = std::fs::metadata(dir).ok()?.dev();
+        Some((device, dir.to_path_buf()))
+    }
+    #[cfg(not(unix))]
+    Some((0, dir.to_path_buf()))
+}
+
+/// Bytes available to unprivileged users on the filesystem of `dir`.
+#[cfg(unix)]
+fn free_bytes(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_dir: &Path) -> Option<u64> {
+    None
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} bytes", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::entry;
+
+    #[test]
+    fn counts_growth_and_backups() {
+        let dir = crate::testing::TempDir::new("space");
+        std::fs::write(dir.join("old"), [0; 100]).unwrap();
+        std::fs::write(dir.join("source"), [0; 300]).unwrap();
+
+        let mut plan = Plan::new();
+        plan.add_group(
+            "group".to_string(),
+            vec![
+                entry(
+                    "old",
+                    dir.join("source"),
+                    dir.join("old"),
+                    FileStatus::Overwrite,
+                ),
+                entry(
+                    "new",
+                    dir.join("source"),
+                    dir.join("new"),
+                    FileStatus::Create,
+                ),
+            ],
+        );
+
+        let needs = required(&plan, Mode::File, None);
+        assert_eq!(needs.values().map(|n| n.bytes).sum::<u64>(), 200 + 300);
+        let needs = required(&plan, Mode::File, Some(&dir.join("backups")));
+        assert_eq!(
+            needs.values().map(|n| n.bytes).sum::<u64>(),
+            200 + 300 + 100
+        );
+        assert!(required(&plan, Mode::Link, None).is_empty());
+
+        assert_eq!(format_bytes(512), "512 bytes");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,13 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Directory holding doot's machine-local state, such as caches and sockets.
+pub fn dir() -> Result<PathBuf> {
+    if let Some(root) = crate::sandbox::root() {
+        return Ok(root.join("state/doot"));
+    }
+    let base = dirs::state_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+        .context("Failed to determine state directory")?;
+    Ok(base.join("doot"))
+}
@@ -0,0 +1,281 @@
+use crate::config::Config;
+use crate::history::{self, Run};
+use crate::plan::{self, FileStatus, PlanBuilder};
+use crate::space::format_bytes;
+use crate::store::Store;
+use crate::style;
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Files listed under "Most changed files".
+const TOP_FILES: usize = 10;
+
+/// Size and history of a group, for `doot stats`.
+#[derive(Debug, Serialize)]
+pub struct GroupStats {
+    pub name: String,
+    /// Why the group has no files for the resolver, if it doesn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    pub files: usize,
+    pub bytes: u64,
+    pub last_import: Option<u64>,
+    pub last_export: Option<u64>,
+    /// Recorded runs that changed files of the group
+    pub changed_runs: usize,
+}
+
+/// How often a file was changed by recorded runs.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct FileChurn {
+    pub group: String,
+    pub path: String,
+    pub runs: usize,
+}
+
+pub fn run(config: &Config, store: &dyn Store, resolver: &str) -> Result<()> {
+    let runs = history::load_runs(None)?;
+    let runs: Vec<Run> = runs.into_iter().filter(|run| !run.undone).collect();
+
+    let mut groups = Vec::new();
+    for name in config.groups.keys() {
+        groups.push(group_stats(config, store, resolver, name, &runs)?);
+    }
+    let files = churn(&runs);
+
+    let mut out = std::io::stdout();
+    if style::is_json() {
+        let report = serde_json::json!({
+            "schema_version": crate::schema::VERSION,
+            "resolver": resolver,
+            "runs": runs.len(),
+            "groups": groups,
+            "files": files,
+        });
+        writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?;
+        return Ok(());
+    }
+    write_text(&mut out, resolver, runs.len(), &groups, &files)
+}
+
+fn group_stats(
+    config: &Config,
+    store: &dyn Store,
+    resolver: &str,
+    name: &str,
+    runs: &[Run],
+) -> Result<GroupStats> {
+    let mut stats = GroupStats {
+        name: name.to_string(),
+        skipped: plan::skip_reason(config, name, resolver)?,
+        files: 0,
+        bytes: 0,
+        last_import: last_run(runs, name, "Import"),
+        last_export: last_run(runs, name, "Export"),
+        changed_runs: runs.iter().filter(|run| changes(run, name)).count(),
+    };
+    if stats.skipped.is_some() {
+        return Ok(stats);
+    }
+
+    let resolved_path = config.resolve_group_path(name, resolver)?;
+    let layers = plan::group_layers(config, name, &std::env::current_dir()?)?;
+    let entries = PlanBuilder::new(store).build_group_export(
+        config.get_group(name)?,
+        resolver,
+        &layers,
+        &resolved_path,
+    )?;
+    stats.files = entries.len();
+    stats.bytes = entries
+        .iter()
+        .map(|entry| match &entry.generated {
+            Some(content) => content.len() as u64,
+            None => std::fs::metadata(&entry.source).map_or(0, |m| m.len()),
+        })
+        .sum();
+    Ok(stats)
+}
+
+/// Whether `run` changed any file of `group`.
+fn changes(run: &Run, group: &str) -> bool {
+    run.plan.as_ref().is_some_and(|plan| {
+        plan.groups
+            .iter()
+            .any(|g| g.name == group && g.entries.iter().any(|e| e.status != FileStatus::Same))
+    })
+}
+
+/// Timestamp of the latest run of `operation` (`Import` or `Export`) that
+/// changed files of `group`.
+fn last_run(runs: &[Run], group: &str, operation: &str) -> Option<u64> {
+    runs.iter()
+        .rev()
+        .filter(|run| run.operation.starts_with(operation))
+        .find(|run| changes(run, group))
+        .map(|run| run.timestamp)
+}
+
+/// Files changed by the most runs, most first.
+fn churn(runs: &[Run]) -> Vec<FileChurn> {
+    let mut counts: BTreeMap<(String, String), usize> = BTreeMap::new();
+    for plan in runs.iter().filter_map(|run| run.plan.as_ref()) {
+        for group in &plan.groups {
+            for entry in group
+                .entries
+                .iter()
+                .filter(|e| e.status != FileStatus::Same)
+            {
+                let key = (
+                    group.name.clone(),
+                    entry.path.to_string_lossy().into_owned(),
+                );
+                *counts.entry(key).or_default() += 1;
+            }
+        }
+    }
+    let mut files: Vec<FileChurn> = counts
+        .into_iter()
+        .map(|((group, path), runs)| FileChurn { group, path, runs })
+        .collect();
+    // Stable, so ties keep path order.
+    files.sort_by_key(|file| std::cmp::Reverse(file.runs));
+    files.truncate(TOP_FILES);
+    files
+}
+
+fn write_text(
+    out: &mut dyn Write,
+    resolver: &str,
+    runs: usize,
+    groups: &[GroupStats],
+    files: &[FileChurn],
+) -> Result<()> {
+    let when = |timestamp: Option<u64>| {
+        timestamp
+            .map(history::format_timestamp)
+            .unwrap_or_else(|| "never".to_string())
+    };
+
+    writeln!(out, "Groups ({})", resolver)?;
+    for (i, group) in groups.iter().enumerate() {
+        let is_last = i == groups.len() - 1;
+        if let Some(reason) = &group.skipped {
+            writeln!(
+                out,
+                "{}{}",
+                style::branch(is_last),
+                format!("{} (skipped: {})", group.name, reason).dimmed()
+            )?;
+            continue;
+        }
+        writeln!(
+            out,
+            "{}{}: {} files, {}, changed in {} of {} runs",
+            style::branch(is_last),
+            group.name.bold(),
+            group.files,
+            format_bytes(group.bytes),
+            group.changed_runs,
+            runs
+        )?;
+        writeln!(
+            out,
+            "{}last import {}, last export {}",
+            style::trunk(is_last),
+            when(group.last_import),
+            when(group.last_export)
+        )?;
+    }
+
+    if !files.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "Most changed files")?;
+        for (i, file) in files.iter().enumerate() {
+            writeln!(
+                out,
+                "{}{}/{}: {} {}",
+                style::branch(i == files.len() - 1),
+                file.group,
+                file.path,
+                file.runs,
+                if file.runs == 1 { "run" } else { "runs" }
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{EntrySnapshot, GroupSnapshot, Snapshot};
+    use std::path::PathBuf;
+
+    fn run(operation: &str, timestamp: u64, files: &[(&str, FileStatus)]) -> Run {
+        Run {
+            id: timestamp.to_string(),
+            timestamp,
+            operation: operation.to_string(),
+            tag: None,
+            entries: Vec::new(),
+            undone: false,
+            interrupted: false,
+            pending: Vec::new(),
+            plan: Some(Snapshot {
+                schema_version: crate::schema::VERSION,
+                operation: operation.to_string(),
+                groups: vec![GroupSnapshot {
+                    name: "bash".to_string(),
+                    skipped: None,
+                    entries: files
+                        .iter()
+                        .map(|(path, status)| EntrySnapshot {
+                            path: PathBuf::from(path),
+                            destination: PathBuf::from("/home").join(path),
+                            status: status.clone(),
+                        })
+                        .collect(),
+                }],
+            }),
+        }
+    }
+
+    #[test]
+    fn counts_runs_changing_each_file() {
+        let runs = [
+            run("Export group 'bash'", 1, &[(".bashrc", FileStatus::Create)]),
+            run(
+                "Import group 'bash'",
+                2,
+                &[
+                    (".bashrc", FileStatus::Overwrite),
+                    (".inputrc", FileStatus::Overwrite),
+                ],
+            ),
+            run("Export group 'bash'", 3, &[(".bashrc", FileStatus::Same)]),
+        ];
+
+        assert_eq!(
+            churn(&runs),
+            vec![
+                FileChurn {
+                    group: "bash".to_string(),
+                    path: ".bashrc".to_string(),
+                    runs: 2,
+                },
+                FileChurn {
+                    group: "bash".to_string(),
+                    path: ".inputrc".to_string(),
+                    runs: 1,
+                },
+            ]
+        );
+        assert_eq!(last_run(&runs, "bash", "Export"), Some(1));
+        assert_eq!(last_run(&runs, "bash", "Import"), Some(2));
+        assert_eq!(runs.iter().filter(|run| changes(run, "bash")).count(), 2);
+    }
+}
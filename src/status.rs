@@ -1,11 +1,18 @@
-use crate::config::Config;
-use crate::resolver;
-use crate::store::Store;
-use anyhow::Result;
-use ignore::WalkBuilder;
-use std::path::Path;
-
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::config::{Config, GroupKind};
+use crate::plan::{self, FileStatus, PlanBuilder};
+use crate::store::{self, Store};
+use crate::style::{self, Icon};
+use crate::systemd::{self, UnitStatus};
+use crate::{doctor, git, history, machines, schema};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum GroupStatus {
     InSync,
     OutOfSync,
@@ -13,32 +20,101 @@ pub enum GroupStatus {
     Skipped,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileState {
     InSync,
     Modified,
     New,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FileStatusEntry {
     pub relative_path: String,
     pub state: FileState,
+    /// Layer the file comes from, if not the group's own directory
+    pub layer: Option<String>,
+    /// Whether the repo copy is encrypted with age
+    pub encrypted: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct GroupStatusResult {
     pub name: String,
     pub status: GroupStatus,
     pub files: Vec<FileStatusEntry>,
+    pub units: Vec<UnitStatus>,
+    /// Files in the group's repo directories with uncommitted changes
+    pub uncommitted: Vec<String>,
+    /// Directories from `dir_modes` whose mode differs from the configured one
+    pub dir_drift: Vec<DirDrift>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirDrift {
+    pub path: PathBuf,
+    pub mode: u32,
+    pub expected: u32,
+}
+
+/// Part of the config `doot status` reports on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    All,
+    Group(String),
+    /// A plan and its groups
+    Plan(String),
 }
 
-#[derive(Debug)]
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::All => write!(f, "all"),
+            Scope::Group(name) => write!(f, "group:{}", name),
+            Scope::Plan(name) => write!(f, "plan:{}", name),
+        }
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "all" => Ok(Scope::All),
+            Some(("group", name)) => Ok(Scope::Group(name.to_string())),
+            Some(("plan", name)) => Ok(Scope::Plan(name.to_string())),
+            _ => anyhow::bail!("Unknown status scope: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct PlanStatusResult {
     pub name: String,
     pub status: GroupStatus,
 }
 
+/// Returns the resolvers for which at least one files group's path exists on
+/// this machine.
+pub fn available_resolvers(config: &Config) -> Vec<String> {
+    let mut resolvers = BTreeSet::new();
+    for (group_name, group) in &config.groups {
+        if group.kind != GroupKind::Files {
+            continue;
+        }
+        for resolver in group.resolvers.keys() {
+            if config
+                .resolve_group_path(group_name, resolver)
+                .is_ok_and(|path| path.exists())
+            {
+                resolvers.insert(resolver.clone());
+            }
+        }
+    }
+    resolvers.into_iter().collect()
+}
+
 pub struct StatusChecker<'a> {
     config: &'a Config,
     store: &'a dyn Store,
@@ -55,49 +131,58 @@ impl<'a> StatusChecker<'a> {
     }
 
     pub fn check_group(&self, group_name: &str) -> Result<GroupStatusResult> {
-        let resolved_path = match self.config.get_resolver(group_name, &self.resolver) {
-            Ok(path) => path,
-            Err(_) => {
-                return Ok(GroupStatusResult {
-                    name: group_name.to_string(),
-                    status: GroupStatus::Skipped,
-                    files: Vec::new(),
-                });
-            }
-        };
+        if plan::skip_reason(self.config, group_name, &self.resolver)?.is_some() {
+            return Ok(GroupStatusResult {
+                name: group_name.to_string(),
+                status: GroupStatus::Skipped,
+                files: Vec::new(),
+                units: Vec::new(),
+                uncommitted: Vec::new(),
+                dir_drift: Vec::new(),
+            });
+        }
 
-        let resolved_path = resolver::resolve_path(resolved_path)?;
+        let resolved_path = self.config.resolve_group_path(group_name, &self.resolver)?;
         let cwd = std::env::current_dir()?;
-        let group_dir = cwd.join(group_name);
+        let layers = plan::group_layers(self.config, group_name, &cwd)?;
+        let group = self.config.get_group(group_name)?;
 
-        if !group_dir.exists() {
+        // Env groups are generated from the config and need no directory.
+        if group.kind != GroupKind::Env && layers.iter().all(|l| !l.dir.exists()) {
             return Ok(GroupStatusResult {
                 name: group_name.to_string(),
                 status: GroupStatus::New,
                 files: Vec::new(),
+                units: Vec::new(),
+                uncommitted: Vec::new(),
+                dir_drift: Vec::new(),
             });
         }
 
+        let entries = PlanBuilder::new(self.store).build_group_export(
+            group,
+            &self.resolver,
+            &layers,
+            &resolved_path,
+        )?;
+
         let mut files = Vec::new();
+        let mut unit_names = Vec::new();
         let mut has_changes = false;
         let mut all_new = true;
 
-        let walker = WalkBuilder::new(&group_dir)
-            .standard_filters(false)
-            .add_custom_ignore_filename(".dootignore")
-            .build();
-
-        for entry in walker.filter_map(|e| e.ok()) {
-            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
-                continue;
+        for entry in entries {
+            if let Some(unit) = systemd::unit_name(&entry.destination) {
+                unit_names.push(unit);
             }
 
-            let full_path = entry.path();
-            let relative = full_path.strip_prefix(&group_dir)?;
-            let relative_str = relative.to_string_lossy();
-
-            let destination = resolved_path.join(relative);
-            let state = self.compute_file_state(full_path, &destination);
+            let state = match entry.status {
+                FileStatus::Create => FileState::New,
+                FileStatus::Overwrite | FileStatus::Remove | FileStatus::Conflict => {
+                    FileState::Modified
+                }
+                FileStatus::Same => FileState::InSync,
+            };
 
             match state {
                 FileState::New => has_changes = true,
@@ -109,13 +194,18 @@ impl<'a> StatusChecker<'a> {
             }
 
             files.push(FileStatusEntry {
-                relative_path: relative_str.to_string(),
+                relative_path: entry.relative_path.to_string_lossy().to_string(),
                 state,
+                encrypted: entry.is_encrypted(),
+                layer: entry.layer,
             });
         }
 
         files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
 
+        unit_names.sort();
+        let units = systemd::query(&unit_names);
+
         let status = if files.is_empty() {
             GroupStatus::New
         } else if !has_changes {
@@ -126,23 +216,44 @@ impl<'a> StatusChecker<'a> {
             GroupStatus::OutOfSync
         };
 
+        // In link mode, editing a destination edits the repo copy, which
+        // only shows up as an uncommitted change.
+        let mut uncommitted: Vec<_> = layers
+            .iter()
+            .filter(|layer| layer.dir.exists())
+            .filter_map(|layer| git::uncommitted_files(&layer.dir))
+            .flatten()
+            .collect();
+        uncommitted.sort();
+        uncommitted.dedup();
+
+        let dir_drift = if store::is_pseudo_path(&resolved_path) {
+            Vec::new()
+        } else {
+            plan::parse_dir_modes(&group.dir_modes)?
+                .into_iter()
+                .filter_map(|(dir, expected)| {
+                    let path = plan::join_dir(&resolved_path, &dir);
+                    let mode = store::dir_mode(&path)?;
+                    (mode != expected).then_some(DirDrift {
+                        path,
+                        mode,
+                        expected,
+                    })
+                })
+                .collect()
+        };
+
         Ok(GroupStatusResult {
             name: group_name.to_string(),
             status,
             files,
+            units,
+            uncommitted,
+            dir_drift,
         })
     }
 
-    fn compute_file_state(&self, source: &Path, destination: &Path) -> FileState {
-        if !self.store.exists(destination) {
-            FileState::New
-        } else if self.store.compare(source, destination).unwrap_or(false) {
-            FileState::InSync
-        } else {
-            FileState::Modified
-        }
-    }
-
     pub fn check_all_groups(&self) -> Result<Vec<GroupStatusResult>> {
         let mut results = Vec::new();
         let mut group_names: Vec<_> = self.config.groups.keys().collect();
@@ -155,6 +266,32 @@ impl<'a> StatusChecker<'a> {
         Ok(results)
     }
 
+    /// Checks the plans and groups in `scope`; a single group has no plans.
+    pub fn check_scope(
+        &self,
+        scope: &Scope,
+    ) -> Result<(Vec<PlanStatusResult>, Vec<GroupStatusResult>)> {
+        match scope {
+            Scope::All => {
+                let groups = self.check_all_groups()?;
+                Ok((self.check_all_plans(&groups), groups))
+            }
+            Scope::Group(name) => {
+                self.config.get_group(name)?;
+                Ok((Vec::new(), vec![self.check_group(name)?]))
+            }
+            Scope::Plan(name) => {
+                let mut names = self.config.get_plan_groups(name)?;
+                names.sort();
+                let groups = names
+                    .iter()
+                    .map(|group| self.check_group(group))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((vec![self.check_plan(name, &groups)], groups))
+            }
+        }
+    }
+
     pub fn check_plan(
         &self,
         plan_name: &str,
@@ -214,3 +351,325 @@ impl<'a> StatusChecker<'a> {
         results
     }
 }
+
+/// Renders the status tree of `scope` for `resolver` to `out`.
+pub fn write(
+    out: &mut dyn Write,
+    config: &Config,
+    store: &dyn Store,
+    resolver: &str,
+    scope: &Scope,
+    verbose: bool,
+) -> Result<()> {
+    let checker = StatusChecker::new(config, store, resolver.to_string());
+
+    let (plan_results, group_results) = checker.check_scope(scope)?;
+    if style::is_json() {
+        let report = serde_json::json!({
+            "schema_version": schema::VERSION,
+            "resolver": resolver,
+            "plans": plan_results,
+            "groups": group_results,
+        });
+        writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?;
+        return Ok(());
+    }
+
+    if !matches!(scope, Scope::Group(_)) {
+        writeln!(out, "Plans ({})", resolver)?;
+        for (i, plan) in plan_results.iter().enumerate() {
+            let is_last = i == plan_results.len() - 1;
+            let prefix = style::branch(is_last);
+
+            print_status_line(out, prefix, &plan.name, &plan.status)?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "Groups ({})", resolver)?;
+    for (i, group) in group_results.iter().enumerate() {
+        let is_last = i == group_results.len() - 1;
+        let prefix = style::branch(is_last);
+        let child_prefix = style::trunk(is_last);
+
+        print_status_line(out, prefix, &group.name, &group.status)?;
+
+        let files: &[_] = if verbose && group.status != GroupStatus::Skipped {
+            &group.files
+        } else {
+            &[]
+        };
+        let units: Vec<_> = group
+            .units
+            .iter()
+            .filter(|unit| verbose || !unit.is_healthy())
+            .collect();
+        let child_count =
+            files.len() + units.len() + group.uncommitted.len() + group.dir_drift.len();
+
+        for (j, file) in files.iter().enumerate() {
+            let is_last_file = j == child_count - 1;
+            let file_prefix = style::branch(is_last_file);
+
+            let mut path = match &file.layer {
+                Some(layer) => {
+                    format!("{} {}", file.relative_path, format!("({})", layer).dimmed())
+                }
+                None => file.relative_path.clone(),
+            };
+            if file.encrypted {
+                path = format!("{} {}", path, "(encrypted)".magenta());
+            }
+            print_file_status_line(out, child_prefix, file_prefix, &path, &file.state)?;
+        }
+
+        for (j, unit) in units.iter().enumerate() {
+            let is_last_unit = files.len() + j == child_count - 1;
+            let unit_prefix = style::branch(is_last_unit);
+
+            print_unit_status_line(out, child_prefix, unit_prefix, unit)?;
+        }
+
+        for (j, path) in group.uncommitted.iter().enumerate() {
+            let is_last_change = files.len() + units.len() + j == child_count - 1;
+            let change_prefix = style::branch(is_last_change);
+
+            writeln!(
+                out,
+                "{child_prefix}{change_prefix}[{}] {} {}",
+                Icon::Uncommitted.glyph().magenta(),
+                path,
+                "(uncommitted)".dimmed()
+            )?;
+        }
+
+        for (j, drift) in group.dir_drift.iter().enumerate() {
+            let is_last_drift =
+                files.len() + units.len() + group.uncommitted.len() + j == child_count - 1;
+            let drift_prefix = style::branch(is_last_drift);
+
+            writeln!(
+                out,
+                "{child_prefix}{drift_prefix}[{}] {} {}",
+                Icon::Warning.glyph().yellow(),
+                drift.path.display(),
+                format!("(mode {:o}, expected {:o})", drift.mode, drift.expected).dimmed()
+            )?;
+        }
+    }
+
+    if *scope == Scope::All {
+        let repo = std::env::current_dir().context("Failed to get current directory")?;
+        let undeclared = doctor::undeclared_groups(config, &repo)?;
+        if !undeclared.is_empty() {
+            writeln!(out)?;
+        }
+        for name in undeclared {
+            writeln!(
+                out,
+                "[{}] Directory '{}' looks like a group but isn't in doot.yaml; \
+                 `doot doctor` offers to add it",
+                Icon::Warning.glyph().yellow(),
+                name
+            )?;
+        }
+    }
+
+    if config.track_machines && *scope == Scope::All {
+        let repo = std::env::current_dir().context("Failed to get current directory")?;
+        if !machines::ever_exported(&repo)? {
+            writeln!(
+                out,
+                "\n[{}] This config has never been exported to this machine.",
+                Icon::Warning.glyph().yellow()
+            )?;
+        }
+        write_machines(out, store)?;
+    }
+
+    Ok(())
+}
+
+/// Lists the machines recorded in the repo and the groups they haven't synced
+/// since the repo changed.
+fn write_machines(out: &mut dyn Write, store: &dyn Store) -> Result<()> {
+    let repo = std::env::current_dir().context("Failed to get current directory")?;
+    let machines = machines::load_all(&repo)?;
+    if machines.is_empty() {
+        return Ok(());
+    }
+    let this = machines::local()?.id;
+
+    writeln!(out)?;
+    writeln!(out, "Machines")?;
+    for (i, machine) in machines.iter().enumerate() {
+        let prefix = style::branch(i == machines.len() - 1);
+        let behind: Vec<_> = machine
+            .groups
+            .iter()
+            .filter(|(_, sync)| {
+                machines::freshness(&repo, store, sync) == machines::Freshness::Behind
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let last = machine.groups.values().map(|sync| sync.timestamp).max();
+
+        let mut name = machine.name.clone();
+        if let Some(profile) = &machine.profile {
+            name = format!("{} [{}]", name, profile);
+        }
+        if machine.id == this {
+            name = format!("{} {}", name, "(this machine)".dimmed());
+        }
+        let state = if machine.groups.is_empty() {
+            "never synced".dimmed()
+        } else if behind.is_empty() {
+            "up to date".blue()
+        } else {
+            format!("behind on {}", behind.join(", ")).yellow()
+        };
+        let last = last
+            .map(|timestamp| format!(" (last run {})", history::format_timestamp(timestamp)))
+            .unwrap_or_default();
+        writeln!(out, "{prefix}{}: {}{}", name, state, last.dimmed())?;
+    }
+    Ok(())
+}
+
+/// Renders the status of every group against every available resolver.
+pub fn write_matrix(out: &mut dyn Write, config: &Config, store: &dyn Store) -> Result<()> {
+    let resolvers = available_resolvers(config);
+    if resolvers.is_empty() {
+        writeln!(out, "No resolver has paths on this machine.")?;
+        return Ok(());
+    }
+
+    let mut columns = Vec::new();
+    for resolver in &resolvers {
+        let checker = StatusChecker::new(config, store, resolver.clone());
+        columns.push(checker.check_all_groups()?);
+    }
+
+    let mut groups: Vec<_> = config.groups.keys().collect();
+    groups.sort();
+    let width = groups.iter().map(|g| g.len()).max().unwrap_or(0).max(6);
+
+    // Wide enough for the resolver name and the longest cell (words in --ascii).
+    let cell_widths: Vec<usize> = resolvers
+        .iter()
+        .map(|r| {
+            r.chars()
+                .count()
+                .max(Icon::Modified.glyph().chars().count())
+        })
+        .collect();
+
+    write!(out, "{:width$}", "Groups")?;
+    for (resolver, w) in resolvers.iter().zip(&cell_widths) {
+        write!(out, "  {:w$}", resolver)?;
+    }
+    writeln!(out)?;
+
+    for group in groups {
+        write!(out, "{:width$}", group)?;
+        for (w, results) in cell_widths.iter().zip(&columns) {
+            let status = results
+                .iter()
+                .find(|r| &r.name == group)
+                .map_or(&GroupStatus::Skipped, |r| &r.status);
+            let (glyph, color) = match status {
+                GroupStatus::InSync => (Icon::InSync.glyph(), "blue"),
+                GroupStatus::OutOfSync => (Icon::Modified.glyph(), "yellow"),
+                GroupStatus::New => (Icon::New.glyph(), "green"),
+                GroupStatus::Skipped => ("-", "bright black"),
+            };
+            let cell = format!("{:w$}", glyph);
+            write!(out, "  {}", cell.color(color))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn print_status_line(
+    out: &mut dyn Write,
+    prefix: &str,
+    name: &str,
+    status: &GroupStatus,
+) -> io::Result<()> {
+    match status {
+        GroupStatus::InSync => {
+            writeln!(out, "{prefix}[{}] {}", Icon::InSync.glyph().blue(), name)?;
+        }
+        GroupStatus::OutOfSync => {
+            writeln!(
+                out,
+                "{prefix}[{}] {}",
+                Icon::Modified.glyph().yellow(),
+                name
+            )?;
+        }
+        GroupStatus::New => {
+            writeln!(out, "{prefix}[{}] {}", Icon::New.glyph().green(), name)?;
+        }
+        GroupStatus::Skipped => {
+            writeln!(out, "{prefix}{}", name.dimmed())?;
+        }
+    }
+    Ok(())
+}
+
+fn print_file_status_line(
+    out: &mut dyn Write,
+    child_prefix: &str,
+    file_prefix: &str,
+    path: &str,
+    state: &FileState,
+) -> io::Result<()> {
+    match state {
+        FileState::InSync => {
+            writeln!(
+                out,
+                "{child_prefix}{file_prefix}[{}] {}",
+                Icon::InSync.glyph().blue(),
+                path
+            )?;
+        }
+        FileState::Modified => {
+            writeln!(
+                out,
+                "{child_prefix}{file_prefix}[{}] {}",
+                Icon::Modified.glyph().yellow(),
+                path
+            )?;
+        }
+        FileState::New => {
+            writeln!(
+                out,
+                "{child_prefix}{file_prefix}[{}] {}",
+                Icon::New.glyph().green(),
+                path
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn print_unit_status_line(
+    out: &mut dyn Write,
+    child_prefix: &str,
+    unit_prefix: &str,
+    unit: &systemd::UnitStatus,
+) -> io::Result<()> {
+    let icon = if unit.is_healthy() {
+        Icon::InSync.glyph().blue()
+    } else {
+        Icon::Warning.glyph().yellow()
+    };
+
+    writeln!(
+        out,
+        "{child_prefix}{unit_prefix}[{}] {} ({}, {})",
+        icon, unit.name, unit.enabled, unit.active
+    )
+}
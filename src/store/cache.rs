@@ -0,0 +1,281 @@
+use super::{Metadata, Store};
+use crate::config::HashAlgorithm;
+use crate::users::User;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Files modified this recently aren't cached: a change within the same
+/// timestamp tick, keeping the size, would go unnoticed.
+const SETTLE: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize)]
+struct CachedHash {
+    len: u64,
+    modified: SystemTime,
+    hash: String,
+}
+
+/// The hashes saved between runs, for one hash algorithm.
+#[derive(Default, Serialize, Deserialize)]
+struct Saved {
+    algorithm: String,
+    hashes: HashMap<PathBuf, CachedHash>,
+}
+
+/// Wraps a store and remembers file hashes until a file's size or
+/// modification time changes, so repeated comparisons only `stat` files.
+pub struct CachingStore {
+    inner: Box<dyn Store>,
+    hashes: Mutex<HashMap<PathBuf, CachedHash>>,
+    /// Where the hashes are kept between runs, if anywhere
+    path: Option<PathBuf>,
+    /// Directories of `sensitive` groups, on the machine and in the repo,
+    /// whose hashes are never cached
+    sensitive: Vec<PathBuf>,
+}
+
+fn algorithm_name(algorithm: HashAlgorithm) -> String {
+    format!("{:?}", algorithm).to_lowercase()
+}
+
+impl CachingStore {
+    pub fn new(inner: Box<dyn Store>) -> Self {
+        Self {
+            inner,
+            hashes: Mutex::new(HashMap::new()),
+            path: None,
+            sensitive: Vec::new(),
+        }
+    }
+
+    /// Wraps `inner` with the hashes saved in doot's state by earlier runs.
+    /// A missing or unreadable cache starts empty.
+    pub fn load(inner: Box<dyn Store>) -> Result<Self> {
+        let path = crate::state::dir()?.join("hashes.json");
+        let saved: Saved = std::fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        let hashes = match saved.algorithm == algorithm_name(inner.hash_algorithm()) {
+            true => saved.hashes,
+            false => HashMap::new(),
+        };
+        Ok(Self {
+            inner,
+            hashes: Mutex::new(hashes),
+            path: Some(path),
+            sensitive: Vec::new(),
+        })
+    }
+
+    /// Keeps the hashes of files under `roots` out of the cache.
+    pub fn sensitive(mut self, roots: Vec<PathBuf>) -> Self {
+        self.sensitive = roots;
+        self
+    }
+
+    fn is_sensitive(&self, path: &Path) -> bool {
+        self.sensitive.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Saves the hashes for later runs, dropping those of files that are
+    /// gone or belong to `sensitive` groups.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut hashes = std::mem::take(&mut *self.hashes.lock().unwrap());
+        hashes.retain(|file, _| file.exists() && !self.is_sensitive(file));
+        let saved = Saved {
+            algorithm: algorithm_name(self.inner.hash_algorithm()),
+            hashes,
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let staged = path.with_extension("json.tmp");
+        crate::history::write_private(&staged, &serde_json::to_vec(&saved)?)?;
+        std::fs::rename(&staged, path)
+            .with_context(|| format!("Failed to write: {}", path.display()))?;
+        *self.hashes.lock().unwrap() = saved.hashes;
+        Ok(())
+    }
+
+    pub fn invalidate(&self, path: &Path) {
+        self.hashes.lock().unwrap().remove(path);
+    }
+
+    /// Paths that currently have a cached hash.
+    pub fn cached_paths(&self) -> Vec<PathBuf> {
+        self.hashes.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The cached hash of `path`, if its size and modification time still
+    /// match `meta`.
+    fn cached(&self, path: &Path, meta: &Metadata) -> Option<String> {
+        let hashes = self.hashes.lock().unwrap();
+        let cached = hashes.get(path)?;
+        (cached.len == meta.len && cached.modified == meta.modified).then(|| cached.hash.clone())
+    }
+}
+
+impl Store for CachingStore {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.inner.hash_algorithm()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.invalidate(path);
+        self.inner.write(path, content)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.invalidate(path);
+        self.inner.remove(path)
+    }
+
+    fn hash(&self, path: &Path) -> Result<String> {
+        let Some(meta) = self.inner.metadata(path) else {
+            return self.inner.hash(path);
+        };
+        if let Some(hash) = self.cached(path, &meta) {
+            return Ok(hash);
+        }
+
+        let hash = self.inner.hash(path)?;
+        let settled = SystemTime::now()
+            .duration_since(meta.modified)
+            .is_ok_and(|age| age >= SETTLE);
+        if settled && !self.is_sensitive(path) {
+            self.hashes.lock().unwrap().insert(
+                path.to_path_buf(),
+                CachedHash {
+                    len: meta.len,
+                    modified: meta.modified,
+                    hash: hash.clone(),
+                },
+            );
+        }
+        Ok(hash)
+    }
+
+    fn is_privileged(&self, path: &Path) -> bool {
+        self.inner.is_privileged(path)
+    }
+
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    /// Files of different sizes differ, and files that both have cached
+    /// hashes are compared by them. Otherwise the inner store compares the
+    /// contents, stopping at the first difference; only explicit `hash` calls
+    /// fill the cache.
+    fn compare(&self, a: &Path, b: &Path) -> Result<bool> {
+        if !self.exists(a) || !self.exists(b) {
+            return Ok(false);
+        }
+        let (Some(meta_a), Some(meta_b)) = (self.metadata(a), self.metadata(b)) else {
+            return self.inner.compare(a, b);
+        };
+        if meta_a.len != meta_b.len {
+            return Ok(false);
+        }
+        match (self.cached(a, &meta_a), self.cached(b, &meta_b)) {
+            (Some(hash_a), Some(hash_b)) => Ok(hash_a == hash_b),
+            _ => self.inner.compare(a, b),
+        }
+    }
+
+    fn link(&self, source: &Path, target: &Path) -> Result<()> {
+        self.invalidate(target);
+        self.inner.link(source, target)
+    }
+
+    fn create_dir(&self, path: &Path, mode: u32) -> Result<()> {
+        self.inner.create_dir(path, mode)
+    }
+
+    fn make_readonly(&self, path: &Path) -> Result<()> {
+        self.inner.make_readonly(path)
+    }
+
+    fn hide(&self, path: &Path) -> Result<()> {
+        self.inner.hide(path)
+    }
+
+    fn chown(&self, path: &Path, owner: &User) -> Result<()> {
+        self.inner.chown(path, owner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::FileStore;
+
+    #[test]
+    fn cached_hashes_decide_comparisons() {
+        let dir = crate::testing::TempDir::new("cache");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::write(&a, b"one").unwrap();
+        std::fs::write(&b, b"two").unwrap();
+        let settled = SystemTime::now() - Duration::from_secs(60);
+        for path in [&a, &b] {
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(settled)
+                .unwrap();
+        }
+
+        let store = CachingStore::new(Box::new(FileStore::new(HashAlgorithm::default())))
+            .sensitive(vec![dir.join("secret")]);
+        // Uncached files are compared by content, not hashed.
+        assert!(!store.compare(&a, &b).unwrap());
+        assert!(store.cached_paths().is_empty());
+        store.hash(&a).unwrap();
+        store.hash(&b).unwrap();
+        assert_eq!(store.cached_paths().len(), 2);
+        assert!(!store.compare(&a, &b).unwrap());
+
+        // A cached record with matching size and time is trusted as is.
+        store.hashes.lock().unwrap().get_mut(&b).unwrap().hash = store.hash(&a).unwrap();
+        assert!(store.compare(&a, &b).unwrap());
+
+        // Sizes differ: no hashing needed.
+        std::fs::write(&b, b"three").unwrap();
+        assert!(!store.compare(&a, &b).unwrap());
+
+        // Files of sensitive groups are never cached.
+        std::fs::create_dir_all(dir.join("secret")).unwrap();
+        let secret = dir.join("secret/key");
+        std::fs::write(&secret, b"key").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&secret)
+            .unwrap()
+            .set_modified(settled)
+            .unwrap();
+        store.hash(&secret).unwrap();
+        assert!(!store.cached_paths().contains(&secret));
+    }
+}
@@ -0,0 +1,230 @@
+use super::{Metadata, Store};
+use crate::config::ContainerLimits;
+use crate::error::DootError;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Container engines whose `<engine>://<container>/<path>` resolvers address
+/// files inside a running container.
+pub const ENGINES: &[&str] = &["docker", "podman"];
+
+static LIMITS: RwLock<Option<ContainerLimits>> = RwLock::new(None);
+
+/// Wait before the first retry of a timed-out call.
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// Sets the limits of container calls for the rest of the run.
+pub fn configure(limits: &ContainerLimits) {
+    *LIMITS.write().unwrap() = Some(limits.clone());
+}
+
+/// A path inside a container, split from its pseudo path.
+struct Target<'a> {
+    engine: &'a str,
+    container: &'a str,
+    path: String,
+}
+
+fn parse(path: &Path) -> Option<Target<'_>> {
+    let path = path.to_str()?;
+    ENGINES.iter().find_map(|engine| {
+        let rest = path.strip_prefix(engine)?.strip_prefix("://")?;
+        let (container, inner) = rest.split_once('/')?;
+        Some(Target {
+            engine,
+            container,
+            path: format!("/{}", inner),
+        })
+    })
+}
+
+pub fn is_container_path(path: &Path) -> bool {
+    parse(path).is_some()
+}
+
+/// Returns the engine program a container pseudo path needs, if it is one.
+pub fn engine_of(path: &Path) -> Option<&'static str> {
+    let engine = parse(path)?.engine;
+    ENGINES.iter().copied().find(|e| *e == engine)
+}
+
+/// Returns the daemon `engine` talks to if it is on another machine, as set by
+/// `DOCKER_HOST` or `CONTAINER_HOST`.
+pub fn remote_host(engine: &str) -> Option<String> {
+    let variable = match engine {
+        "docker" => "DOCKER_HOST",
+        "podman" => "CONTAINER_HOST",
+        _ => return None,
+    };
+    crate::process::variable(variable)
+        .filter(|host| !host.is_empty() && !host.starts_with("unix://"))
+}
+
+/// Lists the files below the directory `dir` in a container, relative to it.
+pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let target = parse(dir).context("Not a container path")?;
+    let output = target.run(&["find", &target.path, "-type", "f", "-print0"], None)?;
+    if !output.status.success() {
+        // A missing directory has no files, as on the host.
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}/", target.path.trim_end_matches('/'));
+    Ok(output
+        .stdout
+        .split(|b| *b == 0)
+        .filter_map(|name| std::str::from_utf8(name).ok())
+        .filter_map(|name| name.strip_prefix(&prefix))
+        .map(PathBuf::from)
+        .collect())
+}
+
+impl Target<'_> {
+    fn exec(&self, args: &[&str], interactive: bool) -> Command {
+        let mut command = crate::process::command(self.engine);
+        command.arg("exec");
+        if interactive {
+            command.arg("-i");
+        }
+        command.arg(self.container).args(args);
+        command
+    }
+
+    /// Runs `args` in the container, with `input` on stdin if given. Calls
+    /// that time out are retried with a growing wait, since an engine that
+    /// is starting up or under load often answers a moment later.
+    fn run(&self, args: &[&str], input: Option<&[u8]>) -> Result<Output> {
+        if let Some(host) = remote_host(self.engine) {
+            crate::offline::ensure_online(&format!("{} at {}", self.engine, host))?;
+        }
+        let limits = LIMITS.read().unwrap().clone().unwrap_or_default();
+        let timeout = Duration::from_secs(limits.timeout);
+        let mut attempt = 0;
+        loop {
+            let command = self.exec(args, input.is_some());
+            match crate::process::output_within(command, input.unwrap_or_default(), timeout) {
+                Err(error) if crate::error::code(&error) == "timeout" => {
+                    if attempt == limits.retries {
+                        return Err(DootError::Timeout(format!(
+                            "{} {} didn't answer in {} attempt(s) of {}s",
+                            self.engine,
+                            self.container,
+                            attempt + 1,
+                            limits.timeout
+                        ))
+                        .into());
+                    }
+                    std::thread::sleep(backoff(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn fail(&self, action: &str, stderr: &[u8]) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Failed to {} {}:{}: {}",
+            action,
+            self.container,
+            self.path,
+            String::from_utf8_lossy(stderr).trim()
+        )
+    }
+}
+
+/// Wait before retry number `attempt + 1`.
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF * 2u32.saturating_pow(attempt)
+}
+
+/// Reads and writes files in containers with `docker exec` (or `podman exec`).
+pub struct ContainerStore;
+
+impl ContainerStore {
+    fn target(path: &Path) -> Result<Target<'_>> {
+        parse(path).with_context(|| format!("Not a container path: {}", path.display()))
+    }
+}
+
+impl Store for ContainerStore {
+    fn name(&self) -> &'static str {
+        "container"
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let target = Self::target(path)?;
+        let output = target.run(&["cat", "--", &target.path], None)?;
+        if !output.status.success() {
+            return Err(target.fail("read", &output.stderr));
+        }
+        Ok(output.stdout)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let target = Self::target(path)?;
+        let output = target.run(
+            &[
+                "sh",
+                "-c",
+                "mkdir -p \"$(dirname \"$1\")\" && cat > \"$1\"",
+                "sh",
+                &target.path,
+            ],
+            Some(content),
+        )?;
+        if !output.status.success() {
+            return Err(target.fail("write", &output.stderr));
+        }
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let Ok(target) = Self::target(path) else {
+            return false;
+        };
+        target
+            .run(&["test", "-f", &target.path], None)
+            .is_ok_and(|output| output.status.success())
+    }
+
+    fn metadata(&self, _path: &Path) -> Option<Metadata> {
+        None
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        let target = Self::target(path)?;
+        let output = target.run(&["rm", "-f", "--", &target.path], None)?;
+        if !output.status.success() {
+            return Err(target.fail("remove", &output.stderr));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_engine_container_and_path() {
+        let path = Path::new("docker://dev/home/me/.bashrc");
+        let target = parse(path).unwrap();
+        assert_eq!(
+            (target.engine, target.container, target.path.as_str()),
+            ("docker", "dev", "/home/me/.bashrc")
+        );
+        assert_eq!(engine_of(Path::new("podman://box/root")), Some("podman"));
+        assert!(!is_container_path(Path::new("/home/me/.bashrc")));
+        assert!(!is_container_path(Path::new("docker://dev")));
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,110 @@
+use super::{Metadata, Store};
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of the pseudo paths that address a user's installed crontab.
+pub const SCHEME: &str = "crontab:";
+
+/// Returns the pseudo path for the crontab of `user` (empty for the current user).
+pub fn path_for(user: &str) -> PathBuf {
+    PathBuf::from(format!("{SCHEME}{user}"))
+}
+
+pub fn is_crontab_path(path: &Path) -> bool {
+    path.to_str().is_some_and(|p| p.starts_with(SCHEME))
+}
+
+pub struct CrontabStore;
+
+impl CrontabStore {
+    fn command(path: &Path) -> Command {
+        let mut command = crate::process::command("crontab");
+
+        let user = path
+            .to_str()
+            .and_then(|p| p.strip_prefix(SCHEME))
+            .unwrap_or_default();
+        let current_user = std::env::var("USER").unwrap_or_default();
+        if !user.is_empty() && user != current_user {
+            command.args(["-u", user]);
+        }
+
+        command
+    }
+}
+
+impl Store for CrontabStore {
+    fn name(&self) -> &'static str {
+        "crontab"
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let output = Self::command(path)
+            .arg("-l")
+            .output()
+            .context("Failed to run crontab -l")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "crontab -l failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(output.stdout)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        let mut child = Self::command(path)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run crontab -")?;
+
+        child
+            .stdin
+            .take()
+            .context("Failed to open crontab stdin")?
+            .write_all(content)
+            .context("Failed to write crontab")?;
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "crontab - failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        Self::command(path)
+            .arg("-l")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    fn metadata(&self, _path: &Path) -> Option<Metadata> {
+        None
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        if self.exists(path) {
+            let status = Self::command(path)
+                .arg("-r")
+                .status()
+                .context("Failed to run crontab -r")?;
+            if !status.success() {
+                anyhow::bail!("crontab -r failed");
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,25 +1,41 @@
 use super::Store;
-use anyhow::{Context, Result};
+use crate::config::HashAlgorithm;
+use crate::error::DootError;
+use anyhow::Result;
 use std::path::Path;
 
-pub struct FileStore;
+pub struct FileStore {
+    hash: HashAlgorithm,
+}
+
+impl FileStore {
+    pub fn new(hash: HashAlgorithm) -> Self {
+        Self { hash }
+    }
+}
 
 impl Store for FileStore {
     fn name(&self) -> &'static str {
         "file"
     }
 
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash
+    }
+
     fn read(&self, path: &Path) -> Result<Vec<u8>> {
-        std::fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))
+        Ok(std::fs::read(path).map_err(|error| DootError::io("read", path, error))?)
     }
 
     fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+                .map_err(|error| DootError::io("create directory", parent, error))?;
         }
-        std::fs::write(path, content)
-            .with_context(|| format!("Failed to write: {}", path.display()))
+
+        super::ensure_writable(path)?;
+        std::fs::write(path, content).map_err(|error| DootError::io("write", path, error))?;
+        Ok(())
     }
 
     fn exists(&self, path: &Path) -> bool {
@@ -28,9 +44,15 @@ impl Store for FileStore {
 
     fn remove(&self, path: &Path) -> Result<()> {
         if path.exists() {
-            std::fs::remove_file(path)
-                .with_context(|| format!("Failed to remove: {}", path.display()))?;
+            std::fs::remove_file(path).map_err(|error| DootError::io("remove", path, error))?;
         }
         Ok(())
     }
+
+    fn compare(&self, a: &Path, b: &Path) -> Result<bool> {
+        if !self.exists(a) || !self.exists(b) {
+            return Ok(false);
+        }
+        super::compare_files(a, b)
+    }
 }
@@ -1,25 +1,40 @@
 use super::Store;
+use crate::config::HashAlgorithm;
+use crate::error::DootError;
 use anyhow::{Context, Result};
 use std::path::Path;
 
-pub struct LinkStore;
+pub struct LinkStore {
+    hash: HashAlgorithm,
+}
+
+impl LinkStore {
+    pub fn new(hash: HashAlgorithm) -> Self {
+        Self { hash }
+    }
+}
 
 impl Store for LinkStore {
     fn name(&self) -> &'static str {
         "link"
     }
 
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash
+    }
+
     fn read(&self, path: &Path) -> Result<Vec<u8>> {
-        std::fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))
+        Ok(std::fs::read(path).map_err(|error| DootError::io("read", path, error))?)
     }
 
     fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+                .map_err(|error| DootError::io("create directory", parent, error))?;
         }
-        std::fs::write(path, content)
-            .with_context(|| format!("Failed to write: {}", path.display()))
+        super::ensure_writable(path)?;
+        std::fs::write(path, content).map_err(|error| DootError::io("write", path, error))?;
+        Ok(())
     }
 
     fn exists(&self, path: &Path) -> bool {
@@ -28,18 +43,16 @@ impl Store for LinkStore {
 
     fn remove(&self, path: &Path) -> Result<()> {
         if path.exists() || path.is_symlink() {
-            std::fs::remove_file(path)
-                .with_context(|| format!("Failed to remove: {}", path.display()))?;
+            std::fs::remove_file(path).map_err(|error| DootError::io("remove", path, error))?;
         }
         Ok(())
     }
 
-    fn hash(&self, path: &Path) -> Result<String> {
-        use sha2::{Digest, Sha256};
-        let content = self.read(path)?;
-        let mut hasher = Sha256::new();
-        hasher.update(&content);
-        Ok(hex::encode(hasher.finalize()))
+    fn compare(&self, a: &Path, b: &Path) -> Result<bool> {
+        if !self.exists(a) || !self.exists(b) {
+            return Ok(false);
+        }
+        super::compare_files(a, b)
     }
 }
 
@@ -47,12 +60,12 @@ impl LinkStore {
     pub fn create_symlink(source: &Path, target: &Path) -> Result<()> {
         if let Some(parent) = target.parent() {
             std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+                .map_err(|error| DootError::io("create directory", parent, error))?;
         }
 
         if target.exists() || target.is_symlink() {
             std::fs::remove_file(target)
-                .with_context(|| format!("Failed to remove existing: {}", target.display()))?;
+                .map_err(|error| DootError::io("remove existing", target, error))?;
         }
 
         #[cfg(unix)]
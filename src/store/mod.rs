@@ -1,11 +1,106 @@
+pub mod cache;
+pub mod container;
+pub mod crontab;
 mod file;
 mod link;
+mod recording;
+mod router;
+pub mod sudo;
 
+pub use cache::CachingStore;
 pub use file::FileStore;
 pub use link::LinkStore;
+#[allow(unused_imports)]
+pub use recording::Mutation;
+pub use recording::RecordingStore;
+pub use router::RouterStore;
 
-use anyhow::Result;
-use std::path::Path;
+use crate::config::HashAlgorithm;
+use crate::users::User;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Whether `path` addresses something other than a local file (a crontab or
+/// a file in a container), which can't be linked or have its permissions set.
+pub fn is_pseudo_path(path: &Path) -> bool {
+    crontab::is_crontab_path(path) || container::is_container_path(path)
+}
+
+/// Size and modification time of a file, enough to tell most changes apart
+/// without reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+/// Metadata of the local file at `path`, following symlinks.
+pub fn local_metadata(path: &Path) -> Option<Metadata> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some(Metadata {
+        len: meta.len(),
+        modified: meta.modified().ok()?,
+    })
+}
+
+/// Hashes `content` with `algorithm`, returning a hex digest.
+pub fn hash_bytes(algorithm: HashAlgorithm, content: &[u8]) -> String {
+    match algorithm {
+        HashAlgorithm::Blake3 => blake3::hash(content).to_hex().to_string(),
+        HashAlgorithm::Sha256 => {
+            use sha2::{Digest, Sha256};
+            hex::encode(Sha256::digest(content))
+        }
+    }
+}
+
+/// Compares two files on disk byte by byte, stopping at the first differing
+/// chunk. Files of different sizes are never read.
+pub fn compare_files(a: &Path, b: &Path) -> Result<bool> {
+    use anyhow::Context;
+    use std::io::Read;
+
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    let len_a = std::fs::metadata(a)
+        .with_context(|| format!("Failed to stat: {}", a.display()))?
+        .len();
+    let len_b = std::fs::metadata(b)
+        .with_context(|| format!("Failed to stat: {}", b.display()))?
+        .len();
+    if len_a != len_b {
+        return Ok(false);
+    }
+
+    let mut file_a =
+        std::fs::File::open(a).with_context(|| format!("Failed to read: {}", a.display()))?;
+    let mut file_b =
+        std::fs::File::open(b).with_context(|| format!("Failed to read: {}", b.display()))?;
+    let mut buf_a = vec![0; CHUNK_SIZE];
+    let mut buf_b = vec![0; CHUNK_SIZE];
+
+    loop {
+        let read = read_chunk(&mut file_a, &mut buf_a)?;
+        if read != read_chunk(&mut file_b, &mut buf_b)? || buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+
+    fn read_chunk(file: &mut std::fs::File, buf: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match file.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        Ok(filled)
+    }
+}
 
 pub trait Store: Send + Sync {
     #[allow(dead_code)]
@@ -20,27 +115,198 @@ pub trait Store: Send + Sync {
     #[allow(dead_code)]
     fn remove(&self, path: &Path) -> Result<()>;
 
+    /// Algorithm used by `hash` when comparing contents.
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::default()
+    }
+
     fn hash(&self, path: &Path) -> Result<String> {
-        use sha2::{Digest, Sha256};
         let content = self.read(path)?;
-        let mut hasher = Sha256::new();
-        hasher.update(&content);
-        Ok(hex::encode(hasher.finalize()))
+        Ok(hash_bytes(self.hash_algorithm(), &content))
+    }
+
+    /// Whether changes to `path` go through sudo.
+    fn is_privileged(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Size and modification time of `path`, if the store can tell them
+    /// without reading the file.
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        local_metadata(path)
     }
 
     fn compare(&self, a: &Path, b: &Path) -> Result<bool> {
         if !self.exists(a) || !self.exists(b) {
             return Ok(false);
         }
+        if let (Some(meta_a), Some(meta_b)) = (self.metadata(a), self.metadata(b)) {
+            if meta_a.len != meta_b.len {
+                return Ok(false);
+            }
+        }
         let hash_a = self.hash(a)?;
         let hash_b = self.hash(b)?;
         Ok(hash_a == hash_b)
     }
+
+    /// Replaces `target` with a symlink to `source`.
+    fn link(&self, source: &Path, target: &Path) -> Result<()> {
+        LinkStore::create_symlink(source, target)
+    }
+
+    /// Creates the directory `path` with permission bits `mode`.
+    fn create_dir(&self, path: &Path, mode: u32) -> Result<()> {
+        create_dir_with_mode(path, mode)
+    }
+
+    fn make_readonly(&self, path: &Path) -> Result<()> {
+        set_readonly(path, true)
+    }
+
+    fn hide(&self, path: &Path) -> Result<()> {
+        set_hidden(path)
+    }
+
+    fn chown(&self, path: &Path, owner: &User) -> Result<()> {
+        crate::users::chown(path, owner)
+    }
+}
+
+/// The store for `mode`, changing files under `sudo_roots` through sudo.
+pub fn create_store(
+    mode: crate::config::Mode,
+    hash: HashAlgorithm,
+    sudo_roots: Vec<PathBuf>,
+) -> Box<dyn Store> {
+    let inner: Box<dyn Store> = match mode {
+        crate::config::Mode::File => Box::new(FileStore::new(hash)),
+        crate::config::Mode::Link => Box::new(LinkStore::new(hash)),
+    };
+    Box::new(RouterStore::new(inner, sudo_roots))
 }
 
-pub fn create_store(mode: crate::config::Mode) -> Box<dyn Store> {
-    match mode {
-        crate::config::Mode::File => Box::new(FileStore),
-        crate::config::Mode::Link => Box::new(LinkStore),
+/// Removes (or restores) the owner's write permission on `path`.
+pub fn set_readonly(path: &Path, readonly: bool) -> Result<()> {
+    let mut permissions = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read metadata: {}", path.display()))?
+        .permissions();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = permissions.mode();
+        permissions.set_mode(if readonly {
+            mode & !0o222
+        } else {
+            mode | 0o200
+        });
+    }
+
+    #[cfg(not(unix))]
+    permissions.set_readonly(readonly);
+
+    std::fs::set_permissions(path, permissions)
+        .with_context(|| format!("Failed to change permissions: {}", path.display()))
+}
+
+/// Creates `path` (and missing parents) with permission bits `mode` on the
+/// directory itself. Elsewhere than Unix, `mode` is ignored.
+pub fn create_dir_with_mode(path: &Path, mode: u32) -> Result<()> {
+    std::fs::create_dir_all(path)
+        .with_context(|| format!("Failed to create directory: {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to change permissions: {}", path.display()))?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
+
+/// Permission bits of the directory at `path`, if it exists (Unix only).
+pub fn dir_mode(path: &Path) -> Option<u32> {
+    let metadata = std::fs::metadata(path).ok().filter(|m| m.is_dir())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o7777)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Sets the Windows hidden attribute on `path`. Elsewhere the leading dot
+/// already hides it, so this does nothing.
+pub fn set_hidden(path: &Path) -> Result<()> {
+    #[cfg(windows)]
+    {
+        let status = crate::process::command("attrib")
+            .arg("+h")
+            .arg(path)
+            .status()
+            .with_context(|| format!("Failed to run attrib: {}", path.display()))?;
+        anyhow::ensure!(status.success(), "Failed to hide file: {}", path.display());
+    }
+
+    #[cfg(not(windows))]
+    let _ = path;
+
+    Ok(())
+}
+
+/// Makes a file left read-only by a `readonly` group writable again, so it
+/// can be overwritten. Symlinks are left alone.
+fn ensure_writable(path: &Path) -> Result<()> {
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.is_file() && m.permissions().readonly()) {
+        set_readonly(path, false)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_bytes_known_digests() {
+        assert_eq!(
+            hash_bytes(HashAlgorithm::Sha256, b"doot"),
+            "690e8ea126c616b60d6572705492457fcd9c1105e597afba9a5153c6f44f2d62"
+        );
+        assert_eq!(
+            hash_bytes(HashAlgorithm::Blake3, b""),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[test]
+    fn compare_files_by_content() {
+        let dir = crate::testing::TempDir::new("compare");
+        let a = dir.join("a");
+        let b = dir.join("b");
+
+        let content = vec![7u8; 200 * 1024];
+        std::fs::write(&a, &content).unwrap();
+        std::fs::write(&b, &content).unwrap();
+        assert!(compare_files(&a, &b).unwrap());
+
+        let mut changed = content.clone();
+        *changed.last_mut().unwrap() = 8;
+        std::fs::write(&b, &changed).unwrap();
+        assert!(!compare_files(&a, &b).unwrap());
+
+        std::fs::write(&b, &content[1..]).unwrap();
+        assert!(!compare_files(&a, &b).unwrap());
     }
 }
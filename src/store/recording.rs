@@ -0,0 +1,231 @@
+use super::Store;
+use crate::config::HashAlgorithm;
+use crate::error::DootError;
+use crate::users::User;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A change to the file system that a `RecordingStore` recorded instead of
+/// making.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    Write { path: PathBuf, content: Vec<u8> },
+    Remove { path: PathBuf },
+    Link { source: PathBuf, target: PathBuf },
+    CreateDir { path: PathBuf, mode: u32 },
+    MakeReadonly { path: PathBuf },
+    Hide { path: PathBuf },
+    Chown { path: PathBuf, owner: User },
+}
+
+impl std::fmt::Display for Mutation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mutation::Write { path, content } => {
+                write!(f, "write {} ({} bytes)", path.display(), content.len())
+            }
+            Mutation::Remove { path } => write!(f, "remove {}", path.display()),
+            Mutation::Link { source, target } => write!(
+                f,
+                "link {} {} {}",
+                target.display(),
+                crate::style::arrow(),
+                source.display()
+            ),
+            Mutation::CreateDir { path, mode } => {
+                write!(f, "create directory {} (mode {:o})", path.display(), mode)
+            }
+            Mutation::MakeReadonly { path } => write!(f, "make {} read-only", path.display()),
+            Mutation::Hide { path } => write!(f, "hide {}", path.display()),
+            Mutation::Chown { path, owner } => {
+                write!(f, "change owner of {} to {}", path.display(), owner.name)
+            }
+        }
+    }
+}
+
+/// Wraps a store and records every change instead of making it, for
+/// `--dry-run` and for tests. Reads see the recorded changes, so a simulated
+/// run behaves like a real one.
+pub struct RecordingStore<'a> {
+    inner: &'a dyn Store,
+    mutations: Mutex<Vec<Mutation>>,
+}
+
+/// What the recorded changes left at a path.
+enum Recorded {
+    Content(Vec<u8>),
+    Link(PathBuf),
+    Removed,
+}
+
+impl<'a> RecordingStore<'a> {
+    pub fn new(inner: &'a dyn Store) -> Self {
+        Self {
+            inner,
+            mutations: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Changes recorded so far, in order.
+    pub fn mutations(&self) -> Vec<Mutation> {
+        self.mutations.lock().unwrap().clone()
+    }
+
+    /// Makes the recorded changes to `store`, in order.
+    #[allow(dead_code)]
+    pub fn replay(&self, store: &dyn Store) -> Result<()> {
+        for mutation in self.mutations() {
+            match mutation {
+                Mutation::Write { path, content } => store.write(&path, &content)?,
+                Mutation::Remove { path } => store.remove(&path)?,
+                Mutation::Link { source, target } => store.link(&source, &target)?,
+                Mutation::CreateDir { path, mode } => store.create_dir(&path, mode)?,
+                Mutation::MakeReadonly { path } => store.make_readonly(&path)?,
+                Mutation::Hide { path } => store.hide(&path)?,
+                Mutation::Chown { path, owner } => store.chown(&path, &owner)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&self, mutation: Mutation) -> Result<()> {
+        self.mutations.lock().unwrap().push(mutation);
+        Ok(())
+    }
+
+    fn recorded(&self, path: &Path) -> Option<Recorded> {
+        self.mutations
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|mutation| match mutation {
+                Mutation::Write { path: p, content } if p == path => {
+                    Some(Recorded::Content(content.clone()))
+                }
+                Mutation::Link { source, target } if target == path => {
+                    Some(Recorded::Link(source.clone()))
+                }
+                Mutation::Remove { path: p } if p == path => Some(Recorded::Removed),
+                _ => None,
+            })
+    }
+}
+
+impl Store for RecordingStore<'_> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.inner.hash_algorithm()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.recorded(path) {
+            Some(Recorded::Content(content)) => Ok(content),
+            Some(Recorded::Link(source)) => self.read(&source),
+            Some(Recorded::Removed) => {
+                Err(DootError::io("read", path, std::io::ErrorKind::NotFound.into()).into())
+            }
+            None => self.inner.read(path),
+        }
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.record(Mutation::Write {
+            path: path.to_path_buf(),
+            content: content.to_vec(),
+        })
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        match self.recorded(path) {
+            Some(Recorded::Content(_) | Recorded::Link(_)) => true,
+            Some(Recorded::Removed) => false,
+            None => self.inner.exists(path),
+        }
+    }
+
+    fn is_privileged(&self, path: &Path) -> bool {
+        self.inner.is_privileged(path)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.record(Mutation::Remove {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn link(&self, source: &Path, target: &Path) -> Result<()> {
+        self.record(Mutation::Link {
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+        })
+    }
+
+    fn create_dir(&self, path: &Path, mode: u32) -> Result<()> {
+        self.record(Mutation::CreateDir {
+            path: path.to_path_buf(),
+            mode,
+        })
+    }
+
+    fn make_readonly(&self, path: &Path) -> Result<()> {
+        self.record(Mutation::MakeReadonly {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn hide(&self, path: &Path) -> Result<()> {
+        self.record(Mutation::Hide {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn chown(&self, path: &Path, owner: &User) -> Result<()> {
+        self.record(Mutation::Chown {
+            path: path.to_path_buf(),
+            owner: owner.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::FileStore;
+
+    #[test]
+    fn records_without_writing_and_replays() {
+        let dir = crate::testing::TempDir::new("recording");
+        let old = dir.join("old");
+        let new = dir.join("new");
+        std::fs::write(&old, "old").unwrap();
+
+        let files = FileStore::new(HashAlgorithm::default());
+        let store = RecordingStore::new(&files);
+        store.write(&new, b"new").unwrap();
+        store.remove(&old).unwrap();
+
+        assert!(old.exists() && !new.exists());
+        assert_eq!(store.read(&new).unwrap(), b"new");
+        assert!(!store.exists(&old));
+        assert_eq!(
+            store.mutations(),
+            [
+                Mutation::Write {
+                    path: new.clone(),
+                    content: b"new".to_vec()
+                },
+                Mutation::Remove { path: old.clone() },
+            ]
+        );
+
+        store.replay(&files).unwrap();
+        assert!(!old.exists());
+        assert_eq!(std::fs::read(&new).unwrap(), b"new");
+    }
+}
@@ -0,0 +1,112 @@
+use super::container::{self, ContainerStore};
+use super::crontab::{self, CrontabStore};
+use super::sudo::SudoStore;
+use super::{is_pseudo_path, Metadata, Store};
+use crate::config::HashAlgorithm;
+use crate::users::User;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Dispatches pseudo paths (such as `crontab:` and `docker://`) to their
+/// dedicated store, files of `sudo` groups to sudo, and everything else to
+/// the store selected by the configured mode.
+pub struct RouterStore {
+    inner: Box<dyn Store>,
+    crontab: CrontabStore,
+    container: ContainerStore,
+    sudo: SudoStore,
+}
+
+impl RouterStore {
+    /// Routes to `inner`, except files under `sudo_roots`, which go through
+    /// sudo.
+    pub fn new(inner: Box<dyn Store>, sudo_roots: Vec<PathBuf>) -> Self {
+        Self {
+            inner,
+            crontab: CrontabStore,
+            container: ContainerStore,
+            sudo: SudoStore::new(sudo_roots),
+        }
+    }
+
+    fn route(&self, path: &Path) -> &dyn Store {
+        if crontab::is_crontab_path(path) {
+            &self.crontab
+        } else if container::is_container_path(path) {
+            &self.container
+        } else if self.sudo.is_privileged(path) {
+            &self.sudo
+        } else {
+            &*self.inner
+        }
+    }
+}
+
+impl Store for RouterStore {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.inner.hash_algorithm()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.route(path).read(path)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.route(path).write(path, content)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.route(path).exists(path)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.route(path).remove(path)
+    }
+
+    fn hash(&self, path: &Path) -> Result<String> {
+        self.route(path).hash(path)
+    }
+
+    fn metadata(&self, path: &Path) -> Option<Metadata> {
+        self.route(path).metadata(path)
+    }
+
+    fn link(&self, source: &Path, target: &Path) -> Result<()> {
+        self.route(target).link(source, target)
+    }
+
+    fn create_dir(&self, path: &Path, mode: u32) -> Result<()> {
+        self.route(path).create_dir(path, mode)
+    }
+
+    fn make_readonly(&self, path: &Path) -> Result<()> {
+        self.route(path).make_readonly(path)
+    }
+
+    fn chown(&self, path: &Path, owner: &User) -> Result<()> {
+        self.route(path).chown(path, owner)
+    }
+
+    fn is_privileged(&self, path: &Path) -> bool {
+        self.sudo.is_privileged(path)
+    }
+
+    fn compare(&self, a: &Path, b: &Path) -> Result<bool> {
+        if !is_pseudo_path(a)
+            && !is_pseudo_path(b)
+            && !self.sudo.is_privileged(a)
+            && !self.sudo.is_privileged(b)
+        {
+            return self.inner.compare(a, b);
+        }
+
+        if !self.exists(a) || !self.exists(b) {
+            return Ok(false);
+        }
+        Ok(self.hash(a)? == self.hash(b)?)
+    }
+}
@@ -0,0 +1,163 @@
+use super::Store;
+use crate::process;
+use crate::users::User;
+use anyhow::Result;
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Changes files through `sudo`, one command per change, so only the files
+/// of `sudo` groups are touched with root rights. Files it can read itself are
+/// read directly.
+pub struct SudoStore {
+    /// Directories of `sudo` groups, whose files are changed through sudo
+    roots: Vec<PathBuf>,
+}
+
+impl SudoStore {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self { roots }
+    }
+
+    /// Whether changes to `path` go through sudo. Never when doot already
+    /// runs as root.
+    pub fn is_privileged(&self, path: &Path) -> bool {
+        #[cfg(unix)]
+        if unsafe { libc::geteuid() } == 0 {
+            return false;
+        }
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
+/// Runs `sudo -- args...` with `input` on stdin and returns its stdout.
+fn sudo(args: &[&OsStr], input: &[u8]) -> Result<Vec<u8>> {
+    let mut command = process::command("sudo");
+    command.arg("--").args(args);
+    let name = format!("sudo {}", args[0].to_string_lossy());
+    process::pipe(command, &name, input)
+}
+
+impl Store for SudoStore {
+    fn name(&self) -> &'static str {
+        "sudo"
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match std::fs::read(path) {
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                sudo(&["cat".as_ref(), "--".as_ref(), path.as_ref()], b"")
+            }
+            result => Ok(result.map_err(|error| crate::error::DootError::io("read", path, error))?),
+        }
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !self.exists(parent)) {
+            sudo(
+                &[
+                    "mkdir".as_ref(),
+                    "-p".as_ref(),
+                    "--".as_ref(),
+                    parent.as_ref(),
+                ],
+                b"",
+            )?;
+        }
+        // `tee` would echo the content back; the shell only writes the file.
+        let args: [&OsStr; 5] = [
+            "sh".as_ref(),
+            "-c".as_ref(),
+            "cat > \"$1\"".as_ref(),
+            "sh".as_ref(),
+            path.as_ref(),
+        ];
+        sudo(&args, content)?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        match std::fs::symlink_metadata(path) {
+            Ok(_) => true,
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                sudo(&["test".as_ref(), "-e".as_ref(), path.as_ref()], b"").is_ok()
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        sudo(
+            &["rm".as_ref(), "-f".as_ref(), "--".as_ref(), path.as_ref()],
+            b"",
+        )?;
+        Ok(())
+    }
+
+    fn link(&self, source: &Path, target: &Path) -> Result<()> {
+        let args: [&OsStr; 4] = [
+            "ln".as_ref(),
+            "-sfn".as_ref(),
+            source.as_ref(),
+            target.as_ref(),
+        ];
+        sudo(&args, b"")?;
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path, mode: u32) -> Result<()> {
+        let mode = format!("{:o}", mode);
+        let args: [&OsStr; 6] = [
+            "mkdir".as_ref(),
+            "-p".as_ref(),
+            "-m".as_ref(),
+            mode.as_ref(),
+            "--".as_ref(),
+            path.as_ref(),
+        ];
+        sudo(&args, b"")?;
+        Ok(())
+    }
+
+    fn make_readonly(&self, path: &Path) -> Result<()> {
+        sudo(
+            &[
+                "chmod".as_ref(),
+                "a-w".as_ref(),
+                "--".as_ref(),
+                path.as_ref(),
+            ],
+            b"",
+        )?;
+        Ok(())
+    }
+
+    fn chown(&self, path: &Path, owner: &User) -> Result<()> {
+        let ids = format!("{}:{}", owner.uid, owner.gid);
+        let args: [&OsStr; 5] = [
+            "chown".as_ref(),
+            "-h".as_ref(),
+            ids.as_ref(),
+            "--".as_ref(),
+            path.as_ref(),
+        ];
+        sudo(&args, b"")?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_below_roots_are_privileged() {
+        let store = SudoStore::new(vec![PathBuf::from("/etc/nginx")]);
+        let root = unsafe { libc::geteuid() } == 0;
+        assert_eq!(
+            store.is_privileged(Path::new("/etc/nginx/nginx.conf")),
+            !root
+        );
+        assert!(!store.is_privileged(Path::new("/etc/nginx.conf")));
+    }
+}
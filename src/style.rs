@@ -0,0 +1,209 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ASCII: AtomicBool = AtomicBool::new(false);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Output formats of commands that report results (`--format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// A unified diff, for `doot diff`
+    Patch,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "patch" => Ok(OutputFormat::Patch),
+            _ => Err(format!(
+                "unknown output format '{}', expected 'text', 'json' or 'patch'",
+                s
+            )),
+        }
+    }
+}
+
+/// Switches import, export and status to printing a single JSON document
+/// (`--format json`), for scripts and dashboards.
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Switches output to plain words and ASCII (`--ascii`), for screen readers
+/// and dumb terminals.
+pub fn set_ascii(ascii: bool) {
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+pub fn is_ascii() -> bool {
+    ASCII.load(Ordering::Relaxed)
+}
+
+/// Markers shown in brackets before files, groups and units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icon {
+    InSync,
+    New,
+    Modified,
+    Removed,
+    Warning,
+    Uncommitted,
+}
+
+impl Icon {
+    pub fn glyph(self) -> &'static str {
+        match (self, is_ascii()) {
+            (Icon::InSync, false) => "✓",
+            (Icon::InSync, true) => "ok",
+            (Icon::New, false) => "+",
+            (Icon::New, true) => "new",
+            (Icon::Modified, false) => "~",
+            (Icon::Modified, true) => "changed",
+            (Icon::Removed, false) => "-",
+            (Icon::Removed, true) => "removed",
+            (Icon::Warning, false) => "!",
+            (Icon::Warning, true) => "warning",
+            (Icon::Uncommitted, false) => "*",
+            (Icon::Uncommitted, true) => "uncommitted",
+        }
+    }
+}
+
+/// Prefix of an item in a tree, depending on whether it is the last child.
+pub fn branch(last: bool) -> &'static str {
+    match (is_ascii(), last) {
+        (true, _) => "  ",
+        (false, true) => "└── ",
+        (false, false) => "├── ",
+    }
+}
+
+/// Prefix continuing the tree below an item, for that item's children.
+pub fn trunk(last: bool) -> &'static str {
+    match (is_ascii(), last) {
+        (true, _) => "  ",
+        (false, true) => "    ",
+        (false, false) => "│   ",
+    }
+}
+
+/// A horizontal separator line.
+pub fn rule(width: usize) -> String {
+    if is_ascii() { "-" } else { "─" }.repeat(width)
+}
+
+/// Arrow between a name and what it maps to.
+pub fn arrow() -> &'static str {
+    if is_ascii() {
+        "->"
+    } else {
+        "→"
+    }
+}
+
+/// Colors a terminal can show, detected from `COLORTERM` and `TERM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorDepth {
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        Self::from_env(&colorterm, &term)
+    }
+
+    fn from_env(colorterm: &str, term: &str) -> Self {
+        if matches!(colorterm, "truecolor" | "24bit") || term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// Escape setting the foreground to the closest color to `r`, `g`, `b`.
+    pub fn fg(self, r: u8, g: u8, b: u8) -> String {
+        match self {
+            ColorDepth::TrueColor => format!("\x1b[38;2;{};{};{}m", r, g, b),
+            ColorDepth::Ansi256 => format!("\x1b[38;5;{}m", ansi256(r, g, b)),
+            ColorDepth::Ansi16 => format!("\x1b[{}m", ansi16(r, g, b)),
+        }
+    }
+
+    /// Escape setting the background, or nothing where a background would be
+    /// too coarse to be readable (16 colors).
+    pub fn bg(self, r: u8, g: u8, b: u8) -> String {
+        match self {
+            ColorDepth::TrueColor => format!("\x1b[48;2;{};{};{}m", r, g, b),
+            ColorDepth::Ansi256 => format!("\x1b[48;5;{}m", ansi256(r, g, b)),
+            ColorDepth::Ansi16 => String::new(),
+        }
+    }
+}
+
+/// Index of the closest color in the 6x6x6 cube or grayscale ramp of the
+/// 256-color palette.
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            248..=255 => 231,
+            _ => 232 + (r - 8) / 10,
+        };
+    }
+    let level = |c: u8| if c < 48 { 0 } else { (c - 35) / 40 };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// SGR code of the closest of the 16 basic colors.
+fn ansi16(r: u8, g: u8, b: u8) -> u8 {
+    let bit = |c: u8| u8::from(c >= 128);
+    let code = bit(r) | (bit(g) << 1) | (bit(b) << 2);
+    let bright = r.max(g).max(b) >= 200;
+    if bright {
+        90 + code
+    } else {
+        30 + code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_color_depth() {
+        assert_eq!(
+            ColorDepth::from_env("truecolor", "xterm"),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env("", "xterm-256color"),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(ColorDepth::from_env("", "linux"), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn maps_rgb_to_palettes() {
+        assert_eq!(ansi256(255, 0, 0), 196);
+        assert_eq!(ansi256(40, 40, 40), 235);
+        assert_eq!(ansi16(191, 97, 106), 31);
+        assert_eq!(ansi16(0, 0, 0), 30);
+    }
+}
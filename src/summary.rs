@@ -0,0 +1,114 @@
+use crate::plan::{FileStatus, Plan};
+use std::path::Path;
+
+/// Well-known files whose consumers need to be told to reload them, and the
+/// command that does so. `{}` is replaced with the destination path.
+const RELOAD_HINTS: &[(&str, &str)] = &[
+    (".tmux.conf", "tmux source-file {}"),
+    ("tmux.conf", "tmux source-file {}"),
+    ("sshd_config", "sudo systemctl reload sshd"),
+    (".Xresources", "xrdb -merge {}"),
+    (".bashrc", "source {}"),
+    (".zshrc", "source {}"),
+];
+
+/// Builds next-step suggestions for a plan that has just been applied.
+pub fn suggestions(plan: &Plan, imported: bool) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    let changed = plan
+        .groups
+        .iter()
+        .flat_map(|g| &g.entries)
+        .filter(|e| e.status != FileStatus::Same);
+
+    if imported {
+        if plan.has_changes() && Path::new(".git").exists() {
+            suggestions.push("Commit the imported changes: git add -A && git commit".to_string());
+        }
+        return suggestions;
+    }
+
+    let mut reload_units = false;
+    for entry in changed {
+        if crate::systemd::unit_name(&entry.destination).is_some() {
+            reload_units = true;
+            continue;
+        }
+
+        // Reload commands would run on the host, not in the container.
+        if crate::store::container::is_container_path(&entry.destination) {
+            continue;
+        }
+        let Some(file_name) = entry.destination.file_name() else {
+            continue;
+        };
+        for (name, command) in RELOAD_HINTS {
+            if file_name == *name {
+                let command = command.replace("{}", &entry.destination.display().to_string());
+                suggestions.push(format!("Reload {name}: {command}"));
+            }
+        }
+    }
+
+    if reload_units {
+        suggestions.push("Reload systemd user units: systemctl --user daemon-reload".to_string());
+    }
+
+    suggestions
+}
+
+pub fn print(plan: &Plan, imported: bool) {
+    let suggestions = suggestions(plan, imported);
+    if suggestions.is_empty() || crate::style::is_json() {
+        return;
+    }
+
+    println!("\nNext steps:");
+    for suggestion in suggestions {
+        println!("  - {}", suggestion);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::entry;
+
+    fn plan_with(destination: &str, status: FileStatus) -> Plan {
+        let mut plan = Plan::new();
+        plan.add_group(
+            "group".to_string(),
+            vec![entry(destination, "/repo/group/file", destination, status)],
+        );
+        plan
+    }
+
+    #[test]
+    fn suggests_reload_for_changed_well_known_files() {
+        let plan = plan_with("/home/me/.tmux.conf", FileStatus::Overwrite);
+        let suggestions = suggestions(&plan, false);
+        assert_eq!(
+            suggestions,
+            vec!["Reload .tmux.conf: tmux source-file /home/me/.tmux.conf"]
+        );
+    }
+
+    #[test]
+    fn no_reload_for_unchanged_files() {
+        let plan = plan_with("/home/me/.tmux.conf", FileStatus::Same);
+        assert!(suggestions(&plan, false).is_empty());
+    }
+
+    #[test]
+    fn suggests_daemon_reload_for_units() {
+        let plan = plan_with(
+            "/home/me/.config/systemd/user/foo.service",
+            FileStatus::Create,
+        );
+        let suggestions = suggestions(&plan, false);
+        assert_eq!(
+            suggestions,
+            vec!["Reload systemd user units: systemctl --user daemon-reload"]
+        );
+    }
+}
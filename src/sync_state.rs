@@ -0,0 +1,312 @@
+use crate::plan::{FileEntry, FileStatus, Plan};
+use crate::store::Store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Directory in the repo with what this machine last synced of each group.
+/// It describes this machine's files, so git is told to ignore it.
+pub const DIR: &str = ".doot/state";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Import,
+    Export,
+}
+
+/// A file as the last run that synced it left it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncedFile {
+    /// The machine's copy of the file
+    pub path: PathBuf,
+    /// Hash of the machine's copy
+    pub hash: String,
+    /// Hash of the repo file, if it differs from the machine's copy
+    /// (templates, encrypted and merged files)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_hash: Option<String>,
+    /// Modification time of the machine's copy, in nanoseconds since the
+    /// epoch
+    pub mtime: u64,
+    pub direction: Direction,
+}
+
+impl SyncedFile {
+    fn new(store: &dyn Store, machine: &Path, repo: &Path, direction: Direction) -> Option<Self> {
+        let hash = store.hash(machine).ok()?;
+        let repo_hash = store.hash(repo).ok()?;
+        Some(Self {
+            path: machine.to_path_buf(),
+            repo_hash: (repo_hash != hash).then_some(repo_hash),
+            hash,
+            mtime: mtime(machine).unwrap_or_default(),
+            direction,
+        })
+    }
+
+    /// Whether the machine's copy changed since. An unchanged modification
+    /// time spares hashing it.
+    fn machine_changed(&self, store: &dyn Store) -> bool {
+        mtime(&self.path) != Some(self.mtime)
+            && store.hash(&self.path).ok().as_ref() != Some(&self.hash)
+    }
+
+    fn repo_changed(&self, store: &dyn Store, repo: &Path) -> bool {
+        let hash = self.repo_hash.as_ref().unwrap_or(&self.hash);
+        store.hash(repo).ok().as_ref() != Some(hash)
+    }
+}
+
+fn mtime(path: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let nanos = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    u64::try_from(nanos).ok()
+}
+
+/// What this machine last synced of a group, by path relative to the
+/// group's resolved path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GroupState {
+    pub files: BTreeMap<PathBuf, SyncedFile>,
+}
+
+fn path(repo: &Path, group: &str) -> PathBuf {
+    repo.join(DIR).join(format!("{}.json", group))
+}
+
+impl GroupState {
+    /// Loads the state of `group`, or an empty one if it was never synced.
+    pub fn load(repo: &Path, group: &str) -> Result<Self> {
+        let path = path(repo, group);
+        match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .with_context(|| format!("Failed to parse: {}", path.display())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Replaces the state of `group` at once, so an interrupted run never
+    /// leaves it half-written.
+    fn save(&self, repo: &Path, group: &str) -> Result<()> {
+        let path = path(repo, group);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            let ignore = parent.join(".gitignore");
+            if !ignore.exists() {
+                std::fs::write(&ignore, "*\n")
+                    .with_context(|| format!("Failed to write: {}", ignore.display()))?;
+            }
+        }
+        let staged = path.with_extension("json.tmp");
+        std::fs::write(&staged, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write: {}", staged.display()))?;
+        std::fs::rename(&staged, &path)
+            .with_context(|| format!("Failed to write: {}", path.display()))
+    }
+}
+
+/// The machine's copy and the repo file of `entry`, in that order.
+fn sides(entry: &crate::plan::FileEntry, import: bool) -> (&Path, &Path) {
+    match import {
+        true => (&entry.source, &entry.destination),
+        false => (&entry.destination, &entry.source),
+    }
+}
+
+/// Turns the overwrites of `plan` whose machine copy and repo file both
+/// changed since the last sync into conflicts. Files of `sensitive` groups
+/// have no recorded hashes and never conflict.
+pub fn mark_conflicts(repo: &Path, plan: &mut Plan, store: &dyn Store, import: bool) -> Result<()> {
+    for group in plan.groups.iter_mut().filter(|g| g.skipped.is_none()) {
+        let state = GroupState::load(repo, &group.group_name)?;
+        for entry in &mut group.entries {
+            if entry.status != FileStatus::Overwrite {
+                continue;
+            }
+            let (machine, repo_file) = sides(entry, import);
+            let Some(synced) = state
+                .files
+                .get(&entry.relative_path)
+                .filter(|synced| synced.path == machine)
+            else {
+                continue;
+            };
+            if synced.machine_changed(store) && synced.repo_changed(store, repo_file) {
+                entry.status = FileStatus::Conflict;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Records the files of the applied `plan` as synced in each group's state.
+pub fn record(repo: &Path, plan: &Plan, store: &dyn Store, import: bool) -> Result<()> {
+    let direction = match import {
+        true => Direction::Import,
+        false => Direction::Export,
+    };
+    for group in plan.groups.iter().filter(|g| g.skipped.is_none()) {
+        let mut state = GroupState::load(repo, &group.group_name)?;
+        for entry in &group.entries {
+            let (machine, repo_file) = sides(entry, import);
+            let synced = match entry.status {
+                FileStatus::Remove => None,
+                _ if group.sensitive => None,
+                _ => SyncedFile::new(store, machine, repo_file, direction),
+            };
+            match synced {
+                Some(synced) => state.files.insert(entry.relative_path.clone(), synced),
+                None => state.files.remove(&entry.relative_path),
+            };
+        }
+        state.save(repo, &group.group_name)?;
+    }
+    Ok(())
+}
+
+/// Entries removing the files earlier exports of `plan`'s groups wrote under
+/// their resolved path, given by `root`, that the groups no longer have.
+/// Files that were removed by hand are left out, and so are the files of
+/// `sensitive` groups, which aren't recorded.
+pub fn orphans(
+    repo: &Path,
+    plan: &Plan,
+    root: impl Fn(&str) -> Option<PathBuf>,
+) -> Result<Vec<(String, FileEntry)>> {
+    let mut orphans = Vec::new();
+    for group in plan.groups.iter().filter(|g| g.skipped.is_none()) {
+        let Some(root) = root(group.config_name()) else {
+            continue;
+        };
+        let state = GroupState::load(repo, &group.group_name)?;
+        for (relative, synced) in &state.files {
+            let current = group.entries.iter().any(|e| e.destination == synced.path);
+            if synced.direction != Direction::Export
+                || !synced.path.starts_with(&root)
+                || current
+                || synced.path.symlink_metadata().is_err()
+            {
+                continue;
+            }
+            let entry = FileEntry::new(
+                relative.clone(),
+                synced.path.clone(),
+                synced.path.clone(),
+                FileStatus::Remove,
+            );
+            orphans.push((group.group_name.clone(), entry));
+        }
+    }
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HashAlgorithm;
+    use crate::store::FileStore;
+    use crate::testing::entry;
+
+    #[test]
+    fn files_changed_on_both_sides_conflict() {
+        let dir = crate::testing::TempDir::new("sync-state");
+        let store = FileStore::new(HashAlgorithm::default());
+        let (source, destination) = (dir.join("sh/.rc"), dir.join("home-rc"));
+        std::fs::create_dir_all(dir.join("sh")).unwrap();
+        std::fs::write(&source, b"a").unwrap();
+        std::fs::write(&destination, b"a").unwrap();
+        let plan = || {
+            let mut plan = Plan::new();
+            plan.add_group(
+                "sh".to_string(),
+                vec![entry(".rc", &source, &destination, FileStatus::Overwrite)],
+            );
+            plan
+        };
+        let status = || {
+            let mut plan = plan();
+            mark_conflicts(&dir, &mut plan, &store, false).unwrap();
+            plan.groups[0].entries[0].status.clone()
+        };
+
+        // Never synced.
+        std::fs::write(&source, b"b").unwrap();
+        std::fs::write(&destination, b"c").unwrap();
+        assert_eq!(status(), FileStatus::Overwrite);
+
+        std::fs::write(&source, b"a").unwrap();
+        std::fs::write(&destination, b"a").unwrap();
+        record(&dir, &plan(), &store, false).unwrap();
+        let state = GroupState::load(&dir, "sh").unwrap();
+        assert_eq!(state.files[Path::new(".rc")].direction, Direction::Export);
+
+        // Only the repo file changed: a plain overwrite.
+        std::fs::write(&source, b"b").unwrap();
+        assert_eq!(status(), FileStatus::Overwrite);
+
+        // Both changed.
+        std::fs::write(&destination, b"c").unwrap();
+        assert_eq!(status(), FileStatus::Conflict);
+    }
+
+    #[test]
+    fn exported_files_gone_from_a_group_are_orphans_until_pruned() {
+        let dir = crate::testing::TempDir::new("sync-state-orphans");
+        std::fs::create_dir_all(dir.join("sh")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        let store = FileStore::new(HashAlgorithm::default());
+        for name in [".shrc", ".oldrc"] {
+            std::fs::write(dir.join("sh").join(name), b"").unwrap();
+            std::fs::write(dir.join("home").join(name), b"").unwrap();
+        }
+        let exported = |names: &[&str], status: FileStatus| {
+            let mut plan = Plan::new();
+            let entries = names
+                .iter()
+                .map(|name| {
+                    entry(
+                        name,
+                        dir.join("sh").join(name),
+                        dir.join("home").join(name),
+                        status.clone(),
+                    )
+                })
+                .collect();
+            plan.add_group("sh".to_string(), entries);
+            plan
+        };
+        let home = dir.join("home");
+        let root = |_: &str| Some(home.clone());
+
+        record(
+            &dir,
+            &exported(&[".shrc", ".oldrc"], FileStatus::Same),
+            &store,
+            false,
+        )
+        .unwrap();
+        let plan = exported(&[".shrc"], FileStatus::Same);
+        let orphans = orphans(&dir, &plan, root).unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].1.destination, dir.join("home/.oldrc"));
+        assert_eq!(orphans[0].1.relative_path, PathBuf::from(".oldrc"));
+        // Files outside the resolved path belong to another resolver.
+        assert!(super::orphans(&dir, &plan, |_| Some(dir.join("elsewhere")))
+            .unwrap()
+            .is_empty());
+
+        // Not pruned: still an orphan. Pruned: forgotten.
+        record(&dir, &plan, &store, false).unwrap();
+        assert_eq!(super::orphans(&dir, &plan, root).unwrap().len(), 1);
+        let mut pruned = Plan::new();
+        pruned.add_group("sh".to_string(), vec![orphans[0].1.clone()]);
+        record(&dir, &pruned, &store, false).unwrap();
+        assert!(super::orphans(&dir, &plan, root).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use std::path::Path;
+
+const UNIT_EXTENSIONS: &[&str] = &[
+    "service",
+    "socket",
+    "timer",
+    "path",
+    "target",
+    "mount",
+    "automount",
+];
+
+const ENABLED_STATES: &[&str] = &[
+    "enabled",
+    "enabled-runtime",
+    "static",
+    "indirect",
+    "generated",
+    "alias",
+];
+
+/// Enablement states of units that are started by other units, so being
+/// inactive is normal for them.
+const STARTED_BY_OTHERS: &[&str] = &["static", "indirect"];
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct UnitStatus {
+    pub name: String,
+    pub enabled: String,
+    pub active: String,
+    /// Timers, sockets or paths that start the unit
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub triggered_by: Vec<String>,
+}
+
+impl UnitStatus {
+    /// Whether the unit is enabled and, unless it only runs when something
+    /// else starts it, active.
+    pub fn is_healthy(&self) -> bool {
+        if !ENABLED_STATES.contains(&self.enabled.as_str()) {
+            return false;
+        }
+        STARTED_BY_OTHERS.contains(&self.enabled.as_str())
+            || !self.triggered_by.is_empty()
+            || self.active == "active"
+    }
+}
+
+/// Returns the unit name if `destination` is a systemd user unit file, i.e. it
+/// lives directly in a `systemd/user` directory and has a unit extension.
+pub fn unit_name(destination: &Path) -> Option<String> {
+    let parent = destination.parent()?;
+    if !parent.ends_with("systemd/user") {
+        return None;
+    }
+
+    let extension = destination.extension()?.to_str()?;
+    if !UNIT_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+
+    destination
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Queries `systemctl --user` for the enablement and activity of `units`, in
+/// one call.
+///
+/// Returns nothing if `systemctl` is not available on this machine.
+pub fn query(units: &[String]) -> Vec<UnitStatus> {
+    if units.is_empty() {
+        return Vec::new();
+    }
+    let Ok(output) = crate::process::command("systemctl")
+        .args([
+            "--user",
+            "show",
+            "-p",
+            "UnitFileState,ActiveState,TriggeredBy",
+        ])
+        .args(units)
+        .output()
+    else {
+        return Vec::new();
+    };
+    parse_show(units, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses `systemctl show` output, which has a block of `Key=value` lines per
+/// unit, separated by blank lines, in the order the units were given.
+fn parse_show(units: &[String], output: &str) -> Vec<UnitStatus> {
+    let blocks = output.split("\n\n").map(|block| {
+        block
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .collect::<Vec<_>>()
+    });
+    units
+        .iter()
+        .zip(blocks.chain(std::iter::repeat(Vec::new())))
+        .map(|(unit, properties)| {
+            let property = |key: &str| {
+                properties
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, value)| value.trim())
+                    .filter(|value| !value.is_empty())
+                    .unwrap_or("unknown")
+                    .to_string()
+            };
+            UnitStatus {
+                name: unit.clone(),
+                enabled: property("UnitFileState"),
+                active: property("ActiveState"),
+                triggered_by: properties
+                    .iter()
+                    .find(|(k, _)| *k == "TriggeredBy")
+                    .map(|(_, value)| value.split_whitespace().map(String::from).collect())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_name_for_user_unit() {
+        let path = Path::new("/home/me/.config/systemd/user/syncthing.service");
+        assert_eq!(unit_name(path), Some("syncthing.service".to_string()));
+    }
+
+    #[test]
+    fn unit_name_ignores_drop_ins_and_other_files() {
+        let drop_in = Path::new("/home/me/.config/systemd/user/foo.service.d/override.conf");
+        assert_eq!(unit_name(drop_in), None);
+
+        let other = Path::new("/home/me/.config/foo/bar.service");
+        assert_eq!(unit_name(other), None);
+    }
+
+    #[test]
+    fn unit_health() {
+        let unit = UnitStatus {
+            name: "foo.service".to_string(),
+            enabled: "enabled".to_string(),
+            active: "active".to_string(),
+            triggered_by: Vec::new(),
+        };
+        assert!(unit.is_healthy());
+
+        let disabled = UnitStatus {
+            enabled: "disabled".to_string(),
+            ..unit.clone()
+        };
+        assert!(!disabled.is_healthy());
+
+        let stopped = UnitStatus {
+            active: "inactive".to_string(),
+            ..unit.clone()
+        };
+        assert!(!stopped.is_healthy());
+        assert!(UnitStatus {
+            enabled: "static".to_string(),
+            ..stopped.clone()
+        }
+        .is_healthy());
+        assert!(UnitStatus {
+            triggered_by: vec!["foo.timer".to_string()],
+            ..stopped
+        }
+        .is_healthy());
+    }
+
+    #[test]
+    fn show_output_is_split_per_unit() {
+        let units = ["a.service".to_string(), "b.service".to_string()];
+        let output = "ActiveState=inactive\nUnitFileState=static\nTriggeredBy=a.timer a.path\n\nActiveState=active\nUnitFileState=enabled\nTriggeredBy=\n";
+        let statuses = parse_show(&units, output);
+        assert_eq!(statuses[0].enabled, "static");
+        assert_eq!(statuses[0].triggered_by, ["a.timer", "a.path"]);
+        assert_eq!(statuses[1].active, "active");
+        assert!(statuses[1].triggered_by.is_empty());
+    }
+}
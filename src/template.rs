@@ -0,0 +1,95 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Extension of files rendered on export.
+pub const EXTENSION: &str = "tmpl";
+
+/// Variables of templates besides `resolver`: the built-in ones, then the
+/// config's `vars`, which may replace them.
+static VARIABLES: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// Defines the variables of templates, with `user` as the user files are
+/// exported for.
+pub fn configure(vars: &BTreeMap<String, String>, user: &str) {
+    let mut variables = VARIABLES.write().unwrap();
+    variables.clear();
+    variables.insert("hostname".to_string(), crate::machines::hostname());
+    variables.insert("os".to_string(), std::env::consts::OS.to_string());
+    variables.insert("user".to_string(), user.to_string());
+    variables.extend(vars.clone());
+}
+
+/// The path `relative` is rendered to, if it is a template.
+pub fn target(relative: &Path) -> Option<PathBuf> {
+    crate::naming::strip_extension(relative, EXTENSION)
+}
+
+/// The template `relative` would be rendered from.
+pub fn source(relative: &Path) -> PathBuf {
+    crate::naming::add_extension(relative, EXTENSION)
+}
+
+/// Renders `content` for `resolver`, replacing each `{{ name }}` with the
+/// value of the variable. Unknown variables are an error.
+pub fn render(content: &[u8], resolver: &str) -> Result<Vec<u8>> {
+    let variables = VARIABLES.read().unwrap();
+    let text = String::from_utf8_lossy(content);
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text.as_ref();
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + end].trim();
+        rendered.push_str(&rest[..start]);
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            // Not a variable, such as `{{ a + b }}`; keep it as written.
+            rendered.push_str(&rest[start..start + end + 2]);
+        } else if name == "resolver" {
+            rendered.push_str(resolver);
+        } else {
+            let value = variables
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown template variable '{}'", name))?;
+            rendered.push_str(value);
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_variables() {
+        let vars = BTreeMap::from([("email".to_string(), "me@example.com".to_string())]);
+        configure(&vars, "alice");
+
+        let rendered = render(
+            b"[user]\n  email = {{ email }}\n  name = {{user}} on {{ resolver }}\n{{ a + b }}\n",
+            "nux",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(rendered).unwrap(),
+            "[user]\n  email = me@example.com\n  name = alice on nux\n{{ a + b }}\n"
+        );
+        assert!(render(b"{{ missing }}", "nux").is_err());
+
+        assert_eq!(
+            target(Path::new(".gitconfig.tmpl")),
+            Some(PathBuf::from(".gitconfig"))
+        );
+        assert_eq!(target(Path::new(".gitconfig")), None);
+        assert_eq!(
+            source(Path::new(".config/app.toml")),
+            PathBuf::from(".config/app.toml.tmpl")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Helpers for tests that run plans and executors against an in-memory file
+//! system: a store, a fake resolver pointing groups at a fake home, and plan
+//! entries.
+//!
+//! Groups that walk their directory still read the real file system; list
+//! their `files` to keep them in memory.
+
+use crate::config::Config;
+use crate::plan::{FileEntry, FileStatus, Layer, Plan, PlanBuilder};
+use crate::store::Store;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A store keeping files in memory. Writes and removals apply to the map, so
+/// a run can be checked by reading files back.
+#[derive(Default)]
+pub struct MemoryStore {
+    files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(self, path: impl AsRef<Path>, content: &[u8]) -> Self {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.as_ref().to_path_buf(), content.to_vec());
+        self
+    }
+
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(path.as_ref()).cloned()
+    }
+}
+
+impl Store for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.file(path)
+            .ok_or_else(|| anyhow::anyhow!("File not found: {}", path.display()))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), content.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let files = self.files.lock().unwrap();
+        files.keys().any(|file| file.starts_with(path))
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        self.files.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    fn link(&self, source: &Path, target: &Path) -> Result<()> {
+        let content = self.read(source)?;
+        self.write(target, &content)
+    }
+
+    fn create_dir(&self, _path: &Path, _mode: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn make_readonly(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A directory in the temp dir that is removed when dropped. Each one gets a
+/// name of its own, so tests running in parallel, or in several checkouts at
+/// once, never share files.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "doot-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("temp dir can be created");
+        Self(path)
+    }
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Config whose `groups` each list `files` and resolve to `home` with the
+/// resolver `resolver`.
+pub fn config(resolver: &str, home: &Path, groups: &[(&str, &[&str])]) -> Config {
+    let mut yaml = String::from("version: v1\ngroups:\n");
+    for (name, files) in groups {
+        yaml.push_str(&format!(
+            "  {}:\n    {}: {:?}\n    files: {:?}\n",
+            name,
+            resolver,
+            home.display().to_string(),
+            files
+        ));
+    }
+    Config::parse(&yaml).expect("fixture config is valid")
+}
+
+/// A resolver that puts every group in a fake home, with the repo in a fake
+/// directory, so plans for it don't depend on the machine running them.
+pub struct FakeResolver {
+    pub name: String,
+    pub home: PathBuf,
+    pub repo: PathBuf,
+}
+
+impl FakeResolver {
+    pub fn new(name: &str, home: impl Into<PathBuf>, repo: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.to_string(),
+            home: home.into(),
+            repo: repo.into(),
+        }
+    }
+
+    /// Config whose `groups` each list `files` and resolve to the fake home.
+    pub fn config(&self, groups: &[(&str, &[&str])]) -> Config {
+        config(&self.name, &self.home, groups)
+    }
+
+    /// Plan exporting `groups` from the fake repo to the fake home.
+    pub fn plan_export(&self, config: &Config, store: &dyn Store, groups: &[&str]) -> Result<Plan> {
+        let builder = PlanBuilder::new(store);
+        let mut plan = Plan::new();
+        for name in groups {
+            let home = config.resolve_group_path(name, &self.name)?;
+            let entries = builder.build_group_export(
+                config.get_group(name)?,
+                &self.name,
+                &[Layer::own(self.repo.join(name))],
+                &home,
+            )?;
+            plan.add_group(name.to_string(), entries);
+        }
+        Ok(plan)
+    }
+
+    /// Plan importing `groups` from the fake home into the fake repo.
+    pub fn plan_import(&self, config: &Config, store: &dyn Store, groups: &[&str]) -> Result<Plan> {
+        let builder = PlanBuilder::new(store);
+        let mut plan = Plan::new();
+        for name in groups {
+            let home = config.resolve_group_path(name, &self.name)?;
+            let dir = self.repo.join(name);
+            let entries = builder.build_group_import(
+                config.get_group(name)?,
+                &[Layer::own(dir.clone())],
+                &home,
+                &dir.join(".dootignore"),
+            )?;
+            plan.add_group(name.to_string(), entries);
+        }
+        Ok(plan)
+    }
+}
+
+/// Plan entry copying `source` to `destination`.
+pub fn entry(
+    relative_path: &str,
+    source: impl Into<PathBuf>,
+    destination: impl Into<PathBuf>,
+    status: FileStatus,
+) -> FileEntry {
+    FileEntry::new(
+        PathBuf::from(relative_path),
+        source.into(),
+        destination.into(),
+        status,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Mode;
+    use crate::executor::Executor;
+
+    #[test]
+    fn export_round_trip_in_memory() {
+        let resolver = FakeResolver::new("nux", "/home/me", "/repo");
+        let config = resolver.config(&[("bash", &[".bashrc"])]);
+        let store = MemoryStore::new()
+            .with_file("/repo/bash/.bashrc", b"set -o vi\n")
+            .with_file("/home/me/.bashrc", b"set -o emacs\n");
+
+        let plan = resolver.plan_export(&config, &store, &["bash"]).unwrap();
+        assert_eq!(plan.groups[0].entries.len(), 1);
+        assert_eq!(plan.groups[0].entries[0].status, FileStatus::Overwrite);
+
+        Executor::new(&store, Mode::File)
+            .quiet()
+            .execute(&plan)
+            .unwrap();
+        assert_eq!(store.file("/home/me/.bashrc").unwrap(), b"set -o vi\n");
+    }
+}
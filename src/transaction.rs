@@ -0,0 +1,111 @@
+use crate::store::{self, Store};
+use anyhow::Result;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+/// What a destination was before a run changed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Previous {
+    Missing,
+    Link(PathBuf),
+    Content(Vec<u8>),
+}
+
+/// Remembers what each change of a run replaced, so the changes made so far
+/// can be reverted when a later one fails. Directories the run created are
+/// left in place.
+#[derive(Default)]
+pub struct Transaction {
+    changes: RefCell<Vec<(PathBuf, Previous)>>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Saves what `path` is now, before it is changed.
+    pub fn record(&self, store: &dyn Store, path: &Path) -> Result<()> {
+        let previous = if !store::is_pseudo_path(path) && path.is_symlink() {
+            Previous::Link(std::fs::read_link(path)?)
+        } else if store.exists(path) {
+            Previous::Content(store.read(path)?)
+        } else {
+            Previous::Missing
+        };
+        self.changes
+            .borrow_mut()
+            .push((path.to_path_buf(), previous));
+        Ok(())
+    }
+
+    /// How many changes were recorded so far, to roll back to later with
+    /// [`Transaction::roll_back_from`].
+    pub fn mark(&self) -> usize {
+        self.changes.borrow().len()
+    }
+
+    /// Puts every recorded path back the way it was, newest first, and
+    /// returns how many there were. Goes on past failures and returns the
+    /// first.
+    pub fn roll_back(&self, store: &dyn Store) -> Result<usize> {
+        self.roll_back_from(store, 0)
+    }
+
+    /// Like [`Transaction::roll_back`], for the changes recorded after the
+    /// first `start`. Earlier changes are kept and can still be rolled back.
+    pub fn roll_back_from(&self, store: &dyn Store, start: usize) -> Result<usize> {
+        let changes = self.changes.borrow_mut().split_off(start);
+        let mut failure = None;
+        for (path, previous) in changes.iter().rev() {
+            if let Err(error) = restore(store, path, previous) {
+                failure.get_or_insert(error);
+            }
+        }
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(changes.len()),
+        }
+    }
+}
+
+fn restore(store: &dyn Store, path: &Path, previous: &Previous) -> Result<()> {
+    if let Previous::Content(content) = previous {
+        if store::is_pseudo_path(path) {
+            return store.write(path, content);
+        }
+    }
+    // Remove first so symlinks are replaced rather than written through.
+    store.remove(path)?;
+    match previous {
+        Previous::Missing => Ok(()),
+        Previous::Link(target) => store.link(target, path),
+        Previous::Content(content) => store.write(path, content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+
+    #[test]
+    fn restores_in_reverse_order() {
+        let store = MemoryStore::new().with_file("/home/me/.bashrc", b"old");
+        let transaction = Transaction::new();
+        let bashrc = Path::new("/home/me/.bashrc");
+        let inputrc = Path::new("/home/me/.inputrc");
+
+        transaction.record(&store, bashrc).unwrap();
+        store.write(bashrc, b"new").unwrap();
+        transaction.record(&store, inputrc).unwrap();
+        store.write(inputrc, b"new").unwrap();
+        transaction.record(&store, bashrc).unwrap();
+        store.write(bashrc, b"newer").unwrap();
+
+        assert_eq!(transaction.roll_back(&store).unwrap(), 3);
+        assert_eq!(store.read(bashrc).unwrap(), b"old");
+        assert!(!store.exists(inputrc));
+        assert_eq!(transaction.roll_back(&store).unwrap(), 0);
+    }
+}
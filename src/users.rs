@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Account details needed to hand files over to a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Looks up `name` in `/etc/passwd`.
+pub fn lookup(name: &str) -> Result<User> {
+    let passwd = std::fs::read_to_string("/etc/passwd").context("Failed to read /etc/passwd")?;
+    parse_passwd(&passwd, name).with_context(|| format!("User '{}' not found", name))
+}
+
+fn parse_passwd(passwd: &str, name: &str) -> Option<User> {
+    passwd.lines().find_map(|line| {
+        let fields: Vec<_> = line.split(':').collect();
+        if fields.len() < 4 || fields[0] != name {
+            return None;
+        }
+        Some(User {
+            name: name.to_string(),
+            uid: fields[2].parse().ok()?,
+            gid: fields[3].parse().ok()?,
+        })
+    })
+}
+
+/// Looks up an owner given as `user` or `user:group`; the group, if given,
+/// replaces the user's primary group.
+pub fn lookup_owner(spec: &str) -> Result<User> {
+    let (name, group) = match spec.split_once(':') {
+        Some((name, group)) => (name, Some(group)),
+        None => (spec, None),
+    };
+    let mut user = lookup(name)?;
+    if let Some(group) = group {
+        let groups = std::fs::read_to_string("/etc/group").context("Failed to read /etc/group")?;
+        user.gid =
+            parse_group(&groups, group).with_context(|| format!("Group '{}' not found", group))?;
+    }
+    Ok(user)
+}
+
+fn parse_group(groups: &str, name: &str) -> Option<u32> {
+    groups.lines().find_map(|line| {
+        let fields: Vec<_> = line.split(':').collect();
+        if fields.len() < 3 || fields[0] != name {
+            return None;
+        }
+        fields[2].parse().ok()
+    })
+}
+
+/// Makes `user` the owner of `path` (not following symlinks).
+#[cfg(unix)]
+pub fn chown(path: &Path, user: &User) -> Result<()> {
+    std::os::unix::fs::lchown(path, Some(user.uid), Some(user.gid)).with_context(|| {
+        format!(
+            "Failed to change owner of {} to {}",
+            path.display(),
+            user.name
+        )
+    })
+}
+
+#[cfg(not(unix))]
+pub fn chown(_path: &Path, _user: &User) -> Result<()> {
+    anyhow::bail!("Changing file ownership is only supported on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_passwd_entry() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\nalice:x:1000:100::/home/alice:/bin/zsh\n";
+        assert_eq!(
+            parse_passwd(passwd, "alice"),
+            Some(User {
+                name: "alice".to_string(),
+                uid: 1000,
+                gid: 100,
+            })
+        );
+        assert_eq!(parse_passwd(passwd, "bob"), None);
+    }
+
+    #[test]
+    fn parse_group_entry() {
+        let groups = "root:x:0:\nwheel:x:10:alice\n";
+        assert_eq!(parse_group(groups, "wheel"), Some(10));
+        assert_eq!(parse_group(groups, "staff"), None);
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+
+/// A release version like `1.2.3` or `1.2.3-rc.1`. Pre-releases sort before
+/// the release they lead up to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    major: u64,
+    minor: u64,
+    patch: u64,
+    release: bool,
+}
+
+impl Version {
+    /// Version of the running binary.
+    pub fn current() -> Self {
+        Self::parse(env!("CARGO_PKG_VERSION")).expect("package version is valid")
+    }
+
+    /// Parses a full version, with or without a leading `v`.
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let (core, release) = match version.split_once('-') {
+            Some((core, _)) => (core, false),
+            None => (version, true),
+        };
+        let mut parts = core.split('.').map(|part| part.parse().ok());
+        let version = Self {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next()??,
+            release,
+        };
+        parts.next().is_none().then_some(version)
+    }
+
+    /// Parses a version in a requirement, where missing parts are 0 (`0.4`).
+    fn parse_partial(version: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = version.trim().split('.').collect();
+        if parts.len() > 3 {
+            return None;
+        }
+        parts.resize(3, "0");
+        Self::parse(&parts.join("."))
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Returns whether `version` satisfies `requirement`: comparisons such as
+/// `>=0.4` or `>=1.1, <2`, all of which must hold.
+pub fn satisfies(version: Version, requirement: &str) -> Result<bool> {
+    for comparison in requirement.split(',') {
+        let comparison = comparison.trim();
+        let (op, rest) = ["<=", ">=", "<", ">", "="]
+            .iter()
+            .find_map(|op| comparison.strip_prefix(op).map(|rest| (*op, rest)))
+            .unwrap_or(("=", comparison));
+        let bound = Version::parse_partial(rest)
+            .with_context(|| format!("Invalid version requirement: {}", requirement))?;
+
+        let ok = match op {
+            "<=" => version <= bound,
+            ">=" => version >= bound,
+            "<" => version < bound,
+            ">" => version > bound,
+            _ => version == bound,
+        };
+        if !ok {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_versions() {
+        assert!(Version::parse("v1.2.0") > Version::parse("1.1.9"));
+        assert!(Version::parse("v1.2.0") > Version::parse("v1.2.0-rc.1"));
+        assert_eq!(Version::parse("v1.2"), None);
+    }
+
+    #[test]
+    fn checks_requirements() {
+        let version = Version::parse("1.1.1").unwrap();
+        assert!(satisfies(version, ">=0.4").unwrap());
+        assert!(satisfies(version, ">=1.1, <2").unwrap());
+        assert!(!satisfies(version, ">=1.2").unwrap());
+        assert!(satisfies(version, "1.1.1").unwrap());
+        assert!(satisfies(version, ">=x").is_err());
+    }
+}
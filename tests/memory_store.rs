@@ -0,0 +1,51 @@
+//! Import and export flows run end to end against `doot::testing`'s
+//! in-memory store.
+
+use doot::config::Mode;
+use doot::executor::Executor;
+use doot::plan::FileStatus;
+use doot::store::Store;
+use doot::testing::{FakeResolver, MemoryStore};
+
+#[test]
+fn export_then_import_round_trip() {
+    let resolver = FakeResolver::new("nux", "/home/me", "/repo");
+    let config = resolver.config(&[("git", &[".gitconfig"]), ("vim", &[".vimrc"])]);
+    let store = MemoryStore::new()
+        .with_file("/repo/git/.gitconfig", b"[user]\n\tname = me\n")
+        .with_file("/repo/vim/.vimrc", b"set number\n")
+        .with_file("/home/me/.vimrc", b"set number\n");
+
+    let plan = resolver
+        .plan_export(&config, &store, &["git", "vim"])
+        .unwrap();
+    let statuses: Vec<_> = plan
+        .groups
+        .iter()
+        .map(|group| group.entries[0].status.clone())
+        .collect();
+    assert_eq!(statuses, [FileStatus::Create, FileStatus::Same]);
+    Executor::new(&store, Mode::File)
+        .quiet()
+        .execute(&plan)
+        .unwrap();
+    assert_eq!(
+        store.file("/home/me/.gitconfig").unwrap(),
+        b"[user]\n\tname = me\n"
+    );
+
+    // An edit on the machine comes back into the repo on import.
+    store
+        .write("/home/me/.gitconfig".as_ref(), b"[user]\n\tname = you\n")
+        .unwrap();
+    let plan = resolver.plan_import(&config, &store, &["git"]).unwrap();
+    assert_eq!(plan.groups[0].entries[0].status, FileStatus::Overwrite);
+    Executor::new(&store, Mode::File)
+        .quiet()
+        .execute(&plan)
+        .unwrap();
+    assert_eq!(
+        store.file("/repo/git/.gitconfig").unwrap(),
+        b"[user]\n\tname = you\n"
+    );
+}